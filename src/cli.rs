@@ -0,0 +1,425 @@
+use std::fs::File;
+use std::future::ready;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use flowync::error::IOError;
+use tokio::runtime;
+
+use crate::config::{get_config, Config};
+use crate::credentials;
+use crate::export::{self, ExportFormat};
+use crate::socrata::auth::Auth;
+use crate::socrata::client::{CancelToken, ClientError, Progress, SocrataClient};
+use crate::socrata::make_query;
+use crate::socrata::paging::{PagedQuery, Paging};
+use crate::socrata::soql;
+use crate::socrata::types::{column_types, ColumnType};
+
+/// The request failed or the results could not be written
+const EXIT_FAILED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+/// Unknown profile, locked credentials or incomplete connection settings
+const EXIT_CONFIG: i32 = 3;
+/// The query doesn't parse
+const EXIT_INVALID_QUERY: i32 = 4;
+
+/// Encrypted credentials are unlocked with this instead of the passphrase field
+const PASSPHRASE_VAR: &str = "SOQLSTUDIO_PASSPHRASE";
+
+const USAGE: &str = "\
+Usage: soqlstudio run [OPTIONS] [FILE]
+
+Runs the SoQL query in FILE, or read from stdin when FILE is `-` or missing,
+using a connection profile of the app.
+
+Options:
+  -p, --profile NAME     Connection profile (default: the last one used)
+  -d, --domain DOMAIN    Overrides the profile's domain
+  -i, --dataset ID       Overrides the profile's dataset
+  -q, --query SOQL       Runs SOQL instead of reading a file
+  -f, --format FORMAT    csv, excel-csv, json, ndjson, arrow or table (default: csv)
+  -o, --output PATH      Writes to PATH instead of stdout
+      --all-pages        Pages through every row, using the app's paging settings
+      --no-check         Sends the query even if it doesn't parse locally
+      --credentials PATH Encrypted credentials file (default: credentials.enc)
+  -h, --help             Shows this message
+
+Encrypted credentials are unlocked with the SOQLSTUDIO_PASSPHRASE variable.
+Pass --credentials when running from another directory, e.g. from cron.
+
+Exit codes: 0 success, 1 request or output failed, 2 bad arguments,
+3 profile or connection error, 4 invalid query.
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Export(ExportFormat),
+    /// Aligned columns for reading in a terminal
+    Table,
+}
+
+impl OutputFormat {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "csv" => OutputFormat::Export(ExportFormat::Csv),
+            "excel-csv" => OutputFormat::Export(ExportFormat::ExcelCsv),
+            "json" => OutputFormat::Export(ExportFormat::Json),
+            "ndjson" => OutputFormat::Export(ExportFormat::Ndjson),
+            "arrow" => OutputFormat::Export(ExportFormat::ArrowIpc),
+            "table" => OutputFormat::Table,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum QuerySource {
+    /// `-` or no file at all
+    Stdin,
+    File(PathBuf),
+    Inline(String),
+}
+
+#[derive(Debug, PartialEq)]
+struct RunOptions {
+    profile: Option<String>,
+    domain: Option<String>,
+    dataset: Option<String>,
+    query: QuerySource,
+    format: OutputFormat,
+    output: Option<PathBuf>,
+    all_pages: bool,
+    check: bool,
+    credentials: PathBuf,
+}
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Run(RunOptions),
+    Help,
+}
+
+/// Whether the arguments ask for the command line instead of the app
+pub fn is_command(args: &[String]) -> bool {
+    matches!(args.first().map(String::as_str), Some("run" | "help" | "-h" | "--help"))
+}
+
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    let mut args = args.iter();
+    match args.next().map(String::as_str) {
+        Some("run") => {}
+        Some("help" | "-h" | "--help") => return Ok(Command::Help),
+        Some(other) => return Err(format!("unknown command `{}`", other)),
+        None => return Err("missing command".into()),
+    }
+    let mut options = RunOptions {
+        profile: None,
+        domain: None,
+        dataset: None,
+        query: QuerySource::Stdin,
+        format: OutputFormat::Export(ExportFormat::Csv),
+        output: None,
+        all_pages: false,
+        check: true,
+        credentials: PathBuf::from(credentials::ENCRYPTED_FILE_PATH),
+    };
+    let mut file = None;
+    let mut inline = None;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().ok_or(format!("`{}` needs a value", arg));
+        match arg.as_str() {
+            "-p" | "--profile" => options.profile = Some(value()?),
+            "-d" | "--domain" => options.domain = Some(value()?),
+            "-i" | "--dataset" => options.dataset = Some(value()?),
+            "-q" | "--query" => inline = Some(value()?),
+            "-f" | "--format" => {
+                let name = value()?;
+                options.format = OutputFormat::parse(&name).ok_or(format!("unknown format `{}`", name))?;
+            }
+            "-o" | "--output" => options.output = Some(PathBuf::from(value()?)),
+            "--all-pages" => options.all_pages = true,
+            "--no-check" => options.check = false,
+            "--credentials" => options.credentials = PathBuf::from(value()?),
+            "-h" | "--help" => return Ok(Command::Help),
+            "-" => file = Some(arg.to_owned()),
+            flag if flag.starts_with('-') => return Err(format!("unknown option `{}`", flag)),
+            _ if file.is_some() => return Err(format!("unexpected argument `{}`", arg)),
+            _ => file = Some(arg.to_owned()),
+        }
+    }
+    options.query = match (inline, file) {
+        (Some(_), Some(_)) => return Err("give either a query file or --query, not both".into()),
+        (Some(query), None) => QuerySource::Inline(query),
+        (None, Some(file)) if file != "-" => QuerySource::File(PathBuf::from(file)),
+        (None, _) => QuerySource::Stdin,
+    };
+    Ok(Command::Run(options))
+}
+
+/// Runs the command in `args` and returns the process exit code
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            return 0;
+        }
+        Err(e) => {
+            eprintln!("soqlstudio: {}\n\n{}", e, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+    match run_query(options) {
+        Ok(()) => 0,
+        Err((code, e)) => {
+            eprintln!("soqlstudio: {}", e);
+            code
+        }
+    }
+}
+
+fn read_query(source: &QuerySource) -> io::Result<String> {
+    match source {
+        QuerySource::Stdin => {
+            let mut query = String::new();
+            io::stdin().read_to_string(&mut query)?;
+            Ok(query)
+        }
+        QuerySource::File(path) => std::fs::read_to_string(path),
+        QuerySource::Inline(query) => Ok(query.to_owned()),
+    }
+}
+
+/// The auth of `profile`, with its secrets read from the credential store
+fn profile_auth(config: &Config, profile: usize, file: &Path) -> Result<Auth, IOError> {
+    let profile = &config.profiles[profile];
+    // Logins written by older versions are still in the config itself
+    let legacy = config.legacy_login().filter(|login| login.profile == profile.id);
+    let auth = Auth {
+        mode: profile.auth_mode,
+        username: legacy.as_ref().map(|login| login.username.to_owned()).unwrap_or_default(),
+        password: legacy.map(|login| login.password).unwrap_or_default(),
+        ..Default::default()
+    };
+    let reference = match &profile.credentials {
+        Some(reference) => reference,
+        None => return Ok(auth),
+    };
+    let passphrase = std::env::var(PASSPHRASE_VAR).ok().filter(|p| !p.is_empty());
+    if reference.needs_passphrase() && passphrase.is_none() {
+        return Err(format!("profile `{}` needs {} to unlock its credentials", profile.name, PASSPHRASE_VAR).into());
+    }
+    let stored: Auth = serde_json::from_str(&credentials::load(reference, passphrase.as_deref(), file)?)?;
+    // The mode saved in the config wins over the one in the secret
+    Ok(Auth { mode: auth.mode, ..stored })
+}
+
+fn run_query(options: RunOptions) -> Result<(), (i32, String)> {
+    let config = get_config();
+    let profile = match &options.profile {
+        Some(name) => config
+            .profiles
+            .iter()
+            .position(|p| &p.name == name)
+            .ok_or((EXIT_CONFIG, format!("no profile named `{}`", name)))?,
+        None => config.profiles.iter().position(|p| p.name == config.last_profile).unwrap_or(0),
+    };
+    let auth = profile_auth(&config, profile, &options.credentials).map_err(|e| (EXIT_CONFIG, format!("unable to read credentials: {}", e)))?;
+    let domain = options.domain.to_owned().unwrap_or_else(|| config.profiles[profile].domain.to_owned());
+    let dataset = options.dataset.to_owned().unwrap_or_else(|| config.profiles[profile].dataset.to_owned());
+
+    let query = read_query(&options.query).map_err(|e| (EXIT_USAGE, format!("unable to read the query: {}", e)))?;
+    if options.check {
+        if let Err(e) = soql::parse(&query) {
+            let (line, column) = e.line_col(&query);
+            return Err((EXIT_INVALID_QUERY, format!("line {}, column {}: {}", line, column, e.message)));
+        }
+    }
+    make_query(&domain, &dataset, &query).map_err(|e| (EXIT_CONFIG, e.to_string()))?;
+    let client = SocrataClient::new(&domain, auth).map_err(|e| (EXIT_CONFIG, e.to_string()))?;
+
+    let paging = Some(&config.paging).filter(|_| options.all_pages);
+    let (data, types) = fetch(&client, &dataset, &query, paging).map_err(|e| (EXIT_FAILED, e.to_string()))?;
+    let written = match &options.output {
+        Some(path) => File::create(path).map_err(IOError::from).and_then(|file| write(file, options.format, &data, &types)),
+        None => write(io::stdout().lock(), options.format, &data, &types),
+    };
+    written.map_err(|e| (EXIT_FAILED, format!("unable to write the results: {}", e)))
+}
+
+/// Runs the query the same way the app does, waiting for every row.
+/// Returns the rows along with the type of each column.
+fn fetch(client: &SocrataClient, dataset: &str, query: &str, paging: Option<&Paging>) -> Result<(Vec<Vec<String>>, Vec<ColumnType>), ClientError> {
+    let rt = runtime::Builder::new_multi_thread().enable_all().build()?;
+    let mut rows = vec![];
+    let mut columns = vec![];
+    let progress = |progress| {
+        match progress {
+            Progress::Rows(batch) => rows.extend(batch),
+            Progress::Columns(c) => columns = c,
+            _ => {}
+        }
+        ready(())
+    };
+    let cancel = CancelToken::default();
+    rt.block_on(async {
+        match paging {
+            Some(paging) => client.query_paged(dataset, &PagedQuery::new(query), paging, &cancel, progress).await,
+            None => client.query(dataset, query, &cancel, progress).await,
+        }
+    })?;
+    let header = match rows.first() {
+        Some(header) => header,
+        None => return Ok((rows, vec![])),
+    };
+    // Without the type headers the dataset's schema is the next best thing
+    let schema = if columns.is_empty() { rt.block_on(client.metadata(dataset)).ok() } else { None };
+    let types = column_types(header, &columns, schema.as_ref());
+    Ok((rows, types))
+}
+
+fn write<W: Write>(out: W, format: OutputFormat, data: &[Vec<String>], types: &[ColumnType]) -> Result<(), IOError> {
+    match format {
+        OutputFormat::Export(format) => export::write_to(out, format, data, types, |_| {}).map(|_| ()),
+        OutputFormat::Table => write_table(out, data),
+    }
+}
+
+/// Writes the rows as left-aligned columns, with a rule under the header row
+fn write_table<W: Write>(out: W, data: &[Vec<String>]) -> Result<(), IOError> {
+    let mut out = io::BufWriter::new(out);
+    let columns = data.iter().map(Vec::len).max().unwrap_or(0);
+    let mut widths = vec![0; columns];
+    for row in data {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for (i, row) in data.iter().enumerate() {
+        let cells: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(j, width)| format!("{:width$}", row.get(j).map_or("", String::as_str), width = width))
+            .collect();
+        writeln!(out, "{}", cells.join(" | ").trim_end())?;
+        if i == 0 {
+            let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+            writeln!(out, "{}", rule.join("-+-"))?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Profile;
+    use crate::socrata::auth::AuthMode;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn run_options(list: &[&str]) -> RunOptions {
+        match parse_args(&args(list)) {
+            Ok(Command::Run(options)) => options,
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn only_known_commands_skip_the_app() {
+        assert!(is_command(&args(&["run", "query.soql"])));
+        assert!(is_command(&args(&["--help"])));
+        assert!(!is_command(&args(&[])));
+        // e.g. `-psn_0_12345` added by macOS
+        assert!(!is_command(&args(&["-psn_0_12345"])));
+    }
+
+    #[test]
+    fn reads_stdin_by_default() {
+        let options = run_options(&["run"]);
+        assert_eq!(options.query, QuerySource::Stdin);
+        assert_eq!(options.format, OutputFormat::Export(ExportFormat::Csv));
+        assert!(options.check);
+        assert_eq!(run_options(&["run", "-"]).query, QuerySource::Stdin);
+    }
+
+    #[test]
+    fn parses_every_option() {
+        let options = run_options(&[
+            "run", "-p", "Work", "--domain", "data.example.com", "-i", "abcd-1234",
+            "-f", "table", "-o", "out.txt", "--all-pages", "--no-check", "--credentials", "/etc/soql.enc",
+            "query.soql",
+        ]);
+        assert_eq!(
+            options,
+            RunOptions {
+                profile: Some("Work".into()),
+                domain: Some("data.example.com".into()),
+                dataset: Some("abcd-1234".into()),
+                query: QuerySource::File("query.soql".into()),
+                format: OutputFormat::Table,
+                output: Some("out.txt".into()),
+                all_pages: true,
+                check: false,
+                credentials: "/etc/soql.enc".into(),
+            }
+        );
+        assert_eq!(run_options(&["run", "-q", "select 1"]).query, QuerySource::Inline("select 1".into()));
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        for (list, message) in [
+            (&["run", "-f", "xml"][..], "unknown format `xml`"),
+            (&["run", "--profile"][..], "`--profile` needs a value"),
+            (&["run", "--verbose"][..], "unknown option `--verbose`"),
+            (&["run", "a.soql", "b.soql"][..], "unexpected argument `b.soql`"),
+            (&["run", "-q", "select 1", "a.soql"][..], "give either a query file or --query, not both"),
+            (&["query"][..], "unknown command `query`"),
+        ] {
+            assert_eq!(parse_args(&args(list)), Err(message.to_owned()));
+        }
+    }
+
+    #[test]
+    fn tables_line_up() {
+        let data = vec![
+            vec!["name".to_owned(), "count".to_owned()],
+            vec!["Brooklyn".to_owned(), "12".to_owned()],
+            vec!["Bronx".to_owned(), "7".to_owned()],
+        ];
+        let mut out = vec![];
+        write_table(&mut out, &data).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "name     | count\n\
+             ---------+------\n\
+             Brooklyn | 12\n\
+             Bronx    | 7\n"
+        );
+    }
+
+    #[test]
+    fn only_the_migrated_profile_gets_the_old_login() {
+        let mut migrated = Profile::new("Default");
+        migrated.auth_mode = AuthMode::Basic;
+        let mut other = Profile::new("Other");
+        other.auth_mode = AuthMode::Basic;
+        let config = Config {
+            username: "someone".to_owned(),
+            password: "secret".to_owned(),
+            legacy_profile: migrated.id.to_owned(),
+            profiles: vec![migrated, other],
+            ..Default::default()
+        };
+        let file = Path::new("unused.enc");
+        let auth = profile_auth(&config, 0, file).unwrap();
+        assert_eq!((auth.username.as_str(), auth.password.as_str()), ("someone", "secret"));
+        let auth = profile_auth(&config, 1, file).unwrap();
+        assert!(auth.username.is_empty() && auth.password.is_empty());
+    }
+}
@@ -1,27 +1,121 @@
 use serde_derive::{Deserialize, Serialize};
+use crate::socrata::paging::Paging;
+use crate::credentials::CredentialRef;
+use crate::socrata::auth::AuthMode;
 
 const CONFIG_JSON_FILE_PATH: &str = "config.json";
 
-#[derive(Deserialize, Serialize, Debug)]
+/// A named Socrata connection. Secrets live in the credential store.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Profile {
+    /// Names its secrets in the credential store, so it never changes.
+    /// Profiles saved before there were ids get one when loaded.
+    #[serde(default = "Profile::new_id")]
+    pub id: String,
+    pub name: String,
+    pub domain: String,
+    pub dataset: String,
+    #[serde(default)]
+    pub auth_mode: AuthMode,
+    #[serde(default)]
+    pub credentials: Option<CredentialRef>,
+}
+
+impl Profile {
+    pub fn new(name: &str) -> Self {
+        Self {
+            id: Self::new_id(),
+            name: name.to_owned(),
+            domain: "".to_owned(),
+            dataset: "".to_owned(),
+            auth_mode: AuthMode::Anonymous,
+            credentials: None,
+        }
+    }
+
+    fn new_id() -> String {
+        format!("{:016x}", rand::random::<u64>())
+    }
+
+    /// The id of the profile's secrets in the credential store
+    pub fn credential_id(&self) -> String {
+        format!("profile:{}", self.id)
+    }
+}
+
+/// A plaintext login written by older versions, and the profile it belongs to
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegacyLogin {
+    pub profile: String,
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
 pub struct Config {
+    /// Plaintext login written by older versions, kept until it has been
+    /// moved to the credential store
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub username: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub password: String,
+    /// Id of the profile that `username` and `password` belong to, the one
+    /// made from the single connection of older versions
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub legacy_profile: String,
+    /// Only read to migrate the single connection of older versions into a profile
+    #[serde(default, skip_serializing)]
     pub domain: String,
+    #[serde(default, skip_serializing)]
     pub dataset: String,
-    pub query: String
+    #[serde(default, skip_serializing)]
+    pub credentials: Option<CredentialRef>,
+    #[serde(default, skip_serializing)]
+    pub auth_mode: Option<AuthMode>,
+    pub query: String,
+    #[serde(default)]
+    pub paging: Paging,
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    /// Name of the profile that was selected when the app was last used
+    #[serde(default)]
+    pub last_profile: String,
+}
+
+impl Config {
+    pub fn legacy_login(&self) -> Option<LegacyLogin> {
+        if self.username.is_empty() && self.password.is_empty() {
+            return None;
+        }
+        Some(LegacyLogin {
+            profile: self.legacy_profile.to_owned(),
+            username: self.username.to_owned(),
+            password: self.password.to_owned(),
+        })
+    }
 }
 
 pub fn get_config() -> Config {
     let raw_config = std::fs::read_to_string(CONFIG_JSON_FILE_PATH).unwrap_or(String::from(r#"
     {"username":"", "password":"", "domain":"", "dataset":"", "query":"","theme":"light"}
     "#));
-    serde_json::from_str::<Config>(&raw_config).unwrap_or(Config {
-        username: "".to_owned(),
-        password: "".to_owned(),
-        domain: "".to_owned(),
-        dataset: "".to_owned(),
-        query: "".to_owned(),
-    })
+    let mut config = serde_json::from_str::<Config>(&raw_config).unwrap_or_default();
+    if config.profiles.is_empty() {
+        // Connections saved before other auth modes existed all used basic auth
+        let has_login = config.credentials.is_some() || !config.password.is_empty();
+        let default_mode = if has_login { AuthMode::Basic } else { AuthMode::Anonymous };
+        let profile = Profile {
+            id: Profile::new_id(),
+            name: "Default".to_owned(),
+            domain: config.domain.to_owned(),
+            dataset: config.dataset.to_owned(),
+            auth_mode: config.auth_mode.unwrap_or(default_mode),
+            credentials: config.credentials.to_owned(),
+        };
+        config.legacy_profile = profile.id.to_owned();
+        config.profiles.push(profile);
+    }
+    config
 }
 
 
@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use flowync::error::IOError;
+use rand::RngCore;
+use serde_derive::{Deserialize, Serialize};
+
+const KEYRING_SERVICE: &str = "soqlstudio";
+/// Where the app keeps the encrypted file, next to `config.json`
+pub const ENCRYPTED_FILE_PATH: &str = "credentials.enc";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Where a secret is kept
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// The OS keyring, i.e. the Secret Service on Linux
+    Keyring,
+    /// A file such as `credentials.enc`, encrypted with a key derived from a master passphrase
+    EncryptedFile,
+}
+
+/// What `config.json` keeps instead of the secret itself
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CredentialRef {
+    pub backend: Backend,
+    pub id: String,
+}
+
+impl CredentialRef {
+    pub fn needs_passphrase(&self) -> bool {
+        self.backend == Backend::EncryptedFile
+    }
+}
+
+#[derive(Deserialize, Serialize, Default)]
+struct EncryptedFile {
+    salt: String,
+    /// Base64 of the nonce followed by the ciphertext, by credential id
+    entries: BTreeMap<String, String>,
+}
+
+impl EncryptedFile {
+    /// A missing file has no entries yet. A file that can't be read is an
+    /// error rather than empty, so that storing doesn't overwrite it.
+    fn read(path: &Path) -> Result<Self, IOError> {
+        let raw = match std::fs::read_to_string(path) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_str(&raw).map_err(|e| format!("{} is corrupt: {}", path.display(), e).into())
+    }
+
+    fn write(&self, path: &Path) -> Result<(), IOError> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn cipher(&mut self, passphrase: &str) -> Result<Aes256Gcm, IOError> {
+        if self.salt.is_empty() {
+            let mut salt = [0u8; SALT_LEN];
+            rand::thread_rng().fill_bytes(&mut salt);
+            self.salt = BASE64.encode(salt);
+        }
+        let salt = BASE64.decode(&self.salt)?;
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| e.to_string())?;
+        Ok(Aes256Gcm::new(&key.into()))
+    }
+
+    fn store(&mut self, id: &str, secret: &str, passphrase: &str, path: &Path) -> Result<(), IOError> {
+        let cipher = self.cipher(passphrase)?;
+        // Make sure the passphrase matches the one protecting the other entries
+        if let Some(other) = self.entries.keys().find(|k| *k != id).cloned() {
+            self.decrypt(&cipher, &other)?;
+        }
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let mut sealed = nonce.to_vec();
+        sealed.extend(
+            cipher
+                .encrypt(Nonce::from_slice(&nonce), secret.as_bytes())
+                .map_err(|_| "unable to encrypt credentials")?,
+        );
+        self.entries.insert(id.to_owned(), BASE64.encode(sealed));
+        self.write(path)
+    }
+
+    fn decrypt(&self, cipher: &Aes256Gcm, id: &str) -> Result<String, IOError> {
+        let sealed = BASE64.decode(self.entries.get(id).ok_or("no stored credentials")?)?;
+        if sealed.len() < NONCE_LEN {
+            return Err("stored credentials are corrupt".into());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let secret = cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "wrong master passphrase")?;
+        Ok(String::from_utf8(secret)?)
+    }
+}
+
+/// Stores `secret` in the OS keyring, falling back to the encrypted `file`
+/// when no keyring is available and a master passphrase was given.
+pub fn store(id: &str, secret: &str, passphrase: Option<&str>, file: &Path) -> Result<CredentialRef, IOError> {
+    let keyring = keyring::Entry::new(KEYRING_SERVICE, id).and_then(|entry| entry.set_password(secret));
+    match (keyring, passphrase) {
+        (Ok(()), _) => Ok(CredentialRef {
+            backend: Backend::Keyring,
+            id: id.to_owned(),
+        }),
+        (Err(_), Some(passphrase)) if !passphrase.is_empty() => {
+            EncryptedFile::read(file)?.store(id, secret, passphrase, file)?;
+            Ok(CredentialRef {
+                backend: Backend::EncryptedFile,
+                id: id.to_owned(),
+            })
+        }
+        (Err(e), _) => Err(format!("No keyring available ({}); set a master passphrase to use an encrypted file instead", e).into()),
+    }
+}
+
+/// Reads a secret back; `file` is only used by [`Backend::EncryptedFile`]
+pub fn load(reference: &CredentialRef, passphrase: Option<&str>, file: &Path) -> Result<String, IOError> {
+    match reference.backend {
+        Backend::Keyring => Ok(keyring::Entry::new(KEYRING_SERVICE, &reference.id)?.get_password()?),
+        Backend::EncryptedFile => {
+            let passphrase = passphrase.ok_or("master passphrase required")?;
+            let mut encrypted = EncryptedFile::read(file)?;
+            let cipher = encrypted.cipher(passphrase)?;
+            encrypted.decrypt(&cipher, &reference.id)
+        }
+    }
+}
+
+pub fn delete(reference: &CredentialRef, file: &Path) -> Result<(), IOError> {
+    match reference.backend {
+        Backend::Keyring => Ok(keyring::Entry::new(KEYRING_SERVICE, &reference.id)?.delete_password()?),
+        Backend::EncryptedFile => {
+            let mut encrypted = EncryptedFile::read(file)?;
+            encrypted.entries.remove(&reference.id);
+            encrypted.write(file)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A fresh path in the temp directory, without a file
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("soqlstudio-credentials-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn reference(id: &str) -> CredentialRef {
+        CredentialRef {
+            backend: Backend::EncryptedFile,
+            id: id.to_owned(),
+        }
+    }
+
+    /// Stores straight into the encrypted file, whether or not there is a keyring
+    fn store_encrypted(path: &Path, id: &str, secret: &str, passphrase: &str) -> Result<(), IOError> {
+        EncryptedFile::read(path)?.store(id, secret, passphrase, path)
+    }
+
+    #[test]
+    fn round_trips_through_the_encrypted_file() {
+        let path = temp_path("round-trip.enc");
+        store_encrypted(&path, "work", r#"{"token":"s3cret"}"#, "correct horse").unwrap();
+        store_encrypted(&path, "home", "other", "correct horse").unwrap();
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("s3cret"));
+        assert_eq!(load(&reference("work"), Some("correct horse"), &path).unwrap(), r#"{"token":"s3cret"}"#);
+        assert_eq!(load(&reference("home"), Some("correct horse"), &path).unwrap(), "other");
+
+        delete(&reference("home"), &path).unwrap();
+        assert_eq!(load(&reference("home"), Some("correct horse"), &path).unwrap_err().to_string(), "no stored credentials");
+        assert!(load(&reference("work"), Some("correct horse"), &path).is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_the_wrong_passphrase() {
+        let path = temp_path("wrong-passphrase.enc");
+        store_encrypted(&path, "work", "secret", "correct horse").unwrap();
+        let err = load(&reference("work"), Some("battery staple"), &path).unwrap_err();
+        assert_eq!(err.to_string(), "wrong master passphrase");
+        assert!(load(&reference("work"), None, &path).is_err());
+
+        // Entries can't be added under a different passphrase
+        let err = store_encrypted(&path, "home", "other", "battery staple").unwrap_err();
+        assert_eq!(err.to_string(), "wrong master passphrase");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_files_are_not_overwritten() {
+        let path = temp_path("corrupt.enc");
+        std::fs::write(&path, "{not json").unwrap();
+        assert!(load(&reference("work"), Some("correct horse"), &path)
+            .unwrap_err()
+            .to_string()
+            .contains("is corrupt"));
+        assert!(store_encrypted(&path, "work", "secret", "correct horse").is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{not json");
+
+        // A truncated entry is reported rather than decrypted
+        std::fs::write(&path, r#"{"salt": "AAAAAAAAAAAAAAAAAAAAAA==", "entries": {"work": "AAAA"}}"#).unwrap();
+        let err = load(&reference("work"), Some("correct horse"), &path).unwrap_err();
+        assert_eq!(err.to_string(), "stored credentials are corrupt");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,85 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, BooleanArray, Float64Array, RecordBatch, StringArray, TimestampMillisecondArray};
+use arrow_ipc::writer::FileWriter;
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use flowync::error::IOError;
+
+use super::PROGRESS_INTERVAL;
+use crate::socrata::types::{ColumnType, Value};
+
+fn data_type(column_type: &ColumnType) -> DataType {
+    match column_type {
+        t if t.is_numeric() => DataType::Float64,
+        ColumnType::Checkbox => DataType::Boolean,
+        ColumnType::FloatingTimestamp => DataType::Timestamp(TimeUnit::Millisecond, None),
+        ColumnType::FixedTimestamp => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+        _ => DataType::Utf8,
+    }
+}
+
+/// The Arrow type of column `i`. A column with cells that don't decode as
+/// its type is written as text, so that they are kept as the JSON exports keep them.
+fn column_data_type(rows: &[Vec<String>], i: usize, column_type: &ColumnType) -> DataType {
+    let data_type = data_type(column_type);
+    let undecodable = |row: &Vec<String>| matches!(row.get(i).map(|cell| column_type.decode(cell)), Some(Value::Text(_)));
+    if data_type != DataType::Utf8 && rows.iter().any(undecodable) {
+        DataType::Utf8
+    } else {
+        data_type
+    }
+}
+
+/// One column of a batch as `data_type`
+fn column(batch: &[Vec<String>], i: usize, column_type: &ColumnType, data_type: &DataType) -> ArrayRef {
+    let cells = batch.iter().map(|row| row.get(i).map_or(Value::Null, |cell| column_type.decode(cell)));
+    match data_type {
+        DataType::Float64 => {
+            let column: Float64Array = cells.map(|v| if let Value::Number(n) = v { Some(n) } else { None }).collect();
+            Arc::new(column)
+        }
+        DataType::Boolean => {
+            let column: BooleanArray = cells.map(|v| if let Value::Checkbox(b) = v { Some(b) } else { None }).collect();
+            Arc::new(column)
+        }
+        DataType::Timestamp(_, timezone) => {
+            let column: TimestampMillisecondArray = cells.map(|v| if let Value::Timestamp(t) = v { Some(t) } else { None }).collect();
+            Arc::new(column.with_timezone_opt(timezone.clone()))
+        }
+        _ => {
+            let column: StringArray = batch.iter().map(|row| row.get(i).map(String::as_str)).collect();
+            Arc::new(column)
+        }
+    }
+}
+
+/// Writes the rows as an Arrow IPC file, one record batch per
+/// [`PROGRESS_INTERVAL`] rows. Numbers, checkboxes and timestamps get
+/// their own Arrow types when all their cells decode; everything else is
+/// written as text.
+pub fn write<W: Write>(out: W, headers: &[String], rows: &[Vec<String>], types: &[ColumnType], mut progress: impl FnMut(usize)) -> Result<usize, IOError> {
+    let data_types: Vec<DataType> = types.iter().enumerate().map(|(i, t)| column_data_type(rows, i, t)).collect();
+    let fields: Vec<Field> = headers
+        .iter()
+        .zip(&data_types)
+        .map(|(h, t)| Field::new(h, t.clone(), true))
+        .collect();
+    let schema = Arc::new(Schema::new(fields));
+    let mut writer = FileWriter::try_new(out, &schema)?;
+
+    let mut written = 0;
+    for batch in rows.chunks(PROGRESS_INTERVAL) {
+        let columns: Vec<ArrayRef> = types
+            .iter()
+            .zip(&data_types)
+            .enumerate()
+            .map(|(i, (t, data_type))| column(batch, i, t, data_type))
+            .collect();
+        writer.write(&RecordBatch::try_new(schema.clone(), columns)?)?;
+        written += batch.len();
+        progress(written);
+    }
+    writer.finish()?;
+    Ok(written)
+}
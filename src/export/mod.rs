@@ -0,0 +1,335 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use flowync::error::IOError;
+
+use crate::socrata::types::ColumnType;
+
+mod arrow;
+
+/// How often, in rows, progress is reported while writing
+pub const PROGRESS_INTERVAL: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    /// CSV with a byte order mark and CRLF line endings so Excel reads UTF-8 correctly
+    ExcelCsv,
+    Json,
+    Ndjson,
+    ArrowIpc,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 5] = [
+        ExportFormat::Csv,
+        ExportFormat::ExcelCsv,
+        ExportFormat::Json,
+        ExportFormat::Ndjson,
+        ExportFormat::ArrowIpc,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::ExcelCsv => "CSV (Excel)",
+            ExportFormat::Json => "JSON",
+            ExportFormat::Ndjson => "NDJSON",
+            ExportFormat::ArrowIpc => "Arrow IPC",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv | ExportFormat::ExcelCsv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::ArrowIpc => "arrow",
+        }
+    }
+}
+
+pub enum ExportChannel {
+    /// Rows written so far
+    Rows(usize),
+}
+
+pub enum ExportErrCause {
+    Data(String),
+}
+
+pub enum ExportContainer {
+    /// Total rows written
+    Rows(usize),
+}
+
+#[derive(Default)]
+pub struct ExportResponseData {
+    /// The tab whose results are being, or were last, exported
+    pub tab: Option<usize>,
+    pub rows_written: usize,
+    pub is_running: bool,
+    pub message: Option<String>,
+    pub error: Option<String>,
+}
+
+impl ExportResponseData {
+    pub fn set_done(&mut self, message: impl ToString) {
+        self.error.take();
+        self.is_running = false;
+        self.message = Some(message.to_string());
+    }
+
+    pub fn set_error(&mut self, e: impl ToString) {
+        self.is_running = false;
+        self.error = Some(e.to_string());
+    }
+}
+
+/// Writes `data` (header row first) to `path`, calling `progress` with the
+/// number of rows written every [`PROGRESS_INTERVAL`] rows. JSON and Arrow
+/// keep the column `types`; columns without one are written as text.
+pub fn write(path: &Path, format: ExportFormat, data: &[Vec<String>], types: &[ColumnType], progress: impl FnMut(usize)) -> Result<usize, IOError> {
+    write_to(File::create(path)?, format, data, types, progress)
+}
+
+/// Like [`write`], but to any writer, e.g. stdout
+pub fn write_to<W: Write>(out: W, format: ExportFormat, data: &[Vec<String>], types: &[ColumnType], progress: impl FnMut(usize)) -> Result<usize, IOError> {
+    let (headers, rows) = match data.split_first() {
+        Some((headers, rows)) => (headers.as_slice(), rows),
+        None => (&[][..], &[][..]),
+    };
+    let types: Vec<ColumnType> = (0..headers.len())
+        .map(|i| types.get(i).cloned().unwrap_or(ColumnType::Text))
+        .collect();
+    match format {
+        ExportFormat::Csv => write_csv(BufWriter::new(out), headers, rows, csv::Terminator::Any(b'\n'), progress),
+        ExportFormat::ExcelCsv => {
+            let mut out = BufWriter::new(out);
+            out.write_all(b"\xEF\xBB\xBF")?;
+            write_csv(out, headers, rows, csv::Terminator::CRLF, progress)
+        }
+        ExportFormat::Json => write_json(BufWriter::new(out), headers, rows, &types, false, progress),
+        ExportFormat::Ndjson => write_json(BufWriter::new(out), headers, rows, &types, true, progress),
+        ExportFormat::ArrowIpc => arrow::write(out, headers, rows, &types, progress),
+    }
+}
+
+fn write_csv<W: Write>(out: W, headers: &[String], rows: &[Vec<String>], terminator: csv::Terminator, mut progress: impl FnMut(usize)) -> Result<usize, IOError> {
+    let mut writer = csv::WriterBuilder::new()
+        .terminator(terminator)
+        .flexible(true)
+        .from_writer(out);
+    writer.write_record(headers)?;
+    for (i, row) in rows.iter().enumerate() {
+        writer.write_record(row)?;
+        if (i + 1) % PROGRESS_INTERVAL == 0 {
+            progress(i + 1);
+        }
+    }
+    writer.flush()?;
+    Ok(rows.len())
+}
+
+fn write_json<W: Write>(mut out: W, headers: &[String], rows: &[Vec<String>], types: &[ColumnType], lines: bool, mut progress: impl FnMut(usize)) -> Result<usize, IOError> {
+    if !lines {
+        out.write_all(b"[")?;
+    }
+    for (i, row) in rows.iter().enumerate() {
+        if i > 0 {
+            out.write_all(if lines { b"\n" } else { b",\n" })?;
+        }
+        // Written by hand so that keys keep the column order
+        out.write_all(b"{")?;
+        for (j, ((header, cell), data_type)) in headers.iter().zip(row.iter()).zip(types).enumerate() {
+            if j > 0 {
+                out.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut out, header)?;
+            out.write_all(b":")?;
+            serde_json::to_writer(&mut out, &data_type.to_json(cell))?;
+        }
+        out.write_all(b"}")?;
+        if (i + 1) % PROGRESS_INTERVAL == 0 {
+            progress(i + 1);
+        }
+    }
+    out.write_all(if lines { b"\n" } else { b"]\n" })?;
+    out.flush()?;
+    Ok(rows.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use arrow_array::{Array, BooleanArray, Float64Array, StringArray, TimestampMillisecondArray};
+    use arrow_ipc::reader::FileReader;
+    use arrow_schema::{DataType, TimeUnit};
+    use serde_json::json;
+
+    use super::*;
+
+    fn data() -> Vec<Vec<String>> {
+        [&["name", "note"][..], &["Brooklyn", "says \"hi\", twice"], &["Bronx", ""]]
+            .iter()
+            .map(|row| row.iter().map(|s| s.to_string()).collect())
+            .collect()
+    }
+
+    fn typed_data() -> (Vec<Vec<String>>, Vec<ColumnType>) {
+        let data = [
+            &["name", "count", "open", "created", "location"][..],
+            &["Brooklyn", "12.5", "true", "2023-01-31T12:00:00.000", "POINT (1 2)"],
+            &["Bronx", "", "", "", ""],
+        ]
+        .iter()
+        .map(|row| row.iter().map(|s| s.to_string()).collect())
+        .collect();
+        let types = vec![ColumnType::Text, ColumnType::Number, ColumnType::Checkbox, ColumnType::FloatingTimestamp, ColumnType::Point];
+        (data, types)
+    }
+
+    fn export(format: ExportFormat, data: &[Vec<String>]) -> Vec<u8> {
+        let mut out = vec![];
+        let rows = write_to(&mut out, format, data, &[], |_| {}).unwrap();
+        assert_eq!(rows, data.len().saturating_sub(1));
+        out
+    }
+
+    #[test]
+    fn writes_csv() {
+        let out = String::from_utf8(export(ExportFormat::Csv, &data())).unwrap();
+        assert_eq!(out, "name,note\nBrooklyn,\"says \"\"hi\"\", twice\"\nBronx,\n");
+    }
+
+    #[test]
+    fn excel_csv_has_a_bom_and_crlf() {
+        let out = export(ExportFormat::ExcelCsv, &data());
+        assert!(out.starts_with(b"\xEF\xBB\xBF"));
+        let out = String::from_utf8(out[3..].to_vec()).unwrap();
+        assert_eq!(out, "name,note\r\nBrooklyn,\"says \"\"hi\"\", twice\"\r\nBronx,\r\n");
+    }
+
+    /// CSV can't tell an empty text from a missing value, so empty cells are null
+    #[test]
+    fn writes_json_in_column_order() {
+        let out = String::from_utf8(export(ExportFormat::Json, &data())).unwrap();
+        assert_eq!(
+            out,
+            "[{\"name\":\"Brooklyn\",\"note\":\"says \\\"hi\\\", twice\"},\n{\"name\":\"Bronx\",\"note\":null}]\n"
+        );
+        let parsed: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(parsed.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn writes_ndjson() {
+        let out = String::from_utf8(export(ExportFormat::Ndjson, &data())).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines, vec![r#"{"name":"Brooklyn","note":"says \"hi\", twice"}"#, r#"{"name":"Bronx","note":null}"#]);
+    }
+
+    #[test]
+    fn empty_results_are_still_valid() {
+        assert_eq!(export(ExportFormat::Json, &[]), b"[]\n");
+        assert_eq!(export(ExportFormat::Ndjson, &data()[..1]), b"\n");
+    }
+
+    #[test]
+    fn writes_arrow_with_a_column_per_header() {
+        let out = export(ExportFormat::ArrowIpc, &data());
+        let reader = FileReader::try_new(Cursor::new(out), None).unwrap();
+        let schema = reader.schema();
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, vec!["name", "note"]);
+        assert!(schema.fields().iter().all(|f| f.data_type() == &DataType::Utf8 && f.is_nullable()));
+
+        let batches: Vec<_> = reader.map(Result::unwrap).collect();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].num_rows(), 2);
+        let notes = batches[0].column(1).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(notes.value(0), "says \"hi\", twice");
+    }
+
+    #[test]
+    fn json_keeps_column_types() {
+        let (mut data, types) = typed_data();
+        data[2][1] = "n/a".to_owned();
+        let mut out = vec![];
+        write_to(&mut out, ExportFormat::Json, &data, &types, |_| {}).unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            parsed,
+            json!([
+                {
+                    "name": "Brooklyn",
+                    "count": 12.5,
+                    "open": true,
+                    "created": "2023-01-31T12:00:00.000",
+                    "location": {"type": "Point", "coordinates": [1.0, 2.0]}
+                },
+                {"name": "Bronx", "count": "n/a", "open": null, "created": null, "location": null}
+            ])
+        );
+    }
+
+    #[test]
+    fn arrow_keeps_column_types() {
+        let (data, types) = typed_data();
+        let mut out = vec![];
+        write_to(&mut out, ExportFormat::ArrowIpc, &data, &types, |_| {}).unwrap();
+        let reader = FileReader::try_new(Cursor::new(out), None).unwrap();
+        let data_types: Vec<DataType> = reader.schema().fields().iter().map(|f| f.data_type().clone()).collect();
+        assert_eq!(
+            data_types,
+            vec![
+                DataType::Utf8,
+                DataType::Float64,
+                DataType::Boolean,
+                DataType::Timestamp(TimeUnit::Millisecond, None),
+                DataType::Utf8
+            ]
+        );
+
+        let batch = reader.map(Result::unwrap).next().unwrap();
+        let counts = batch.column(1).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(counts.value(0), 12.5);
+        assert!(counts.is_null(1));
+        let open = batch.column(2).as_any().downcast_ref::<BooleanArray>().unwrap();
+        assert!(open.value(0));
+        assert!(open.is_null(1));
+        let created = batch.column(3).as_any().downcast_ref::<TimestampMillisecondArray>().unwrap();
+        assert_eq!(created.value(0), 1_675_166_400_000);
+        assert!(created.is_null(1));
+    }
+
+    #[test]
+    fn arrow_keeps_undecodable_cells_as_text() {
+        let (mut data, types) = typed_data();
+        data[2][1] = "n/a".to_owned();
+        data[2][3] = "yesterday".to_owned();
+        let mut out = vec![];
+        write_to(&mut out, ExportFormat::ArrowIpc, &data, &types, |_| {}).unwrap();
+        let reader = FileReader::try_new(Cursor::new(out), None).unwrap();
+        let data_types: Vec<DataType> = reader.schema().fields().iter().map(|f| f.data_type().clone()).collect();
+        assert_eq!(data_types[1..4], [DataType::Utf8, DataType::Boolean, DataType::Utf8]);
+
+        let batch = reader.map(Result::unwrap).next().unwrap();
+        let counts = batch.column(1).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!((counts.value(0), counts.value(1)), ("12.5", "n/a"));
+        let created = batch.column(3).as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(created.value(1), "yesterday");
+    }
+
+    #[test]
+    fn reports_progress_every_interval() {
+        let mut data = data();
+        data.truncate(1);
+        data.extend((0..PROGRESS_INTERVAL * 2 + 1).map(|i| vec![i.to_string(), String::new()]));
+        let mut reported = vec![];
+        write_to(vec![], ExportFormat::Csv, &data, &[], |rows| reported.push(rows)).unwrap();
+        assert_eq!(reported, vec![PROGRESS_INTERVAL, PROGRESS_INTERVAL * 2]);
+    }
+}
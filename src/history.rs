@@ -0,0 +1,131 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::util::format_timestamp;
+
+const HISTORY_JSON_FILE_PATH: &str = "history.json";
+// Oldest entries are dropped past this many
+const MAX_ENTRIES: usize = 500;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct HistoryEntry {
+    pub domain: String,
+    pub dataset: String,
+    pub query: String,
+    /// Seconds since the Unix epoch when the query was started
+    pub timestamp: u64,
+    pub duration: Duration,
+    pub rows: Option<usize>,
+    pub error: Option<String>,
+}
+
+impl HistoryEntry {
+    pub fn started(domain: &str, dataset: &str, query: &str) -> Self {
+        Self {
+            domain: domain.to_owned(),
+            dataset: dataset.to_owned(),
+            query: query.to_owned(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            duration: Duration::new(0, 0),
+            rows: None,
+            error: None,
+        }
+    }
+
+    /// The start time as `YYYY-MM-DD HH:MM:SS` in UTC
+    pub fn started_at(&self) -> String {
+        format_timestamp(self.timestamp)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct History {
+    pub entries: Vec<HistoryEntry>,
+    /// How many entries have been dropped since the history was loaded
+    #[serde(skip)]
+    dropped: usize,
+}
+
+impl History {
+    pub fn record(&mut self, entry: HistoryEntry) {
+        self.entries.push(entry);
+        if self.entries.len() > MAX_ENTRIES {
+            let excess = self.entries.len() - MAX_ENTRIES;
+            self.entries.drain(..excess);
+            self.dropped += excess;
+        }
+        set_history(self);
+    }
+
+    /// An id for the entry at `index` which keeps pointing at it when older
+    /// entries are dropped
+    pub fn id(&self, index: usize) -> usize {
+        self.dropped + index
+    }
+
+    /// The entry with the given [`id`](Self::id), unless it has been dropped
+    pub fn get(&self, id: usize) -> Option<&HistoryEntry> {
+        id.checked_sub(self.dropped).and_then(|i| self.entries.get(i))
+    }
+}
+
+pub fn get_history() -> History {
+    std::fs::read_to_string(HISTORY_JSON_FILE_PATH)
+        .ok()
+        .and_then(|raw| serde_json::from_str::<History>(&raw).ok())
+        .unwrap_or_default()
+}
+
+pub fn set_history(history: &History) {
+    std::fs::write(
+        HISTORY_JSON_FILE_PATH,
+        serde_json::to_string_pretty(history).unwrap(),
+    ).unwrap();
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Line based diff of two queries using their longest common subsequence
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    // lcs[i][j] is the LCS length of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(DiffLine::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            lines.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+    lines.extend(old[i..].iter().map(|l| DiffLine::Removed(l)));
+    lines.extend(new[j..].iter().map(|l| DiffLine::Added(l)));
+    lines
+}
@@ -0,0 +1,475 @@
+use std::path::PathBuf;
+
+use flowync::{error::Compact, CompactFlower, CompactHandle};
+use tokio::time::{Duration, Instant};
+
+use crate::history::HistoryEntry;
+use crate::socrata::analysis::{AnalysisChannel, AnalysisContainer, AnalysisErrCause, AnalysisResponseData};
+use crate::socrata::client::CancelToken;
+use crate::socrata::data::{Channel, Container, ErrCause};
+use crate::workspace::{QueryTab, Workspace};
+
+pub type DataFlower = CompactFlower<Channel, Container, ErrCause>;
+pub type DataFlowerHandle = CompactHandle<Channel, Container, ErrCause>;
+pub type AnalysisFlower = CompactFlower<AnalysisChannel, AnalysisContainer, AnalysisErrCause>;
+pub type AnalysisFlowerHandle = CompactHandle<AnalysisChannel, AnalysisContainer, AnalysisErrCause>;
+
+/// Finished jobs kept around for the jobs panel
+const MAX_FINISHED: usize = 50;
+
+pub enum JobKind {
+    Query,
+    Download(PathBuf),
+    Analysis,
+}
+
+impl JobKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            JobKind::Query => "Query",
+            JobKind::Download(_) => "Download",
+            JobKind::Analysis => "Analysis",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobState {
+    Running,
+    /// Finished, with a short summary of what came back
+    Done(String),
+    Failed(String),
+    Canceled,
+}
+
+/// What a job has received so far
+#[derive(Default)]
+pub struct Progress {
+    pub bytes: usize,
+    /// Records, not counting the header row
+    pub rows: usize,
+    pub pages: usize,
+    header_seen: bool,
+    /// Reported by the job once it is done
+    elapsed: Option<Duration>,
+}
+
+enum JobFlower {
+    Data(DataFlower),
+    Analysis(AnalysisFlower),
+}
+
+/// A fetch, download or analysis started from a query tab
+pub struct Job {
+    pub id: usize,
+    pub tab_id: usize,
+    pub kind: JobKind,
+    /// The dataset and the start of the query
+    pub title: String,
+    pub state: JobState,
+    pub progress: Progress,
+    started: Instant,
+    finished: Option<Instant>,
+    flower: JobFlower,
+    /// Stops the request itself, the flower only tells the UI
+    token: CancelToken,
+    /// Recorded in the history once the job is over
+    entry: Option<HistoryEntry>,
+}
+
+impl Job {
+    pub fn is_running(&self) -> bool {
+        self.state == JobState::Running
+    }
+
+    fn is_fetch(&self) -> bool {
+        !matches!(self.kind, JobKind::Analysis)
+    }
+
+    /// Time spent so far, or until the job was over
+    pub fn elapsed(&self) -> Duration {
+        match self.finished {
+            Some(finished) => self.progress.elapsed.unwrap_or_else(|| finished - self.started),
+            None => self.started.elapsed(),
+        }
+    }
+
+    pub fn cancel(&self) {
+        if !self.is_running() {
+            return;
+        }
+        match &self.flower {
+            JobFlower::Data(flower) => flower.cancel(),
+            JobFlower::Analysis(flower) => flower.cancel(),
+        }
+        self.token.cancel();
+    }
+
+    /// Moves what the job sent since the last frame into `tab`,
+    /// and returns the outcome once it is over
+    fn poll(&mut self, mut tab: Option<&mut QueryTab>, analysis: &mut AnalysisResponseData, latest_analysis: bool) -> Option<JobState> {
+        let progress = &mut self.progress;
+        let mut state = None;
+        match &self.flower {
+            JobFlower::Data(flower) => {
+                flower
+                    .extract(|message| {
+                        match message {
+                            Channel::Data(b) => {
+                                progress.bytes += b;
+                                if let Some(tab) = tab.as_deref_mut() {
+                                    tab.csv_data.tmp_file_size += b;
+                                }
+                            }
+                            Channel::Elapsed(e) => {
+                                progress.elapsed = Some(e);
+                                if let Some(tab) = tab.as_deref_mut() {
+                                    tab.query_duration = e;
+                                }
+                            }
+                            Channel::Rows(rows) => {
+                                // The first rows to arrive start with the header
+                                let header = !progress.header_seen && !rows.is_empty();
+                                progress.header_seen |= header;
+                                progress.rows += rows.len() - header as usize;
+                                if let Some(tab) = tab.as_deref_mut() {
+                                    tab.csv_data.append_rows(rows);
+                                }
+                            }
+                            Channel::Page(p) => {
+                                progress.pages = p;
+                                if let Some(tab) = tab.as_deref_mut() {
+                                    tab.csv_data.pages = p;
+                                }
+                            }
+                            Channel::Columns(columns) => {
+                                if let Some(tab) = tab.as_deref_mut() {
+                                    tab.csv_data.columns = columns;
+                                }
+                            }
+                        }
+                    })
+                    .finalize(|result| {
+                        state = Some(match result {
+                            Ok(Container::Rows(rows)) => {
+                                progress.rows = rows;
+                                JobState::Done(String::new())
+                            }
+                            Ok(Container::Downloaded(bytes)) => {
+                                let message = format!("Saved {} KB to disk", bytes / 1000);
+                                if let Some(tab) = tab.as_deref_mut() {
+                                    tab.csv_data.message = Some(message.to_owned());
+                                }
+                                JobState::Done(message)
+                            }
+                            Ok(Container::Elapsed(_)) => JobState::Done(String::new()),
+                            Err(Compact::Suppose(ErrCause::Elapsed(e))) => {
+                                if let Some(tab) = tab.as_deref_mut() {
+                                    tab.query_duration = Duration::new(0, 0);
+                                }
+                                JobState::Failed(e)
+                            }
+                            Err(Compact::Suppose(ErrCause::Data(e))) => {
+                                if let Some(tab) = tab.as_deref_mut() {
+                                    tab.csv_data.set_error(&e);
+                                }
+                                JobState::Failed(e)
+                            }
+                            // Handle stuff if tokio runtime panicked as well,
+                            // but don't do that and stay calm is highly encouraged.
+                            Err(Compact::Panicked(err)) => {
+                                if let Some(tab) = tab.as_deref_mut() {
+                                    tab.csv_data.set_error(&err);
+                                }
+                                JobState::Failed(err)
+                            }
+                        });
+                    });
+                if state.is_some() {
+                    if let Some(tab) = tab {
+                        tab.csv_data.repair();
+                    }
+                    if flower.is_canceled() {
+                        state = Some(JobState::Canceled);
+                    }
+                }
+            }
+            JobFlower::Analysis(flower) => {
+                flower
+                    .extract(|message| {
+                        match message {
+                            // Not using this for anything yet
+                            AnalysisChannel::Data(_) => {}
+                        }
+                    })
+                    .finalize(|result| {
+                        state = Some(match result {
+                            Ok(AnalysisContainer::Data(data)) => {
+                                if latest_analysis {
+                                    analysis.set_data(data);
+                                }
+                                JobState::Done("Plan ready".into())
+                            }
+                            Err(Compact::Suppose(AnalysisErrCause::Data(e))) => {
+                                if latest_analysis {
+                                    analysis.set_error(&e);
+                                }
+                                JobState::Failed(e)
+                            }
+                            Err(Compact::Panicked(err)) => {
+                                if latest_analysis {
+                                    analysis.set_error(&err);
+                                }
+                                JobState::Failed(err)
+                            }
+                        });
+                    });
+                if state.is_some() {
+                    if latest_analysis {
+                        analysis.is_running = false;
+                    }
+                    if flower.is_canceled() {
+                        state = Some(JobState::Canceled);
+                    }
+                }
+            }
+        }
+        state
+    }
+
+    fn finish(&mut self, state: JobState) -> Option<HistoryEntry> {
+        self.finished = Some(Instant::now());
+        self.state = state;
+        let mut entry = self.entry.take()?;
+        entry.error = match &self.state {
+            JobState::Failed(e) => Some(e.to_owned()),
+            JobState::Canceled => Some("Canceled".into()),
+            _ => None,
+        };
+        entry.duration = self.elapsed();
+        entry.rows = Some(self.progress.rows).filter(|_| self.progress.header_seen);
+        Some(entry)
+    }
+}
+
+/// Every fetch, download and analysis started this session.
+/// Each tab runs at most one fetch and one analysis at a time.
+#[derive(Default)]
+pub struct Jobs {
+    pub jobs: Vec<Job>,
+    next_id: usize,
+    /// The analysis whose plan is shown in the analysis panel
+    latest_analysis: Option<usize>,
+}
+
+impl Jobs {
+    fn start(&mut self, tab: &QueryTab, kind: JobKind, flower: JobFlower, entry: Option<HistoryEntry>) -> CancelToken {
+        let token = CancelToken::default();
+        self.jobs.push(Job {
+            id: self.next_id,
+            tab_id: tab.id,
+            kind,
+            title: tab.title(),
+            state: JobState::Running,
+            progress: Default::default(),
+            started: Instant::now(),
+            finished: None,
+            flower,
+            token: token.clone(),
+            entry,
+        });
+        self.next_id += 1;
+        token
+    }
+
+    /// Starts a query or download for `tab`. Its history entry is recorded once it is over.
+    pub fn start_fetch(&mut self, tab: &QueryTab, kind: JobKind, entry: Option<HistoryEntry>) -> (DataFlowerHandle, CancelToken) {
+        let flower = DataFlower::new(self.next_id);
+        let handle = flower.handle();
+        let token = self.start(tab, kind, JobFlower::Data(flower), entry);
+        (handle, token)
+    }
+
+    pub fn start_analysis(&mut self, tab: &QueryTab) -> (AnalysisFlowerHandle, CancelToken) {
+        let flower = AnalysisFlower::new(self.next_id);
+        let handle = flower.handle();
+        self.latest_analysis = Some(self.next_id);
+        let token = self.start(tab, JobKind::Analysis, JobFlower::Analysis(flower), None);
+        (handle, token)
+    }
+
+    /// The query or download of `tab` that is running or ran last
+    pub fn last_fetch(&self, tab_id: usize) -> Option<&Job> {
+        self.jobs.iter().rev().find(|job| job.tab_id == tab_id && job.is_fetch())
+    }
+
+    pub fn is_fetching(&self, tab_id: usize) -> bool {
+        self.last_fetch(tab_id).map_or(false, |job| job.is_running())
+    }
+
+    pub fn is_analyzing(&self, tab_id: usize) -> bool {
+        self.jobs
+            .iter()
+            .any(|job| job.tab_id == tab_id && !job.is_fetch() && job.is_running())
+    }
+
+    pub fn cancel(&self, id: usize) {
+        if let Some(job) = self.jobs.iter().find(|job| job.id == id) {
+            job.cancel();
+        }
+    }
+
+    /// Cancels the query or download of `tab`, leaving its analysis running
+    pub fn cancel_fetch(&self, tab_id: usize) {
+        if let Some(job) = self.last_fetch(tab_id) {
+            job.cancel();
+        }
+    }
+
+    pub fn cancel_analysis(&self, tab_id: usize) {
+        self.jobs
+            .iter()
+            .filter(|job| job.tab_id == tab_id && !job.is_fetch())
+            .for_each(Job::cancel);
+    }
+
+    /// Cancels everything `tab` started, e.g. when it is closed
+    pub fn cancel_tab(&self, tab_id: usize) {
+        self.jobs
+            .iter()
+            .filter(|job| job.tab_id == tab_id)
+            .for_each(Job::cancel);
+    }
+
+    pub fn clear_finished(&mut self) {
+        self.jobs.retain(Job::is_running);
+    }
+
+    /// Moves what the running jobs sent into their tabs and the analysis panel.
+    /// Returns the history entries of the queries that are over.
+    pub fn poll(&mut self, workspace: &mut Workspace, analysis: &mut AnalysisResponseData) -> Vec<HistoryEntry> {
+        let mut entries = vec![];
+        for job in self.jobs.iter_mut().filter(|job| job.is_running()) {
+            let latest_analysis = self.latest_analysis == Some(job.id);
+            // Results of a closed tab are dropped
+            if let Some(state) = job.poll(workspace.tab_mut(job.tab_id), analysis, latest_analysis) {
+                entries.extend(job.finish(state));
+            }
+        }
+        let finished = self.jobs.iter().filter(|job| !job.is_running()).count();
+        if finished > MAX_FINISHED {
+            let mut excess = finished - MAX_FINISHED;
+            self.jobs.retain(|job| {
+                let drop = excess > 0 && !job.is_running();
+                excess -= drop as usize;
+                !drop
+            });
+        }
+        entries
+    }
+
+    pub fn running(&self) -> usize {
+        self.jobs.iter().filter(|job| job.is_running()).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    /// Polls until at most `left` jobs are running, collecting the finished queries
+    fn run_until(left: usize, jobs: &mut Jobs, workspace: &mut Workspace, analysis: &mut AnalysisResponseData) -> Vec<HistoryEntry> {
+        let mut entries = vec![];
+        while jobs.running() > left {
+            entries.extend(jobs.poll(workspace, analysis));
+            thread::yield_now();
+        }
+        entries
+    }
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.iter().map(|s| s.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn fetches_land_in_their_own_tab() {
+        let mut workspace = Workspace::new("abcd-1234", "select a");
+        workspace.open("wxyz-9876", "select b");
+        let mut jobs = Jobs::default();
+        let mut analysis = AnalysisResponseData::default();
+
+        for (tab_id, value) in [(0, "1"), (1, "2")] {
+            let tab = workspace.tab_mut(tab_id).unwrap();
+            let entry = HistoryEntry::started("example.com", &tab.dataset, &tab.current_query);
+            let (handle, _) = jobs.start_fetch(tab, JobKind::Query, Some(entry));
+            let value = value.to_owned();
+            thread::spawn(move || {
+                handle.activate();
+                handle.send(Channel::Rows(rows(&[&["a"], &[&value], &[&value]])));
+                handle.success(Container::Rows(2));
+            });
+        }
+        assert!(jobs.is_fetching(0) && jobs.is_fetching(1));
+
+        let entries = run_until(0, &mut jobs, &mut workspace, &mut analysis);
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| entry.rows == Some(2) && entry.error.is_none()));
+        assert_eq!(workspace.tab_mut(0).unwrap().csv_data.data.as_deref(), Some(&rows(&[&["a"], &["1"], &["1"]])));
+        assert_eq!(workspace.tab_mut(1).unwrap().csv_data.data.as_deref(), Some(&rows(&[&["a"], &["2"], &["2"]])));
+        assert!(jobs.jobs.iter().all(|job| job.state == JobState::Done(String::new())));
+    }
+
+    #[test]
+    fn canceling_an_analysis_leaves_the_fetch_running() {
+        let mut workspace = Workspace::new("abcd-1234", "select a");
+        let mut jobs = Jobs::default();
+        let mut analysis = AnalysisResponseData::default();
+
+        let tab = workspace.tab_mut(0).unwrap();
+        let (fetch, token) = jobs.start_fetch(tab, JobKind::Query, None);
+        let (explain, explain_token) = jobs.start_analysis(tab);
+        jobs.cancel_analysis(0);
+        assert!(!fetch.should_cancel() && !token.is_canceled());
+        assert!(explain.should_cancel() && explain_token.is_canceled());
+
+        thread::spawn(move || {
+            explain.activate();
+            explain.error(AnalysisErrCause::Data("Analysis canceled".into()));
+        });
+        thread::spawn(move || {
+            fetch.activate();
+            fetch.success(Container::Rows(0));
+        });
+        run_until(0, &mut jobs, &mut workspace, &mut analysis);
+        assert_eq!(jobs.jobs[0].state, JobState::Done(String::new()));
+        assert_eq!(jobs.jobs[1].state, JobState::Canceled);
+        assert_eq!(jobs.last_fetch(0).map(|job| job.id), Some(0));
+    }
+
+    #[test]
+    fn only_the_latest_analysis_is_shown() {
+        let mut workspace = Workspace::new("abcd-1234", "select a");
+        let mut jobs = Jobs::default();
+        let mut analysis = AnalysisResponseData::default();
+
+        let tab = workspace.tab_mut(0).unwrap();
+        let (older, _) = jobs.start_analysis(tab);
+        let (newer, _) = jobs.start_analysis(tab);
+        thread::spawn(move || {
+            newer.activate();
+            newer.error(AnalysisErrCause::Data("newer".into()));
+        });
+        run_until(1, &mut jobs, &mut workspace, &mut analysis);
+        assert_eq!(analysis.error.as_deref(), Some("newer"));
+
+        thread::spawn(move || {
+            older.activate();
+            older.error(AnalysisErrCause::Data("older".into()));
+        });
+        run_until(0, &mut jobs, &mut workspace, &mut analysis);
+        assert_eq!(analysis.error.as_deref(), Some("newer"));
+        assert_eq!(jobs.jobs[0].state, JobState::Failed("older".into()));
+    }
+}
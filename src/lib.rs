@@ -0,0 +1,4 @@
+//! The Socrata client behind SoQL Studio, for tools that want to run
+//! queries without the app.
+
+pub mod socrata;
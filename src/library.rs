@@ -0,0 +1,81 @@
+use std::path::Path;
+
+use flowync::error::IOError;
+use serde_derive::{Deserialize, Serialize};
+
+const LIBRARY_JSON_FILE_PATH: &str = "library.json";
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct SavedQuery {
+    pub name: String,
+    pub domain: String,
+    pub dataset: String,
+    pub query: String,
+}
+
+impl SavedQuery {
+    pub fn matches(&self, search: &str) -> bool {
+        let search = search.to_lowercase();
+        [&self.name, &self.domain, &self.dataset, &self.query]
+            .iter()
+            .any(|field| field.to_lowercase().contains(&search))
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct QueryLibrary {
+    pub queries: Vec<SavedQuery>,
+}
+
+impl QueryLibrary {
+    /// `base` if no saved query uses that name yet, otherwise `base (n)`
+    pub fn unique_name(&self, base: &str) -> String {
+        let taken = |name: &str| self.queries.iter().any(|q| q.name == name);
+        if !taken(base) {
+            return base.to_owned();
+        }
+        (2..)
+            .map(|n| format!("{} ({})", base, n))
+            .find(|name| !taken(name))
+            .unwrap()
+    }
+
+    pub fn duplicate(&mut self, index: usize) {
+        let mut copy = self.queries[index].clone();
+        copy.name = self.unique_name(&copy.name);
+        self.queries.insert(index + 1, copy);
+    }
+
+    /// Adds the queries of a shared library file, renaming any that clash
+    pub fn import(&mut self, path: &Path) -> Result<usize, IOError> {
+        let other = serde_json::from_str::<QueryLibrary>(&std::fs::read_to_string(path)?)?;
+        let count = other.queries.len();
+        for mut query in other.queries {
+            if self.queries.contains(&query) {
+                continue;
+            }
+            query.name = self.unique_name(&query.name);
+            self.queries.push(query);
+        }
+        Ok(count)
+    }
+
+    pub fn export(&self, path: &Path) -> Result<(), IOError> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+pub fn get_library() -> QueryLibrary {
+    std::fs::read_to_string(LIBRARY_JSON_FILE_PATH)
+        .ok()
+        .and_then(|raw| serde_json::from_str::<QueryLibrary>(&raw).ok())
+        .unwrap_or_default()
+}
+
+pub fn set_library(library: &QueryLibrary) {
+    std::fs::write(
+        LIBRARY_JSON_FILE_PATH,
+        serde_json::to_string_pretty(library).unwrap(),
+    ).unwrap();
+}
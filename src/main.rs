@@ -1,27 +1,56 @@
 use eframe::{egui, CreationContext};
 use egui::{RichText, FontId};
-use flowync::{
-    error::{Compact, IOError},
-    CompactFlower, CompactHandle, IntoResult,
-};
-use egui_dock::{DockArea, NodeIndex, Style, Tree};
-use csv;
-use std::str;
-use reqwest::{Client, Response};
+use egui_dock::DockArea;
+use flowync::{error::Compact, CompactFlower};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tokio::runtime;
-use tokio::time::{Instant, Duration};
+use tokio::time::Instant;
 
 mod config;
-use config::{get_config, set_config, Config};
-mod socrata;
-use socrata::{make_query, make_analyze_url};
-use socrata::data::{Channel, Container, ErrCause, ResponseData};
-use socrata::analysis::{AnalysisChannel, AnalysisContainer, AnalysisErrCause, AnalysisResponseData};
+use config::{get_config, set_config, Config, LegacyLogin, Profile};
+mod credentials;
+use credentials::CredentialRef;
+mod library;
+use library::{get_library, set_library, QueryLibrary, SavedQuery};
+mod history;
+use history::{get_history, History, HistoryEntry};
+use soqlstudio::socrata;
+use socrata::{make_query, make_analyze_url, make_schema_url};
+use socrata::client::{ClientError, Progress, SocrataClient};
+use socrata::paging::{PagedQuery, Paging};
+use socrata::auth::Auth;
+use socrata::schema::{DatasetSchema, SchemaCache, SchemaErrCause};
+use socrata::catalog::{CatalogErrCause, CatalogPage, CatalogResults, CatalogSearch};
+use socrata::data::{Channel, Container, ErrCause};
+use socrata::analysis::{AnalysisContainer, AnalysisErrCause, AnalysisResponseData};
 mod syntaxhighlight;
+mod export;
+use export::{ExportChannel, ExportContainer, ExportErrCause, ExportFormat, ExportResponseData};
+mod panels;
+use panels::library::{LibraryAction, LibraryPanel};
+use panels::history::{HistoryAction, HistoryPanel};
+use panels::profiles::{ProfileAction, ProfilesPanel};
+use panels::schema::{SchemaAction, SchemaPanel};
+use panels::catalog::{CatalogAction, CatalogPanel};
+use panels::query::{QueryTabs, TabAction};
+use panels::jobs::JobsAction;
+mod workspace;
+use workspace::Workspace;
+mod jobs;
+use jobs::{DataFlowerHandle, JobKind, Jobs};
+mod cli;
+mod util;
 
 const PPP: f32 = 1.25;
+/// Where the query tabs are kept in eframe's storage
+const WORKSPACE_KEY: &str = "workspace";
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if cli::is_command(&args) {
+        std::process::exit(cli::run(&args));
+    }
     let options = eframe::NativeOptions::default();
     eframe::run_native(
         "SoQL Studio",
@@ -30,261 +59,558 @@ fn main() {
     );
 }
 
-type DataFlower = CompactFlower<Channel, Container, ErrCause>;
-type DataFlowerHandle = CompactHandle<Channel, Container, ErrCause>;
-type AnalysisFlower = CompactFlower<AnalysisChannel, AnalysisContainer, AnalysisErrCause>;
+type ExportFlower = CompactFlower<ExportChannel, ExportContainer, ExportErrCause>;
+type SchemaFlower = CompactFlower<(), DatasetSchema, SchemaErrCause>;
+type CatalogFlower = CompactFlower<(), CatalogPage, CatalogErrCause>;
 
 struct SoqlStudio {
     rt: runtime::Runtime,
-    flower: DataFlower,
-    analysis_flower: AnalysisFlower,
-    get_data: bool,
-    btn_label_next: String,
-    csv_data: ResponseData,
+    export_flower: ExportFlower,
+    schema_flower: SchemaFlower,
+    catalog_flower: CatalogFlower,
+    workspace: Workspace,
+    jobs: Jobs,
+    show_jobs: bool,
     analysis_data: AnalysisResponseData,
+    show_analysis: bool,
+    export_data: ExportResponseData,
+    export_format: ExportFormat,
     domain: String,
-    username: String,
-    password: String,
-    current_query: String,
-    dataset: String,
-    url: String,
-    query_duration: Duration,
+    auth: Auth,
+    profiles: Vec<Profile>,
+    active_profile: usize,
+    profiles_panel: ProfilesPanel,
+    /// Auth as last written to or read from the credential store
+    stored_auth: Option<Auth>,
+    /// Plaintext login from an older config, written back to it until it
+    /// has been moved to the credential store
+    legacy_login: Option<LegacyLogin>,
+    master_passphrase: String,
+    credentials_error: Option<String>,
+    schemas: SchemaCache,
+    paging: Paging,
+    resume_download: bool,
+    library: QueryLibrary,
+    library_panel: LibraryPanel,
+    show_library: bool,
+    history: History,
+    history_panel: HistoryPanel,
+    show_history: bool,
+    schema_panel: SchemaPanel,
+    show_schema: bool,
+    catalog: CatalogResults,
+    catalog_panel: CatalogPanel,
+    show_catalog: bool,
 }
 
 impl SoqlStudio {
     fn new(ctx: &CreationContext) -> Self {
         ctx.egui_ctx.set_pixels_per_point(PPP);
         let c = get_config();
-        Self {
+        let active_profile = c.profiles
+            .iter()
+            .position(|p| p.name == c.last_profile)
+            .unwrap_or(0);
+        let profile = c.profiles[active_profile].to_owned();
+        let legacy_login = c.legacy_login();
+        // Only the profile made from the old connection gets its login
+        let login = legacy_login.as_ref().filter(|login| login.profile == profile.id);
+        // Tabs from the last session, or a single one with the last query
+        let workspace = ctx.storage
+            .and_then(|storage| eframe::get_value(storage, WORKSPACE_KEY))
+            .unwrap_or_else(|| Workspace::new(&profile.dataset, &c.query));
+        let mut app = Self {
             rt: runtime::Builder::new_multi_thread()
                 .enable_all()
                 .build()
                 .unwrap(),
-            flower: DataFlower::new(1),
-            analysis_flower: AnalysisFlower::new(2),
-            get_data: true,
-            btn_label_next: "Run Query".into(),
-            csv_data: Default::default(),
+            export_flower: ExportFlower::new(3),
+            schema_flower: SchemaFlower::new(4),
+            catalog_flower: CatalogFlower::new(5),
+            workspace,
+            jobs: Default::default(),
+            show_jobs: false,
             analysis_data: Default::default(),
-            username: c.username,
-            password: c.password,
-            domain: c.domain,
-            dataset: c.dataset,
-            current_query: c.query,
-            url: "".into(),
-            query_duration: Duration::new(0, 0),
+            show_analysis: false,
+            export_data: Default::default(),
+            export_format: ExportFormat::Csv,
+            auth: Auth {
+                mode: profile.auth_mode,
+                username: login.map(|login| login.username.to_owned()).unwrap_or_default(),
+                password: login.map(|login| login.password.to_owned()).unwrap_or_default(),
+                ..Default::default()
+            },
+            profiles: c.profiles,
+            active_profile,
+            profiles_panel: Default::default(),
+            stored_auth: None,
+            legacy_login,
+            master_passphrase: "".into(),
+            credentials_error: None,
+            domain: profile.domain,
+            schemas: Default::default(),
+            paging: c.paging,
+            resume_download: true,
+            library: get_library(),
+            library_panel: Default::default(),
+            show_library: false,
+            history: get_history(),
+            history_panel: Default::default(),
+            show_history: false,
+            schema_panel: Default::default(),
+            show_schema: false,
+            catalog: Default::default(),
+            catalog_panel: Default::default(),
+            show_catalog: false,
+        };
+        // Logins used to be stored in plaintext; those are moved on the next save
+        if app.auth.password.is_empty() && app.credentials().map_or(false, |c| !c.needs_passphrase()) {
+            app.unlock_credentials();
         }
+        app
     }
 
-    async fn fetch_data(url: String, username: String, password: String, handle: &DataFlowerHandle) -> Result<Container, IOError> {
-        let start = Instant::now();
-        // Build a client
-        let client = Client::builder()
-            // Needed to set UA to get image file, otherwise reqwest error 403
-            .build()?;
-        let mut response = client
-            .get(url)
-            .basic_auth(username, Some(password))
-            .send()
-            .await?;
-
-        // Get Content-Type
-        let content_type = response
-            .headers()
-            .get("Content-Type")
-            .catch("unable to get content type")?
-            .to_str()?
-            .to_owned();
-        
-        
-        if content_type.contains("text/csv") {
-            let cancelation_msg = "Fetching image canceled.";
-            let mut image_bytes = Vec::new();
-            {
-                while let Some(a_chunk) = response.chunk().await? {
-                    // Handle cancelation here
-                    if handle.should_cancel() {
-                        return Err(cancelation_msg.into());
-                    }
+    fn client(&self) -> Result<SocrataClient, ClientError> {
+        SocrataClient::new(self.domain.as_str(), self.auth.to_owned())
+    }
 
-                    // Send chunk size as download progress
-                    let progress = Channel::Data(a_chunk.len());
-                    handle.send_async(progress).await;
-                    a_chunk.into_iter().for_each(|x| {
-                        image_bytes.push(x);
-                    });
-                }
+    /// Reports how a fetch ended, after the time it took
+    async fn finish_fetch(handle: &DataFlowerHandle, start: Instant, result: Result<Container, ClientError>, canceled: &str) {
+        match result {
+            Ok(container) => {
+                handle.send_async(Channel::Elapsed(start.elapsed())).await;
+                handle.success(container);
+            }
+            Err(ClientError::Canceled) => handle.error(ErrCause::Data(canceled.into())),
+            Err(e) => handle.error(ErrCause::Data(e.to_string())),
+        }
+    }
+
+    fn spawn_download(&mut self, tab_id: usize, path: PathBuf) {
+        // Check the connection settings before anything is written to disk
+        let client = self.client();
+        let tab = match self.workspace.tab_mut(tab_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+        if self.jobs.is_fetching(tab_id) {
+            return;
+        }
+        if let Err(e) = make_query(self.domain.as_str(), tab.dataset.as_str(), tab.current_query.as_str()) {
+            tab.csv_data.set_error(e);
+            return;
+        }
+        let client = match client {
+            Ok(client) => client,
+            Err(e) => {
+                tab.csv_data.set_error(e);
+                return;
+            }
+        };
+        let (handle, cancel) = self.jobs.start_fetch(tab, JobKind::Download(path.to_owned()), None);
+        let query = PagedQuery::new(&tab.current_query);
+        let dataset = tab.dataset.to_owned();
+        let resume = self.resume_download;
+        tab.csv_data.error.take();
+        tab.csv_data.message.take();
+        tab.csv_data.is_running = true;
+        self.rt.spawn(async move {
+            handle.activate();
+            let start = Instant::now();
+            let progress = |p: Progress| handle.send_async(p.into());
+            let result = client
+                .download(&dataset, &query, &path, resume, &cancel, progress)
+                .await
+                .map(Container::Downloaded);
+            Self::finish_fetch(&handle, start, result, "Download canceled; run it again to resume.").await;
+        });
+    }
+
+    fn spawn_fetch_data(&mut self, tab_id: usize) {
+        // Save the new config
+        self.save_config();
+
+        let client = self.client();
+        let tab = match self.workspace.tab_mut(tab_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+        let url = match make_query(self.domain.as_str(), tab.dataset.as_str(), tab.current_query.as_str()) {
+            Ok(url) => url,
+            Err(e) => {
+                tab.csv_data.set_error(e);
+                return;
+            }
+        };
+        let client = match client {
+            Ok(client) => client,
+            Err(e) => {
+                tab.csv_data.set_error(e);
+                return;
             }
+        };
+        tab.url = url.to_string();
+        // Set error to None
+        tab.csv_data.error.take();
+        tab.csv_data.message.take();
+        tab.csv_data.data.take();
+        tab.csv_data.columns.clear();
+        tab.csv_data.pages = 0;
+        tab.results_grid.reset();
+        let entry = HistoryEntry::started(&self.domain, &tab.dataset, &tab.current_query);
+        // Show query progress
+        tab.csv_data.is_running = true;
+        // Get flower handle
+        let (handle, cancel) = self.jobs.start_fetch(tab, JobKind::Query, Some(entry));
+        let dataset = tab.dataset.to_owned();
+        let query = tab.current_query.to_owned();
+        let paging = Some(self.paging.to_owned()).filter(|paging| paging.enabled);
+        // Spawn tokio runtime.
+        self.rt.spawn(async move {
+            // Don't forget to activate flower here
+            handle.activate();
+            let start = Instant::now();
+            let progress = |p: Progress| handle.send_async(p.into());
+            // Start fetching
+            let result = match paging {
+                Some(paging) => {
+                    let query = PagedQuery::new(&query);
+                    client.query_paged(&dataset, &query, &paging, &cancel, progress).await
+                }
+                None => client.query(&dataset, &query, &cancel, progress).await,
+            };
+            Self::finish_fetch(&handle, start, result.map(Container::Rows), "Fetching data canceled.").await;
+        });
+    }
 
-            // And also handle cancelation here
-            if handle.should_cancel() {
-                return Err(cancelation_msg.into());
+    fn credentials(&self) -> Option<&CredentialRef> {
+        self.profiles[self.active_profile].credentials.as_ref()
+    }
+
+    /// Reads the auth secrets from the credential store referenced by the active profile
+    fn unlock_credentials(&mut self) {
+        let reference = match self.credentials() {
+            Some(reference) => reference,
+            None => return,
+        };
+        let passphrase = Some(self.master_passphrase.as_str()).filter(|p| !p.is_empty());
+        let auth = credentials::load(reference, passphrase, Path::new(credentials::ENCRYPTED_FILE_PATH))
+            .and_then(|secret| Ok(serde_json::from_str::<Auth>(&secret)?));
+        match auth {
+            Ok(auth) => {
+                // The mode saved in the config wins over the one in the secret
+                self.auth = Auth { mode: self.auth.mode, ..auth };
+                self.stored_auth = Some(self.auth.to_owned());
+                self.credentials_error = None;
             }
-            let elapsed = Channel::Elapsed(start.elapsed());
-            handle.send_async(elapsed).await;
-            let s = match str::from_utf8(&image_bytes) {
-                Ok(v) => v,
-                Err(e) => panic!("Invalid UTF-8 sequence: {}", e),
+            Err(e) => self.credentials_error = Some(format!("Unable to read credentials: {}", e)),
+        }
+    }
+
+    fn save_config(&mut self) {
+        let profile = &self.profiles[self.active_profile];
+        if self.stored_auth.as_ref() != Some(&self.auth) && !self.auth.is_empty() {
+            let passphrase = Some(self.master_passphrase.as_str());
+            let id = match &profile.credentials {
+                Some(reference) => reference.id.to_owned(),
+                None => profile.credential_id(),
             };
-            let mut first_ten_records = vec![];
-            let mut headers = vec![];
-            let mut reader = csv::Reader::from_reader(s.as_bytes());
-            match reader.headers() {
-                Err(_) => headers.push(String::from("Error")),
-                Ok(records) => {
-                    for header in records {
-                        headers.push(header.to_string())
+            match credentials::store(&id, &serde_json::to_string(&self.auth).unwrap(), passphrase, Path::new(credentials::ENCRYPTED_FILE_PATH)) {
+                Ok(reference) => {
+                    let profile = &mut self.profiles[self.active_profile];
+                    profile.credentials = Some(reference);
+                    if self.legacy_login.as_ref().map_or(false, |login| login.profile == profile.id) {
+                        self.legacy_login = None;
                     }
+                    self.stored_auth = Some(self.auth.to_owned());
+                    self.credentials_error = None;
                 }
+                Err(e) => self.credentials_error = Some(format!("Credentials were not saved: {}", e)),
             }
-            first_ten_records.push(headers);
-            for (i, row) in reader.records().enumerate() {
-                if i < 10 {
-                    let mut row_data = vec![];
-                    for cell in row.unwrap().iter() {
-                        row_data.push(cell.to_string())
+        }
+        let tab = self.workspace.focused();
+        let profile = &mut self.profiles[self.active_profile];
+        profile.domain = self.domain.to_owned();
+        profile.dataset = tab.dataset.to_owned();
+        profile.auth_mode = self.auth.mode;
+        let last_profile = profile.name.to_owned();
+        // Until the old login is in the credential store, it stays in the config
+        let legacy = self.legacy_login.to_owned();
+        set_config(Config {
+            username: legacy.as_ref().map(|login| login.username.to_owned()).unwrap_or_default(),
+            password: legacy.as_ref().map(|login| login.password.to_owned()).unwrap_or_default(),
+            legacy_profile: legacy.map(|login| login.profile).unwrap_or_default(),
+            query: tab.current_query.to_owned(),
+            paging: self.paging.to_owned(),
+            profiles: self.profiles.to_owned(),
+            last_profile,
+            ..Default::default()
+        });
+    }
+
+    /// Makes profile `index` the active one without saving the current one first
+    fn load_profile(&mut self, index: usize) {
+        self.active_profile = index;
+        let profile = self.profiles[index].to_owned();
+        self.domain = profile.domain;
+        self.workspace.focused().dataset = profile.dataset;
+        let login = self.legacy_login.as_ref().filter(|login| login.profile == profile.id);
+        self.auth = Auth {
+            mode: profile.auth_mode,
+            username: login.map(|login| login.username.to_owned()).unwrap_or_default(),
+            password: login.map(|login| login.password.to_owned()).unwrap_or_default(),
+            ..Default::default()
+        };
+        self.stored_auth = None;
+        self.credentials_error = None;
+        let locked = profile.credentials.map_or(true, |c| c.needs_passphrase() && self.master_passphrase.is_empty());
+        if !locked {
+            self.unlock_credentials();
+        }
+    }
+
+    fn handle_profile_action(&mut self, action: ProfileAction) {
+        match action {
+            ProfileAction::Switch(index) => {
+                self.save_config();
+                self.load_profile(index);
+            }
+            ProfileAction::Add => {
+                self.save_config();
+                let name = (1..)
+                    .map(|n| format!("Profile {}", n))
+                    .find(|name| self.profiles.iter().all(|p| &p.name != name))
+                    .unwrap();
+                self.profiles.push(Profile::new(&name));
+                self.load_profile(self.profiles.len() - 1);
+            }
+            ProfileAction::Rename(name) => {
+                let taken = self.profiles.iter().any(|p| p.name == name);
+                if !name.is_empty() && !taken {
+                    self.profiles[self.active_profile].name = name;
+                }
+            }
+            ProfileAction::Delete => {
+                if self.profiles.len() > 1 {
+                    let profile = self.profiles.remove(self.active_profile);
+                    // Profiles saved by older versions may share their secrets
+                    let shared = |reference: &CredentialRef| self.profiles.iter().any(|p| p.credentials.as_ref() == Some(reference));
+                    if let Some(reference) = profile.credentials.filter(|reference| !shared(reference)) {
+                        let _ = credentials::delete(&reference, Path::new(credentials::ENCRYPTED_FILE_PATH));
                     }
-                    first_ten_records.push(row_data)
-                } else {
-                    break;
+                    self.load_profile(self.active_profile.min(self.profiles.len() - 1));
                 }
             }
-            let t = Container::Data(first_ten_records);
-            Ok(t)
-        } else {
-            let err = response.text().await;
-            let t = format!("Expected  CSV; found {}: {:?}", content_type, err).into();
-            Err(t)
         }
+        self.save_config();
     }
 
-    
-
-    fn spawn_fetch_data(&mut self) {
-        // Save the new config
-        let new_config = Config {
-            username: self.username.to_owned(),
-            password: self.password.to_owned(),
+    fn save_query(&mut self, tab_id: usize) {
+        let tab = match self.workspace.tab_mut(tab_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+        let query = SavedQuery {
+            name: self.library.unique_name("Untitled query"),
             domain: self.domain.to_owned(),
-            dataset: self.dataset.to_owned(),
-            query: self.current_query.to_owned()
+            dataset: tab.dataset.to_owned(),
+            query: tab.current_query.to_owned(),
         };
-        set_config(new_config);
+        self.library_panel.start_rename(self.library.queries.len(), &query.name);
+        self.library.queries.push(query);
+        set_library(&self.library);
+        self.show_library = true;
+    }
 
-        self.url = make_query(self.domain.as_str(), self.dataset.as_str(), self.current_query.as_str());
-        println!("Making call to: {}", self.url);
-        // Set error to None
-        self.csv_data.error.take();
-        // Show query progress
-        self.csv_data.is_running = true;
-        // Get flower handle
-        let handle = self.flower.handle();
-        let url = self.url.to_owned();
-        let username = self.username.to_owned();
-        let password = self.password.to_owned();
-        // Spawn tokio runtime.
-        self.rt.spawn(async move {
-            // Don't forget to activate flower here
+    fn spawn_export(&mut self, tab_id: usize, path: PathBuf) {
+        let tab = match self.workspace.tab_mut(tab_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+        let data = match tab.csv_data.data.as_ref() {
+            Some(data) => Arc::clone(data),
+            None => return,
+        };
+        let types = tab.csv_data.column_types(self.schemas.get(self.domain.as_str(), &tab.dataset));
+        let format = self.export_format;
+        self.export_data.error.take();
+        self.export_data.message.take();
+        self.export_data.rows_written = 0;
+        self.export_data.is_running = true;
+        self.export_data.tab = Some(tab_id);
+        let handle = self.export_flower.handle();
+        // Writing files blocks, so keep it off the async workers
+        self.rt.spawn_blocking(move || {
             handle.activate();
-            // Start fetching
-            match Self::fetch_data(url, username, password, &handle).await {
-                Ok(container) => handle.success(container),
-                Err(e) => handle.error(ErrCause::Data(format!("{:?}", e))),
+            match export::write(&path, format, &data, &types, |rows| handle.send(ExportChannel::Rows(rows))) {
+                Ok(rows) => handle.success(ExportContainer::Rows(rows)),
+                Err(e) => handle.error(ExportErrCause::Data(format!("{:?}", e))),
             }
         });
     }
 
-    fn reset_fetch(&mut self) {
-        // Handle logical accordingly
-        self.csv_data.repair();
-        if self.get_data && self.flower.is_canceled() {
-            if self.csv_data.seed > 1 {
-                self.csv_data.seed -= 1;
+    fn start_query(&mut self, tab_id: usize) {
+        let tab = match self.workspace.tab_mut(tab_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+        if self.jobs.is_fetching(tab_id) {
+            return;
+        }
+        tab.check_query();
+        if !tab.run_invalid_query {
+            if let Some(e) = &tab.query_error {
+                tab.csv_data.set_error(format!("Not run: {}", e));
+                return;
             }
-            self.btn_label_next = "Retry?".into();
-        } else if !self.get_data && self.flower.is_canceled() {
-            self.csv_data.seed += 1;
-        } else {
-            self.btn_label_next = "Run Query".into();
         }
+        self.spawn_fetch_data(tab_id);
     }
 
-    fn spawn_analyze_query(&mut self) {
-        let new_config = Config {
-            username: self.username.to_owned(),
-            password: self.password.to_owned(),
-            domain: self.domain.to_owned(),
-            dataset: self.dataset.to_owned(),
-            query: self.current_query.to_owned()
+    fn spawn_analyze_query(&mut self, tab_id: usize) {
+        self.save_config();
+        self.show_analysis = true;
+        let client = self.client();
+        let tab = match self.workspace.tab_mut(tab_id) {
+            Some(tab) => tab,
+            None => return,
+        };
+        if let Err(e) = make_analyze_url(self.domain.as_str(), tab.dataset.as_str(), tab.current_query.as_str()) {
+            self.analysis_data.set_error(e);
+            return;
+        }
+        let client = match client {
+            Ok(client) => client,
+            Err(e) => {
+                self.analysis_data.set_error(e);
+                return;
+            }
         };
-        set_config(new_config);
-        self.url = make_analyze_url(self.domain.as_str(), self.dataset.as_str(), self.current_query.as_str());
-        println!("{}", self.url);
         // Set error to None
-        self.csv_data.error.take();
-        // Show query progress
-        self.csv_data.is_running = true;
+        self.analysis_data.error.take();
+        // Show analysis progress
+        self.analysis_data.is_running = true;
         // Get flower handle
-        let handle = self.analysis_flower.handle();
-        let url = self.url.to_owned();
-        let username = self.username.to_owned();
-        let password = self.password.to_owned();
+        let (handle, cancel) = self.jobs.start_analysis(tab);
+        let dataset = tab.dataset.to_owned();
+        let query = tab.current_query.to_owned();
         self.rt.spawn(async move {
             // Don't forget to activate flower here
             handle.activate();
             // Start fetching
-            match Self::fetch_analysis(url, username, password).await {
-                Ok(container) => handle.success(container),
-                Err(e) => handle.error(AnalysisErrCause::Data(format!("{:?}", e))),
+            match client.analyze(&dataset, &query, &cancel).await {
+                Ok(plan) => handle.success(AnalysisContainer::Data(plan)),
+                Err(ClientError::Canceled) => handle.error(AnalysisErrCause::Data("Analysis canceled".into())),
+                Err(e) => handle.error(AnalysisErrCause::Data(e.to_string())),
             }
         });
     }
 
-    async fn fetch_analysis(url: String, username: String, password: String) -> Result<AnalysisContainer, IOError> {
-        let client = reqwest::Client::new();
-        let response: Response = client
-            .get(url)
-            .basic_auth(username, Some(password))
-            .send()
-            .await?;
-        if response.status().is_success() {
-            Ok(AnalysisContainer::Data("Hello".into()))
-        } else {
-            Err("Bad Call".into())
+    fn spawn_fetch_schema(&mut self, dataset: &str) {
+        // Nothing to look up until the connection settings are complete
+        if make_schema_url(self.domain.as_str(), dataset).is_err() {
+            return;
         }
+        let client = match self.client() {
+            Ok(client) => client,
+            Err(_) => return,
+        };
+        self.schemas.start(self.domain.as_str(), dataset);
+        let handle = self.schema_flower.handle();
+        let dataset = dataset.to_owned();
+        self.rt.spawn(async move {
+            handle.activate();
+            match client.metadata(&dataset).await {
+                Ok(schema) => handle.success(schema),
+                Err(e) => handle.error(SchemaErrCause::Data(e.to_string())),
+            }
+        });
+    }
+
+    fn spawn_catalog_search(&mut self, search: CatalogSearch, offset: usize) {
+        let client = match self.client() {
+            Ok(client) => client,
+            Err(e) => {
+                self.catalog.error = Some(e.to_string());
+                return;
+            }
+        };
+        self.catalog.start(search.to_owned(), offset);
+        let handle = self.catalog_flower.handle();
+        self.rt.spawn(async move {
+            handle.activate();
+            match client.search_catalog(&search, offset).await {
+                Ok(page) => handle.success(page),
+                Err(e) => handle.error(CatalogErrCause::Data(e.to_string())),
+            }
+        });
     }
 }
 
 impl eframe::App for SoqlStudio {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, WORKSPACE_KEY, &self.workspace);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Labels
         let app_header = RichText::new("SoQL Studio").font(FontId::proportional(60.0)).color(egui::Color32::WHITE);
         let settings_header = RichText::new("Settings").font(FontId::proportional(40.0));
-        let username_label = RichText::new("Username: ").font(FontId::proportional(25.0));
-        let password_label = RichText::new("Password: ").font(FontId::proportional(25.0));
         let domain_label = RichText::new("Domain: ").font(FontId::proportional(25.0));
         let id_label = RichText::new("Dataset ID: ").font(FontId::proportional(25.0));
         
         egui::TopBottomPanel::new(egui::panel::TopBottomSide::Top, "header").show(ctx, |ui| {
-            ui.heading(app_header);
+            ui.horizontal(|ui| {
+                ui.heading(app_header);
+                if ui.button(RichText::new("New Tab").font(FontId::proportional(25.0))).clicked() {
+                    let dataset = self.workspace.focused().dataset.to_owned();
+                    self.workspace.open(&dataset, "");
+                }
+                if ui.selectable_label(self.show_library, RichText::new("Query Library").font(FontId::proportional(25.0))).clicked() {
+                    self.show_library = !self.show_library;
+                }
+                if ui.selectable_label(self.show_history, RichText::new("History").font(FontId::proportional(25.0))).clicked() {
+                    self.show_history = !self.show_history;
+                }
+                if ui.selectable_label(self.show_catalog, RichText::new("Find Dataset").font(FontId::proportional(25.0))).clicked() {
+                    self.show_catalog = !self.show_catalog;
+                }
+                if ui.selectable_label(self.show_schema, RichText::new("Schema").font(FontId::proportional(25.0))).clicked() {
+                    self.show_schema = !self.show_schema;
+                }
+                let jobs_label = match self.jobs.running() {
+                    0 => "Jobs".to_owned(),
+                    running => format!("Jobs ({})", running),
+                };
+                if ui.selectable_label(self.show_jobs, RichText::new(jobs_label).font(FontId::proportional(25.0))).clicked() {
+                    self.show_jobs = !self.show_jobs;
+                }
+            });
         });
 
         egui::SidePanel::new(egui::panel::Side::Left, "id_source").show(ctx, |ui| {
             ui.set_width(400.0);
             ui.heading(settings_header);
-            ui.label(username_label);
-            ui.add(
-                egui::TextEdit::singleline(&mut self.username)
-                    .font(FontId::proportional(25.0))
-                    .desired_width(375.0)
-                
-            );
-            ui.label(password_label);
-            ui.add(
-                egui::TextEdit::singleline(&mut self.password)
-                    .font(FontId::proportional(25.0))
-                    .password(true).desired_width(375.0)
-            );
+            if let Some(action) = self.profiles_panel.show(ui, &self.profiles, self.active_profile) {
+                self.handle_profile_action(action);
+            }
+            panels::auth::show(ui, &mut self.auth);
+            ui.label("Master passphrase (used when no OS keyring is available):");
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.master_passphrase)
+                        .password(true)
+                        .desired_width(280.0)
+                );
+                let locked = self.stored_auth.is_none()
+                    && self.credentials().map_or(false, |c| c.needs_passphrase());
+                if locked && ui.button("Unlock").clicked() {
+                    self.unlock_credentials();
+                }
+            });
+            if let Some(err) = &self.credentials_error {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
             ui.label(domain_label);
             ui.add(
                 egui::TextEdit::singleline(&mut self.domain)
@@ -293,209 +619,207 @@ impl eframe::App for SoqlStudio {
             );
             ui.label(id_label);
             ui.add(
-                egui::TextEdit::singleline(&mut self.dataset)
+                egui::TextEdit::singleline(&mut self.workspace.focused().dataset)
                     .font(FontId::proportional(25.0))
                     .desired_width(375.0)
             );
+            ui.checkbox(
+                &mut self.paging.enabled,
+                RichText::new("Fetch all pages").font(FontId::proportional(25.0)),
+            );
+            ui.add_enabled_ui(self.paging.enabled, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Page size:");
+                    ui.add(egui::DragValue::new(&mut self.paging.page_size).clamp_range(1..=1_000_000).speed(1000));
+                    ui.label("Concurrent requests:");
+                    ui.add(egui::DragValue::new(&mut self.paging.concurrency).clamp_range(1..=16));
+                });
+            });
 
         });
+
+        if self.show_library {
+            egui::SidePanel::new(egui::panel::Side::Right, "library").show(ctx, |ui| {
+                ui.set_width(400.0);
+                match self.library_panel.show(ui, &mut self.library) {
+                    Some(LibraryAction::Load(query)) => {
+                        self.domain = query.domain;
+                        self.workspace.open(&query.dataset, &query.query);
+                    }
+                    Some(LibraryAction::Close) => self.show_library = false,
+                    None => {}
+                }
+            });
+        }
+
+        if self.show_history {
+            egui::SidePanel::new(egui::panel::Side::Right, "history").show(ctx, |ui| {
+                ui.set_width(400.0);
+                match self.history_panel.show(ui, &self.history) {
+                    Some(HistoryAction::Rerun(entry)) => {
+                        self.domain = entry.domain;
+                        let tab = self.workspace.focused();
+                        tab.dataset = entry.dataset;
+                        tab.current_query = entry.query;
+                        let tab_id = tab.id;
+                        self.start_query(tab_id);
+                    }
+                    Some(HistoryAction::Close) => self.show_history = false,
+                    None => {}
+                }
+            });
+        }
+
+        if self.show_catalog {
+            egui::SidePanel::new(egui::panel::Side::Right, "catalog").show(ctx, |ui| {
+                ui.set_width(400.0);
+                match self.catalog_panel.show(ui, &self.catalog, self.workspace.focused().dataset.as_str()) {
+                    Some(CatalogAction::Search(search)) => self.spawn_catalog_search(search, 0),
+                    Some(CatalogAction::More) => {
+                        let search = self.catalog.search.to_owned();
+                        let offset = self.catalog.datasets.len();
+                        self.spawn_catalog_search(search, offset);
+                    }
+                    Some(CatalogAction::Pick(id)) => self.workspace.focused().dataset = id,
+                    Some(CatalogAction::Close) => self.show_catalog = false,
+                    None => {}
+                }
+            });
+        }
+
+        if self.show_schema {
+            let dataset = self.workspace.focused().dataset.to_owned();
+            if self.schemas.needs_fetch(self.domain.as_str(), dataset.as_str()) {
+                self.spawn_fetch_schema(&dataset);
+            }
+            egui::SidePanel::new(egui::panel::Side::Right, "schema").show(ctx, |ui| {
+                ui.set_width(400.0);
+                let (domain, dataset) = (self.domain.as_str(), dataset.as_str());
+                let action = self.schema_panel.show(
+                    ui,
+                    self.schemas.get(domain, dataset),
+                    self.schemas.error(domain, dataset),
+                    self.schemas.is_pending(domain, dataset),
+                );
+                match action {
+                    Some(SchemaAction::Insert(field_name)) => {
+                        let tab = self.workspace.focused();
+                        panels::schema::insert_at_cursor(ctx, tab.editor_id(), &mut tab.current_query, &field_name);
+                    }
+                    // Fetched again on the next frame
+                    Some(SchemaAction::Refresh) => self.schemas.forget(domain, dataset),
+                    Some(SchemaAction::Close) => self.show_schema = false,
+                    None => {}
+                }
+            });
+        }
+
+        if self.show_jobs {
+            egui::SidePanel::new(egui::panel::Side::Right, "jobs").show(ctx, |ui| {
+                ui.set_width(400.0);
+                match panels::jobs::show(ui, &self.jobs) {
+                    Some(JobsAction::Cancel(id)) => self.jobs.cancel(id),
+                    Some(JobsAction::ShowTab(tab_id)) => self.workspace.show_tab(tab_id),
+                    Some(JobsAction::ClearFinished) => self.jobs.clear_finished(),
+                    Some(JobsAction::Close) => self.show_jobs = false,
+                    None => {}
+                }
+                // Keep the elapsed times ticking
+                if self.jobs.running() > 0 {
+                    ctx.request_repaint();
+                }
+            });
+        }
+
+        if self.show_analysis {
+            egui::SidePanel::new(egui::panel::Side::Right, "analysis").show(ctx, |ui| {
+                ui.set_width(500.0);
+                self.show_analysis = panels::analysis::show(ui, &self.analysis_data);
+            });
+        }
         
+        for entry in self.jobs.poll(&mut self.workspace, &mut self.analysis_data) {
+            self.history.record(entry);
+        }
         egui::CentralPanel::default().show(ctx, |ui| {
-            let mut layouter = |ui: &egui::Ui, string: &str, wrap_width: f32| {
-                let mut layout_job =
-                    syntaxhighlight::highlight(ui.ctx(), string, "sql");
-                layout_job.wrap.max_width = wrap_width;
-                ui.fonts().layout_job(layout_job)
-            };
-            if self.flower.is_active() {
-                let mut fetch_data_finalized = false;
-                self.flower
-                    .extract(|message| {
-                        match message {
-                            Channel::Data(b) => {
-                                self.csv_data.tmp_file_size += b;
-                            }
-                            Channel::Elapsed(e) => {
-                                self.query_duration = e
-                            }
-                        }
-                    })
+            if self.schema_flower.is_active() {
+                let schemas = &mut self.schemas;
+                self.schema_flower
+                    .extract(|()| {})
                     .finalize(|result| {
                         match result {
-                            Ok(Container::Elapsed(_)) => {}
-                            Ok(Container::Data(data)) => {
-                                self.csv_data.set_data(data);
-                                fetch_data_finalized = true;
-                            }
-                            Err(Compact::Suppose(err)) => {
-                                // Get specific error message.
-                                match err {
-                                    ErrCause::Elapsed(_e) => {
-                                        self.query_duration = Duration::new(0, 0);
-                                    }
-                                    ErrCause::Data(e) => {
-                                        self.csv_data.set_error(e);
-                                    }
-                                }
-                                fetch_data_finalized = true;
-                            }
-                            // Handle stuff if tokio runtime panicked as well,
-                            // but don't do that and stay calm is highly encouraged.
-                            Err(Compact::Panicked(err)) => {
-                                self.csv_data.set_error(err);
-                                fetch_data_finalized = true;
-                            }
+                            Ok(schema) => schemas.finish(Ok(schema)),
+                            Err(Compact::Suppose(SchemaErrCause::Data(e))) => schemas.finish(Err(e)),
+                            Err(Compact::Panicked(err)) => schemas.finish(Err(err)),
                         }
                     });
+            }
 
-                if fetch_data_finalized {
-                    self.reset_fetch();
-                }
+            if self.catalog_flower.is_active() {
+                let catalog = &mut self.catalog;
+                self.catalog_flower
+                    .extract(|()| {})
+                    .finalize(|result| {
+                        match result {
+                            Ok(page) => catalog.finish(Ok(page)),
+                            Err(Compact::Suppose(CatalogErrCause::Data(e))) => catalog.finish(Err(e)),
+                            Err(Compact::Panicked(err)) => catalog.finish(Err(err)),
+                        }
+                    });
             }
-            if self.analysis_flower.is_active() {
-                let mut fetch_analysis_finalized = false;
-                self.analysis_flower
+
+            if self.export_flower.is_active() {
+                let export_data = &mut self.export_data;
+                self.export_flower
                     .extract(|message| {
                         match message {
-                            // Not using this for anything yet
-                            AnalysisChannel::Data(_) => {}
+                            ExportChannel::Rows(rows) => {
+                                export_data.rows_written = rows;
+                            }
                         }
                     })
                     .finalize(|result| {
                         match result {
-                            Ok(AnalysisContainer::Data(data)) => {
-                                self.analysis_data.set_data(data);
-                                fetch_analysis_finalized = true;
+                            Ok(ExportContainer::Rows(rows)) => {
+                                export_data.set_done(format!("Exported {} rows", rows));
                             }
-                            Err(Compact::Suppose(err)) => {
-                                // Get specific error message.
-                                match err {
-                                    AnalysisErrCause::Data(e) => {
-                                        self.analysis_data.set_error(e);
-                                    }
-                                }
-                                fetch_analysis_finalized = true;
+                            Err(Compact::Suppose(ExportErrCause::Data(e))) => {
+                                export_data.set_error(e);
                             }
-                            // Handle stuff if tokio runtime panicked as well,
-                            // but don't do that and stay calm is highly encouraged.
                             Err(Compact::Panicked(err)) => {
-                                self.analysis_data.set_error(err);
-                                fetch_analysis_finalized = true;
+                                export_data.set_error(err);
                             }
                         }
                     });
-
-                if fetch_analysis_finalized {
-                    self.reset_fetch();
-                }
             }
 
-            ui.horizontal(|query_box| {
-                query_box.set_height(600.0);
-                egui::ScrollArea::vertical().max_height(900.0).show(query_box, |query_box| {
-                    query_box.add(
-                        egui::TextEdit::multiline(&mut self.current_query)
-                            // .font(egui::TextStyle::Monospace) // for cursor height
-                            .font(egui::TextStyle::Heading)
-                            .code_editor()
-                            .desired_rows(80)
-                            .lock_focus(true)
-                            .desired_width(f32::INFINITY)
-                            .layouter(&mut layouter),
-                    );
-                });
-            });
-            // Action Buttons
-            ui.horizontal(|action_buttons| {
-                if action_buttons.button(egui::RichText::new(&self.btn_label_next).font(egui::FontId::proportional(30.0))).clicked() {
-                    if self.flower.is_active() {
-                        if !self.get_data {
-                            self.btn_label_next = "Wait we are still fetching...".into();
-                        } else {
-                            self.flower.cancel();
-                        }
-                    } else {
-                        // Refetch next image
-                        self.csv_data.seed += 1;
-                        self.spawn_fetch_data();
-                        self.get_data = true;
-                        self.btn_label_next = "Cancel?".into();
-                    }
-                }
-                if action_buttons.button(egui::RichText::new("Save Query").font(egui::FontId::proportional(30.0))).clicked() {
-                    //TODO: Save the query to a file
-                }
-                if action_buttons.button(egui::RichText::new("Run Query Analysis").font(egui::FontId::proportional(30.0))).clicked() {
-                    if self.flower.is_active() {
-                        if !self.get_data {
-                            self.btn_label_next = "Wait we are still fetching...".into();
-                        } else {
-                            self.flower.cancel();
+            // Makes sure there is a tab to show when the last one was closed
+            self.workspace.focused();
+            let mut tabs = QueryTabs {
+                domain: self.domain.as_str(),
+                schemas: &self.schemas,
+                jobs: &self.jobs,
+                resume_download: &mut self.resume_download,
+                export_format: &mut self.export_format,
+                export_data: &self.export_data,
+                actions: vec![],
+            };
+            DockArea::new(&mut self.workspace.tree)
+                .style(egui_dock::Style::from_egui(ui.style().as_ref()))
+                .show_inside(ui, &mut tabs);
+            for (tab_id, action) in tabs.actions {
+                match action {
+                    TabAction::Run => self.start_query(tab_id),
+                    TabAction::Download(path) => self.spawn_download(tab_id, path),
+                    TabAction::Analyze => self.spawn_analyze_query(tab_id),
+                    TabAction::Save => self.save_query(tab_id),
+                    TabAction::Export(path) => self.spawn_export(tab_id, path),
+                    TabAction::FetchSchema => {
+                        if let Some(dataset) = self.workspace.tab_mut(tab_id).map(|tab| tab.dataset.to_owned()) {
+                            self.spawn_fetch_schema(&dataset);
                         }
-                    } else {
-                        // Refetch next image
-                        self.csv_data.seed += 1;
-                        self.spawn_analyze_query();
-                        self.get_data = true;
-                        self.btn_label_next = "Cancel?".into();
                     }
                 }
-            });
-            // The query is being executed
-            if self.csv_data.is_running {
-                ui.horizontal(|ui| {
-                    // We don't need to call repaint since we are using spinner here.
-                    ui.spinner();
-                    let mut downloaded_size = self.csv_data.tmp_file_size;
-                    if downloaded_size > 0 {
-                        // Convert current file size in Bytes to KB.
-                        downloaded_size /= 1000;
-                        // Show downloaded file size.
-                        ui.label(format!("Downloaded size: {} KB", downloaded_size));
-                    }
-                });
-            }
-
-            if let Some(err) = &self.csv_data.error {
-                ui.colored_label(ui.visuals().error_fg_color, egui::RichText::new(err).font(egui::FontId::proportional(40.0)));
-            }
-
-            if let Some(csv_data) = &self.csv_data.data {
-                let text_edit = egui::TextEdit::singleline(&mut self.url)
-                    .desired_width(f32::INFINITY)
-                    .font(FontId::proportional(20.0));
-                ui.add(text_edit);
-                // Query Results Table
-                ui.label(egui::RichText::new("Results").font(egui::FontId::proportional(30.0)));
-                egui::ScrollArea::both()
-                    .auto_shrink([true, true])
-                    .show(ui, |ui| {
-                        egui::Grid::new("my_grid").striped(true).show(ui, |grid| {
-                            for (i, row) in csv_data.iter().enumerate() {
-                                if i == 0 {
-                                    for cell in row.iter() {
-                                        grid.label(egui::RichText::new(cell).font(egui::FontId::proportional(30.0)));
-                                    }
-                                } else {
-                                    for cell in row.iter() {
-                                        grid.label(egui::RichText::new(cell).font(egui::FontId::proportional(20.0)));
-                                    }
-                                }
-                                grid.end_row();
-                            }
-                        })
-                    });
-                // Query Stats
-                ui.label(egui::RichText::new("Statistics").font(egui::FontId::proportional(30.0)));
-                let file_size = self.csv_data.file_size;
-                ui.label(egui::RichText::new(
-                    format!("Current file size: {} KB", file_size)
-                ).font(egui::FontId::proportional(20.0)));
-                // Query Elapsed Text
-                ui.label(egui::RichText::new(format!(
-                    "Query Elapsed: {:#?}",
-                    self.query_duration
-                )).font(egui::FontId::proportional(20.0)));
             }
         });
 
@@ -0,0 +1,73 @@
+use egui::{CollapsingHeader, FontId, RichText};
+
+use crate::socrata::analysis::{AnalysisResponseData, PlanNode};
+
+/// Renders the explain plan as a collapsible tree.
+/// Returns `false` once the user closes the panel.
+pub fn show(ui: &mut egui::Ui, analysis: &AnalysisResponseData) -> bool {
+    let mut open = true;
+    ui.horizontal(|ui| {
+        ui.heading(RichText::new("Query Analysis").font(FontId::proportional(40.0)));
+        if ui.button("Close").clicked() {
+            open = false;
+        }
+    });
+
+    if analysis.is_running {
+        ui.spinner();
+    }
+
+    if let Some(err) = &analysis.error {
+        ui.colored_label(ui.visuals().error_fg_color, RichText::new(err).font(FontId::proportional(20.0)));
+    }
+
+    if let Some(plan) = &analysis.data {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            match &plan.root {
+                Some(root) => show_node(ui, root, "plan"),
+                None => {
+                    ui.label("No plan was returned for this query.");
+                }
+            }
+            for line in plan.summary.iter() {
+                ui.label(RichText::new(line).font(FontId::proportional(18.0)));
+            }
+            CollapsingHeader::new("Raw plan")
+                .id_source("raw_plan")
+                .show(ui, |ui| {
+                    ui.monospace(&plan.raw);
+                });
+        });
+    }
+    open
+}
+
+fn show_node(ui: &mut egui::Ui, node: &PlanNode, path: &str) {
+    let header = match &node.estimate {
+        Some(e) => format!("{}  (rows: {}, cost: {:.2})", node.operation, e.rows, e.total_cost),
+        None => node.operation.to_owned(),
+    };
+    CollapsingHeader::new(RichText::new(header).font(FontId::proportional(18.0)))
+        .id_source(path)
+        .default_open(true)
+        .show(ui, |ui| {
+            if let Some(e) = &node.estimate {
+                ui.label(format!(
+                    "Estimated cost: {:.2}..{:.2}, rows: {}, width: {}",
+                    e.startup_cost, e.total_cost, e.rows, e.width
+                ));
+            }
+            if let Some(a) = &node.actual {
+                ui.label(format!(
+                    "Actual time: {:.3}..{:.3} ms, rows: {}, loops: {}",
+                    a.startup_time, a.total_time, a.rows, a.loops
+                ));
+            }
+            for detail in node.details.iter() {
+                ui.monospace(detail);
+            }
+            for (i, child) in node.children.iter().enumerate() {
+                show_node(ui, child, &format!("{}/{}", path, i));
+            }
+        });
+}
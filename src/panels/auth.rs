@@ -0,0 +1,41 @@
+use egui::{FontId, RichText};
+
+use crate::socrata::auth::{Auth, AuthMode};
+
+fn field(ui: &mut egui::Ui, label: &str, value: &mut String, password: bool) {
+    ui.label(RichText::new(label).font(FontId::proportional(25.0)));
+    ui.add(
+        egui::TextEdit::singleline(value)
+            .font(FontId::proportional(25.0))
+            .password(password)
+            .desired_width(375.0)
+    );
+}
+
+/// Authentication mode picker with the fields that mode needs
+pub fn show(ui: &mut egui::Ui, auth: &mut Auth) {
+    ui.label(RichText::new("Authentication: ").font(FontId::proportional(25.0)));
+    egui::ComboBox::from_id_source("auth_mode")
+        .selected_text(auth.mode.label())
+        .width(375.0)
+        .show_ui(ui, |ui| {
+            for mode in AuthMode::ALL {
+                ui.selectable_value(&mut auth.mode, mode, mode.label());
+            }
+        });
+    match auth.mode {
+        AuthMode::Anonymous => {}
+        AuthMode::Basic => {
+            field(ui, "Username: ", &mut auth.username, false);
+            field(ui, "Password: ", &mut auth.password, true);
+        }
+        AuthMode::ApiKey => {
+            field(ui, "API Key ID: ", &mut auth.key_id, false);
+            field(ui, "API Key Secret: ", &mut auth.key_secret, true);
+        }
+        AuthMode::Bearer => {
+            field(ui, "Access Token: ", &mut auth.token, true);
+        }
+    }
+    field(ui, "App Token (optional): ", &mut auth.app_token, false);
+}
@@ -0,0 +1,94 @@
+use egui::{FontId, Key, RichText};
+
+use crate::socrata::catalog::{CatalogResults, CatalogSearch};
+
+pub enum CatalogAction {
+    Search(CatalogSearch),
+    /// Fetch the next page of the current search
+    More,
+    /// Dataset ID to query
+    Pick(String),
+    Close,
+}
+
+/// Side panel searching the domain's catalog for datasets
+#[derive(Default)]
+pub struct CatalogPanel {
+    search: CatalogSearch,
+}
+
+impl CatalogPanel {
+    pub fn show(&mut self, ui: &mut egui::Ui, results: &CatalogResults, current: &str) -> Option<CatalogAction> {
+        let mut action = None;
+        ui.horizontal(|ui| {
+            ui.heading(RichText::new("Datasets").font(FontId::proportional(40.0)));
+            if ui.button("Close").clicked() {
+                action = Some(CatalogAction::Close);
+            }
+        });
+
+        let mut submitted = false;
+        egui::Grid::new("catalog_search").num_columns(2).show(ui, |ui| {
+            for (label, value) in [
+                ("Keywords:", &mut self.search.keywords),
+                ("Category:", &mut self.search.category),
+                ("Tag:", &mut self.search.tag),
+            ] {
+                ui.label(label);
+                let response = ui.text_edit_singleline(value);
+                submitted |= response.lost_focus() && ui.input().key_pressed(Key::Enter);
+                ui.end_row();
+            }
+        });
+        ui.horizontal(|ui| {
+            submitted |= ui.add_enabled(!results.loading, egui::Button::new("Search")).clicked();
+            if results.loading {
+                ui.spinner();
+            }
+        });
+        if submitted && !results.loading {
+            action = Some(CatalogAction::Search(self.search.to_owned()));
+        }
+        if let Some(err) = &results.error {
+            ui.colored_label(ui.visuals().error_fg_color, err);
+        }
+        ui.separator();
+
+        if !results.datasets.is_empty() {
+            ui.label(format!("Showing {} of {} datasets", results.datasets.len(), results.total));
+        }
+        egui::ScrollArea::vertical().id_source("catalog_results").show(ui, |ui| {
+            for dataset in &results.datasets {
+                ui.horizontal(|ui| {
+                    let selected = dataset.id == current.trim();
+                    if ui.selectable_label(selected, RichText::new(&dataset.name).strong()).clicked() {
+                        action = Some(CatalogAction::Pick(dataset.id.to_owned()));
+                    }
+                    ui.label(RichText::new(&dataset.id).monospace());
+                });
+                let mut details = vec![];
+                if let Some(category) = &dataset.category {
+                    details.push(category.to_owned());
+                }
+                if !dataset.tags.is_empty() {
+                    details.push(dataset.tags.join(", "));
+                }
+                if let Some(date) = dataset.updated_at.get(..10) {
+                    details.push(format!("updated {}", date));
+                }
+                if !details.is_empty() {
+                    ui.label(RichText::new(details.join(" · ")).small());
+                }
+                if !dataset.description.trim().is_empty() {
+                    let description: String = dataset.description.trim().chars().take(200).collect();
+                    ui.label(RichText::new(description).weak()).on_hover_text(dataset.description.trim());
+                }
+                ui.separator();
+            }
+            if results.has_more() && ui.add_enabled(!results.loading, egui::Button::new("More results")).clicked() {
+                action = Some(CatalogAction::More);
+            }
+        });
+        action
+    }
+}
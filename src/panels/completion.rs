@@ -0,0 +1,162 @@
+use egui::text::{CCursor, CCursorRange};
+use egui::{Event, FontId, Key, Modifiers, Rect, RichText};
+
+use crate::socrata::schema::SchemaColumn;
+use crate::socrata::soql::complete::{complete, CompletionKind};
+
+pub enum PopupKey {
+    Up,
+    Down,
+    Accept,
+    Close,
+}
+
+/// Completion list shown under the cursor of the query editor
+#[derive(Default)]
+pub struct CompletionPopup {
+    open: bool,
+    selected: usize,
+    /// How many items were listed last frame
+    shown: usize,
+    /// Where the list was drawn last frame, so clicking it doesn't count as leaving the editor
+    rect: Option<Rect>,
+    last_text: Option<String>,
+    last_cursor: Option<usize>,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == ':'
+}
+
+impl CompletionPopup {
+    /// Takes the keys the list reacts to before the editor sees them.
+    /// Ctrl+Space opens the list even when no word has been typed yet.
+    pub fn take_keys(&mut self, ui: &egui::Ui) -> Option<PopupKey> {
+        let mut input = ui.input_mut();
+        if input.consume_key(Modifiers::CTRL, Key::Space) {
+            input.events.retain(|e| !matches!(e, Event::Text(t) if t == " "));
+            self.open = true;
+            return None;
+        }
+        if !self.open || self.shown == 0 {
+            return None;
+        }
+        let keys = [
+            (Key::ArrowUp, PopupKey::Up),
+            (Key::ArrowDown, PopupKey::Down),
+            (Key::Enter, PopupKey::Accept),
+            (Key::Tab, PopupKey::Accept),
+            (Key::Escape, PopupKey::Close),
+        ];
+        keys.into_iter().find(|(key, _)| input.consume_key(Modifiers::NONE, *key)).map(|(_, action)| action)
+    }
+
+    /// Lists completions for the word at the cursor and inserts the one picked
+    pub fn show(
+        &mut self,
+        ui: &egui::Ui,
+        output: &egui::text_edit::TextEditOutput,
+        text: &mut String,
+        columns: &[SchemaColumn],
+        key: Option<PopupKey>,
+    ) {
+        let over_list = match (self.rect, ui.input().pointer.hover_pos()) {
+            (Some(rect), Some(pos)) => rect.contains(pos),
+            _ => false,
+        };
+        let cursor = output.cursor_range.filter(|r| r.primary.ccursor == r.secondary.ccursor).map(|r| r.primary);
+        let cursor = match cursor {
+            Some(cursor) if output.response.has_focus() || over_list => cursor,
+            _ => {
+                self.close();
+                self.last_text = None;
+                return;
+            }
+        };
+        let byte = text.char_indices().nth(cursor.ccursor.index).map_or(text.len(), |(i, _)| i);
+
+        if self.last_text.as_ref() != Some(text) {
+            // Typing a word opens the list, anything else closes it
+            self.open = text[..byte].chars().next_back().map_or(false, is_word_char);
+            self.selected = 0;
+        } else if self.last_cursor != Some(byte) && !over_list {
+            self.open = false;
+        }
+        self.last_text = Some(text.to_owned());
+        self.last_cursor = Some(byte);
+        if !self.open {
+            self.close();
+            return;
+        }
+
+        let completions = complete(text, byte, columns);
+        if completions.items.is_empty() {
+            self.close();
+            return;
+        }
+        let count = completions.items.len();
+        self.selected = self.selected.min(count - 1);
+        let mut accept = None;
+        match key {
+            Some(PopupKey::Up) => self.selected = (self.selected + count - 1) % count,
+            Some(PopupKey::Down) => self.selected = (self.selected + 1) % count,
+            Some(PopupKey::Accept) => accept = Some(self.selected),
+            Some(PopupKey::Close) => {
+                self.close();
+                return;
+            }
+            None => {}
+        }
+
+        let at = output.galley.pos_from_cursor(&cursor).translate(output.text_draw_pos.to_vec2());
+        let area = egui::Area::new("soql_completions")
+            .order(egui::Order::Foreground)
+            .fixed_pos(at.left_bottom())
+            .show(ui.ctx(), |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                        for (i, item) in completions.items.iter().enumerate() {
+                            let icon = match item.kind {
+                                CompletionKind::Column => "▣",
+                                CompletionKind::Function => "ƒ",
+                                CompletionKind::Keyword => "◆",
+                            };
+                            ui.horizontal(|ui| {
+                                let label = RichText::new(format!("{} {}", icon, item.label)).font(FontId::monospace(18.0));
+                                let response = ui.selectable_label(i == self.selected, label);
+                                if i == self.selected && key.is_some() {
+                                    response.scroll_to_me(None);
+                                }
+                                if response.clicked() {
+                                    accept = Some(i);
+                                }
+                                ui.label(RichText::new(&item.detail).weak());
+                            });
+                        }
+                    });
+                });
+            });
+        self.rect = Some(area.response.rect);
+        self.shown = count;
+
+        if let Some(i) = accept {
+            let item = &completions.items[i];
+            text.replace_range(completions.range.clone(), &item.insert);
+            let end = text[..completions.range.start + item.insert.len()].chars().count();
+            if let Some(mut state) = egui::TextEdit::load_state(ui.ctx(), output.response.id) {
+                state.set_ccursor_range(Some(CCursorRange::one(CCursor::new(end))));
+                egui::TextEdit::store_state(ui.ctx(), output.response.id, state);
+            }
+            ui.ctx().memory().request_focus(output.response.id);
+            self.close();
+            self.last_text = Some(text.to_owned());
+            self.last_cursor = Some(completions.range.start + item.insert.len());
+        }
+    }
+
+    fn close(&mut self) {
+        self.open = false;
+        self.shown = 0;
+        self.rect = None;
+    }
+}
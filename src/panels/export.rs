@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+
+use egui::{FontId, RichText};
+
+use crate::export::{ExportFormat, ExportResponseData};
+
+/// Format picker and "Export" button for the results of tab `tab_id`.
+/// Only one export runs at a time, and its status shows on the tab that
+/// started it. Returns the file chosen by the user once they confirm the
+/// save dialog.
+pub fn show(ui: &mut egui::Ui, tab_id: usize, format: &mut ExportFormat, export: &ExportResponseData) -> Option<PathBuf> {
+    let mut path = None;
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source("export_format")
+            .selected_text(format.label())
+            .show_ui(ui, |ui| {
+                for f in ExportFormat::ALL {
+                    ui.selectable_value(format, f, f.label());
+                }
+            });
+        let button = egui::Button::new(RichText::new("Export…").font(FontId::proportional(20.0)));
+        if ui.add_enabled(!export.is_running, button).clicked() {
+            path = rfd::FileDialog::new()
+                .add_filter(format.label(), &[format.extension()])
+                .set_file_name(format!("results.{}", format.extension()))
+                .save_file();
+        }
+        if export.tab != Some(tab_id) {
+            if export.is_running {
+                ui.label("Another tab is being exported");
+            }
+        } else if export.is_running {
+            ui.spinner();
+            ui.label(format!("Exported {} rows", export.rows_written));
+        } else if let Some(err) = &export.error {
+            ui.colored_label(ui.visuals().error_fg_color, err);
+        } else if let Some(message) = &export.message {
+            ui.label(message);
+        }
+    });
+    path
+}
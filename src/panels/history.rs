@@ -0,0 +1,85 @@
+use egui::{Color32, FontId, RichText};
+
+use crate::history::{diff_lines, DiffLine, History, HistoryEntry};
+
+pub enum HistoryAction {
+    Rerun(HistoryEntry),
+    Close,
+}
+
+/// Side panel listing executed queries, newest first
+#[derive(Default)]
+pub struct HistoryPanel {
+    /// Ids of the entries picked as the old and new side of the diff
+    diff: [Option<usize>; 2],
+}
+
+impl HistoryPanel {
+    pub fn show(&mut self, ui: &mut egui::Ui, history: &History) -> Option<HistoryAction> {
+        let mut action = None;
+        ui.horizontal(|ui| {
+            ui.heading(RichText::new("History").font(FontId::proportional(40.0)));
+            if ui.button("Close").clicked() {
+                action = Some(HistoryAction::Close);
+            }
+        });
+        ui.label("Pick an entry as A and another as B to compare them.");
+
+        if let [Some(a), Some(b)] = self.diff {
+            if let (Some(old), Some(new)) = (history.get(a), history.get(b)) {
+                egui::CollapsingHeader::new("Diff A → B")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        egui::ScrollArea::vertical()
+                            .id_source("history_diff")
+                            .max_height(300.0)
+                            .show(ui, |ui| {
+                                for line in diff_lines(&old.query, &new.query) {
+                                    let (text, color) = match line {
+                                        DiffLine::Same(l) => (format!("  {}", l), ui.visuals().text_color()),
+                                        DiffLine::Removed(l) => (format!("- {}", l), Color32::from_rgb(220, 80, 80)),
+                                        DiffLine::Added(l) => (format!("+ {}", l), Color32::from_rgb(80, 180, 80)),
+                                    };
+                                    ui.label(RichText::new(text).monospace().color(color));
+                                }
+                            });
+                    });
+            }
+        }
+        ui.separator();
+
+        egui::ScrollArea::vertical().id_source("history_entries").show(ui, |ui| {
+            for (i, entry) in history.entries.iter().enumerate().rev() {
+                ui.label(RichText::new(entry.started_at()).strong());
+                ui.label(RichText::new(format!("{} / {}", entry.domain, entry.dataset)).small());
+                ui.label(RichText::new(entry.query.trim()).monospace());
+                match &entry.error {
+                    Some(err) => {
+                        ui.colored_label(ui.visuals().error_fg_color, err);
+                    }
+                    None => {
+                        ui.label(format!(
+                            "{} rows in {:.2?}",
+                            entry.rows.unwrap_or_default(),
+                            entry.duration
+                        ));
+                    }
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Re-run").clicked() {
+                        action = Some(HistoryAction::Rerun(entry.to_owned()));
+                    }
+                    let id = history.id(i);
+                    for (side, label) in ["A", "B"].iter().enumerate() {
+                        let selected = self.diff[side] == Some(id);
+                        if ui.selectable_label(selected, *label).clicked() {
+                            self.diff[side] = if selected { None } else { Some(id) };
+                        }
+                    }
+                });
+                ui.separator();
+            }
+        });
+        action
+    }
+}
@@ -0,0 +1,81 @@
+use egui::{FontId, RichText};
+
+use crate::jobs::{JobKind, JobState, Jobs};
+
+pub enum JobsAction {
+    Cancel(usize),
+    /// Bring the tab that started a job to the front
+    ShowTab(usize),
+    ClearFinished,
+    Close,
+}
+
+/// Side panel listing running and finished jobs, newest first
+pub fn show(ui: &mut egui::Ui, jobs: &Jobs) -> Option<JobsAction> {
+    let mut action = None;
+    ui.horizontal(|ui| {
+        ui.heading(RichText::new("Jobs").font(FontId::proportional(40.0)));
+        if ui.button("Close").clicked() {
+            action = Some(JobsAction::Close);
+        }
+    });
+    let running = jobs.running();
+    ui.horizontal(|ui| {
+        ui.label(format!("{} running", running));
+        if ui.add_enabled(jobs.jobs.len() > running, egui::Button::new("Clear finished")).clicked() {
+            action = Some(JobsAction::ClearFinished);
+        }
+    });
+    ui.separator();
+
+    egui::ScrollArea::vertical().id_source("jobs").show(ui, |ui| {
+        for job in jobs.jobs.iter().rev() {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(job.kind.label()).strong());
+                if ui.link(&job.title).on_hover_text("Show the tab").clicked() {
+                    action = Some(JobsAction::ShowTab(job.tab_id));
+                }
+            });
+            if let JobKind::Download(path) = &job.kind {
+                ui.label(RichText::new(path.display().to_string()).small());
+            }
+            let mut details = vec![format!("{:.1?}", job.elapsed())];
+            if job.progress.bytes > 0 {
+                details.push(format!("{} KB", job.progress.bytes / 1000));
+            }
+            if job.progress.rows > 0 {
+                details.push(format!("{} rows", job.progress.rows));
+            }
+            if job.progress.pages > 0 {
+                details.push(format!("{} pages", job.progress.pages));
+            }
+            ui.horizontal(|ui| {
+                match &job.state {
+                    JobState::Running => {
+                        ui.spinner();
+                        ui.label(details.join(" · "));
+                        if ui.button("Cancel").clicked() {
+                            action = Some(JobsAction::Cancel(job.id));
+                        }
+                    }
+                    JobState::Done(summary) => {
+                        if !summary.is_empty() {
+                            details.push(summary.to_owned());
+                        }
+                        ui.label(details.join(" · "));
+                    }
+                    JobState::Failed(err) => {
+                        ui.label(details.join(" · "));
+                        ui.colored_label(ui.visuals().error_fg_color, "Failed").on_hover_text(err);
+                    }
+                    JobState::Canceled => {
+                        ui.label(details.join(" · "));
+                        ui.colored_label(ui.visuals().warn_fg_color, "Canceled");
+                    }
+                }
+            });
+            ui.separator();
+        }
+    });
+    action
+}
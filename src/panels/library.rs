@@ -0,0 +1,134 @@
+use egui::{FontId, RichText};
+
+use crate::library::{set_library, QueryLibrary, SavedQuery};
+
+pub enum LibraryAction {
+    Load(SavedQuery),
+    Close,
+}
+
+/// Side panel listing the saved queries
+#[derive(Default)]
+pub struct LibraryPanel {
+    search: String,
+    renaming: Option<(usize, String)>,
+    status: Option<String>,
+}
+
+impl LibraryPanel {
+    pub fn start_rename(&mut self, index: usize, name: &str) {
+        self.search.clear();
+        self.renaming = Some((index, name.to_owned()));
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, library: &mut QueryLibrary) -> Option<LibraryAction> {
+        let mut action = None;
+        let mut changed = false;
+
+        ui.horizontal(|ui| {
+            ui.heading(RichText::new("Query Library").font(FontId::proportional(40.0)));
+            if ui.button("Close").clicked() {
+                action = Some(LibraryAction::Close);
+            }
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Import…").clicked() {
+                if let Some(path) = rfd::FileDialog::new().add_filter("JSON", &["json"]).pick_file() {
+                    self.status = Some(match library.import(&path) {
+                        Ok(count) => {
+                            changed = true;
+                            format!("Imported {} queries", count)
+                        }
+                        Err(e) => format!("Import failed: {}", e),
+                    });
+                }
+            }
+            if ui.button("Export…").clicked() {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("JSON", &["json"])
+                    .set_file_name("queries.json")
+                    .save_file()
+                {
+                    self.status = Some(match library.export(&path) {
+                        Ok(()) => format!("Exported {} queries", library.queries.len()),
+                        Err(e) => format!("Export failed: {}", e),
+                    });
+                }
+            }
+        });
+        if let Some(status) = &self.status {
+            ui.label(status);
+        }
+        ui.horizontal(|ui| {
+            ui.label("Search:");
+            ui.add(egui::TextEdit::singleline(&mut self.search).desired_width(f32::INFINITY));
+        });
+        ui.separator();
+
+        let mut delete = None;
+        let mut duplicate = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (i, query) in library.queries.iter_mut().enumerate() {
+                if !query.matches(&self.search) {
+                    continue;
+                }
+                match &mut self.renaming {
+                    Some((index, name)) if *index == i => {
+                        let mut done = None;
+                        ui.horizontal(|ui| {
+                            let response = ui.text_edit_singleline(name);
+                            if ui.button("OK").clicked()
+                                || (response.lost_focus() && ui.input().key_pressed(egui::Key::Enter))
+                            {
+                                done = Some(true);
+                            } else if ui.button("Cancel").clicked() {
+                                done = Some(false);
+                            }
+                        });
+                        if let Some(accepted) = done {
+                            if accepted && !name.trim().is_empty() {
+                                query.name = name.trim().to_owned();
+                                changed = true;
+                            }
+                            self.renaming = None;
+                        }
+                    }
+                    _ => {
+                        ui.label(RichText::new(&query.name).font(FontId::proportional(22.0)).strong());
+                    }
+                }
+                ui.label(RichText::new(format!("{} / {}", query.domain, query.dataset)).small());
+                ui.horizontal(|ui| {
+                    if ui.button("Load").clicked() {
+                        action = Some(LibraryAction::Load(query.to_owned()));
+                    }
+                    if ui.button("Rename").clicked() {
+                        self.renaming = Some((i, query.name.to_owned()));
+                    }
+                    if ui.button("Duplicate").clicked() {
+                        duplicate = Some(i);
+                    }
+                    if ui.button("Delete").clicked() {
+                        delete = Some(i);
+                    }
+                });
+                ui.separator();
+            }
+        });
+
+        if let Some(i) = duplicate {
+            library.duplicate(i);
+            self.renaming = None;
+            changed = true;
+        }
+        if let Some(i) = delete {
+            library.queries.remove(i);
+            self.renaming = None;
+            changed = true;
+        }
+        if changed {
+            set_library(library);
+        }
+        action
+    }
+}
@@ -0,0 +1,12 @@
+pub mod analysis;
+pub mod results;
+pub mod export;
+pub mod library;
+pub mod history;
+pub mod auth;
+pub mod profiles;
+pub mod completion;
+pub mod schema;
+pub mod catalog;
+pub mod query;
+pub mod jobs;
@@ -0,0 +1,67 @@
+use egui::{FontId, RichText};
+
+use crate::config::Profile;
+
+pub enum ProfileAction {
+    Switch(usize),
+    Add,
+    Rename(String),
+    Delete,
+}
+
+/// Connection profile switcher shown at the top of the settings panel
+#[derive(Default)]
+pub struct ProfilesPanel {
+    renaming: Option<String>,
+}
+
+impl ProfilesPanel {
+    pub fn show(&mut self, ui: &mut egui::Ui, profiles: &[Profile], active: usize) -> Option<ProfileAction> {
+        let mut action = None;
+        ui.label(RichText::new("Connection Profile: ").font(FontId::proportional(25.0)));
+
+        if let Some(name) = &mut self.renaming {
+            let mut done = None;
+            ui.horizontal(|ui| {
+                let response = ui.add(egui::TextEdit::singleline(name).desired_width(250.0));
+                if ui.button("OK").clicked()
+                    || (response.lost_focus() && ui.input().key_pressed(egui::Key::Enter))
+                {
+                    done = Some(true);
+                } else if ui.button("Cancel").clicked() {
+                    done = Some(false);
+                }
+            });
+            if let Some(accepted) = done {
+                if accepted {
+                    action = Some(ProfileAction::Rename(name.trim().to_owned()));
+                }
+                self.renaming = None;
+            }
+            return action;
+        }
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("profile")
+                .selected_text(RichText::new(&profiles[active].name).font(FontId::proportional(20.0)))
+                .width(220.0)
+                .show_ui(ui, |ui| {
+                    for (i, profile) in profiles.iter().enumerate() {
+                        if ui.selectable_label(i == active, &profile.name).clicked() && i != active {
+                            action = Some(ProfileAction::Switch(i));
+                        }
+                    }
+                });
+            if ui.button("New").clicked() {
+                action = Some(ProfileAction::Add);
+            }
+            if ui.button("Rename").clicked() {
+                self.renaming = Some(profiles[active].name.to_owned());
+            }
+            if ui.add_enabled(profiles.len() > 1, egui::Button::new("Delete")).clicked() {
+                action = Some(ProfileAction::Delete);
+            }
+        });
+        action
+    }
+}
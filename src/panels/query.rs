@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+
+use egui::{FontId, RichText, WidgetText};
+
+use crate::export::{ExportFormat, ExportResponseData};
+use crate::jobs::{JobState, Jobs};
+use crate::socrata::schema::SchemaCache;
+use crate::socrata::soql::format_query;
+use crate::syntaxhighlight;
+use crate::workspace::QueryTab;
+
+/// What a tab asks of the app, along with the tab's id
+pub enum TabAction {
+    Run,
+    Download(PathBuf),
+    Analyze,
+    Save,
+    Export(PathBuf),
+    /// Look up the columns of the tab's dataset for completion
+    FetchSchema,
+}
+
+/// Shows each query tab's editor and results in the dock area
+pub struct QueryTabs<'a> {
+    pub domain: &'a str,
+    pub schemas: &'a SchemaCache,
+    pub jobs: &'a Jobs,
+    pub resume_download: &'a mut bool,
+    pub export_format: &'a mut ExportFormat,
+    pub export_data: &'a ExportResponseData,
+    pub actions: Vec<(usize, TabAction)>,
+}
+
+impl QueryTabs<'_> {
+    fn tab_ui(&mut self, ui: &mut egui::Ui, tab: &mut QueryTab) {
+        tab.check_query();
+        let error_marker = tab.query_error.as_ref().map(|e| e.marker(&tab.current_query));
+        let error_color = ui.visuals().error_fg_color;
+        let mut layouter = |ui: &egui::Ui, string: &str, wrap_width: f32| {
+            let mut layout_job =
+                syntaxhighlight::highlight(ui.ctx(), string, "soql");
+            if let Some(marker) = &error_marker {
+                syntaxhighlight::underline(&mut layout_job, marker.clone(), egui::Stroke::new(2.0, error_color));
+            }
+            layout_job.wrap.max_width = wrap_width;
+            ui.fonts().layout_job(layout_job)
+        };
+
+        ui.horizontal(|query_box| {
+            query_box.set_height(600.0);
+            egui::ScrollArea::vertical().max_height(900.0).show(query_box, |query_box| {
+                let popup_key = tab.completion.take_keys(query_box);
+                let editor_id = tab.editor_id();
+                let output = egui::TextEdit::multiline(&mut tab.current_query)
+                    .id(editor_id)
+                    // .font(egui::TextStyle::Monospace) // for cursor height
+                    .font(egui::TextStyle::Heading)
+                    .code_editor()
+                    .desired_rows(80)
+                    .lock_focus(true)
+                    .desired_width(f32::INFINITY)
+                    .layouter(&mut layouter)
+                    .show(query_box);
+                // Columns are looked up once the user starts writing a query
+                if output.response.has_focus() && self.schemas.needs_fetch(self.domain, tab.dataset.as_str()) {
+                    self.actions.push((tab.id, TabAction::FetchSchema));
+                }
+                let columns = self.schemas
+                    .get(self.domain, tab.dataset.as_str())
+                    .map(|schema| schema.queryable_columns())
+                    .unwrap_or_default();
+                tab.completion.show(query_box, &output, &mut tab.current_query, &columns, popup_key);
+                // Explain the underlined part when the pointer is over it
+                if let (Some(err), Some(marker), Some(pointer)) =
+                    (&tab.query_error, &error_marker, output.response.hover_pos())
+                {
+                    let cursor = output.galley.cursor_from_pos(pointer - output.text_draw_pos);
+                    let byte = tab.current_query
+                        .char_indices()
+                        .nth(cursor.ccursor.index)
+                        .map_or(tab.current_query.len(), |(i, _)| i);
+                    if marker.start <= byte && byte <= marker.end {
+                        egui::show_tooltip_at_pointer(query_box.ctx(), egui::Id::new("soql_error"), |ui| {
+                            ui.label(&err.message);
+                        });
+                    }
+                }
+            });
+        });
+        if let Some(err) = &tab.query_error {
+            let (line, column) = err.line_col(&tab.current_query);
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    RichText::new(format!("Line {}, column {}: {}", line, column, err.message)).font(FontId::proportional(20.0)),
+                );
+                ui.checkbox(&mut tab.run_invalid_query, "Run anyway");
+            });
+        }
+        let fetching = self.jobs.is_fetching(tab.id);
+        let canceled = self.jobs.last_fetch(tab.id).map_or(false, |job| job.state == JobState::Canceled);
+        // Action Buttons
+        ui.horizontal(|action_buttons| {
+            let runnable = fetching || tab.query_error.is_none() || tab.run_invalid_query;
+            let run_label = if fetching {
+                "Cancel?"
+            } else if canceled {
+                "Retry?"
+            } else {
+                "Run Query"
+            };
+            let run_button = egui::Button::new(egui::RichText::new(run_label).font(egui::FontId::proportional(30.0)));
+            if action_buttons
+                .add_enabled(runnable, run_button)
+                .on_disabled_hover_text("Fix the query or tick \"Run anyway\" to send it as is")
+                .clicked()
+            {
+                if fetching {
+                    self.jobs.cancel_fetch(tab.id);
+                } else {
+                    self.actions.push((tab.id, TabAction::Run));
+                }
+            }
+            let download_button = egui::Button::new(egui::RichText::new("Download to File").font(egui::FontId::proportional(30.0)));
+            if action_buttons
+                .add_enabled(!fetching, download_button)
+                .on_disabled_hover_text("Wait we are still fetching...")
+                .clicked()
+            {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("CSV", &["csv"])
+                    .set_file_name("download.csv")
+                    .save_file()
+                {
+                    self.actions.push((tab.id, TabAction::Download(path)));
+                }
+            }
+            action_buttons
+                .checkbox(self.resume_download, "Resume")
+                .on_hover_text("Continue a file left by an interrupted download of the same query; other files are replaced");
+            let format_button = egui::Button::new(egui::RichText::new("Format Query").font(egui::FontId::proportional(30.0)));
+            if action_buttons
+                .add_enabled(tab.query_error.is_none(), format_button)
+                .on_disabled_hover_text("Only a valid query can be formatted")
+                .clicked()
+            {
+                if let Ok(formatted) = format_query(&tab.current_query) {
+                    tab.current_query = formatted;
+                }
+            }
+            if action_buttons.button(egui::RichText::new("Save Query").font(egui::FontId::proportional(30.0))).clicked() {
+                self.actions.push((tab.id, TabAction::Save));
+            }
+            // Analyzing doesn't touch the tab's fetch, so both can run at once
+            let analyzing = self.jobs.is_analyzing(tab.id);
+            let analysis_label = if analyzing { "Cancel Analysis" } else { "Run Query Analysis" };
+            if action_buttons.button(egui::RichText::new(analysis_label).font(egui::FontId::proportional(30.0))).clicked() {
+                if analyzing {
+                    self.jobs.cancel_analysis(tab.id);
+                } else {
+                    self.actions.push((tab.id, TabAction::Analyze));
+                }
+            }
+        });
+        // The query is being executed
+        if tab.csv_data.is_running {
+            ui.horizontal(|ui| {
+                // We don't need to call repaint since we are using spinner here.
+                ui.spinner();
+                let mut downloaded_size = tab.csv_data.tmp_file_size;
+                if downloaded_size > 0 {
+                    // Convert current file size in Bytes to KB.
+                    downloaded_size /= 1000;
+                    // Show downloaded file size.
+                    ui.label(format!("Downloaded size: {} KB", downloaded_size));
+                }
+                if tab.csv_data.pages > 0 {
+                    ui.label(format!("Pages fetched: {}", tab.csv_data.pages));
+                }
+            });
+        }
+
+        if let Some(err) = &tab.csv_data.error {
+            ui.colored_label(ui.visuals().error_fg_color, egui::RichText::new(err).font(egui::FontId::proportional(40.0)));
+        }
+        if let Some(message) = &tab.csv_data.message {
+            ui.label(egui::RichText::new(message).font(egui::FontId::proportional(20.0)));
+        }
+
+        if let Some(csv_data) = &tab.csv_data.data {
+            let text_edit = egui::TextEdit::singleline(&mut tab.url)
+                .desired_width(f32::INFINITY)
+                .font(FontId::proportional(20.0));
+            ui.add(text_edit);
+            if let Some(path) = super::export::show(ui, tab.id, self.export_format, self.export_data) {
+                self.actions.push((tab.id, TabAction::Export(path)));
+            }
+            // Query Stats
+            ui.label(egui::RichText::new("Statistics").font(egui::FontId::proportional(30.0)));
+            let file_size = tab.csv_data.file_size;
+            ui.label(egui::RichText::new(
+                format!("Current file size: {} KB", file_size)
+            ).font(egui::FontId::proportional(20.0)));
+            // Query Elapsed Text
+            ui.label(egui::RichText::new(format!(
+                "Query Elapsed: {:#?}",
+                tab.query_duration
+            )).font(egui::FontId::proportional(20.0)));
+            // Query Results Table
+            ui.label(egui::RichText::new("Results").font(egui::FontId::proportional(30.0)));
+            let types = tab.csv_data.column_types(self.schemas.get(self.domain, &tab.dataset));
+            tab.results_grid.show(ui, csv_data, &types);
+        }
+    }
+}
+
+impl egui_dock::TabViewer for QueryTabs<'_> {
+    type Tab = QueryTab;
+
+    fn ui(&mut self, ui: &mut egui::Ui, tab: &mut QueryTab) {
+        // Tabs shown side by side must not share widget state
+        ui.push_id(tab.id, |ui| self.tab_ui(ui, tab));
+    }
+
+    fn title(&mut self, tab: &mut QueryTab) -> WidgetText {
+        if self.jobs.is_fetching(tab.id) {
+            format!("{} ⟳", tab.title()).into()
+        } else {
+            tab.title().into()
+        }
+    }
+
+    fn on_close(&mut self, tab: &mut QueryTab) -> bool {
+        self.jobs.cancel_tab(tab.id);
+        true
+    }
+}
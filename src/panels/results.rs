@@ -0,0 +1,290 @@
+use std::cmp::Ordering;
+
+use egui::{Align2, Color32, FontId, Rect, RichText, Sense, Vec2};
+
+use crate::socrata::types::{ColumnType, Value};
+
+const PAGE_SIZES: [usize; 4] = [1_000, 10_000, 100_000, 1_000_000];
+// Only this many rows are measured when sizing columns
+const MEASURED_ROWS: usize = 500;
+const MAX_COLUMN_WIDTH: f32 = 400.0;
+const MAX_CELL_CHARS: usize = 256;
+const CELL_PADDING: f32 = 12.0;
+
+/// Paged results table which only paints the rows that are on screen,
+/// so even very large result sets scroll smoothly. Clicking a header sorts
+/// by that column according to its type.
+pub struct ResultsGrid {
+    page: usize,
+    page_size: usize,
+    widths: Vec<f32>,
+    measured_rows: usize,
+    /// Column to sort by, and whether it is descending
+    sort: Option<(usize, bool)>,
+    /// Row indices in sorted order; empty when unsorted
+    order: Vec<usize>,
+}
+
+impl Default for ResultsGrid {
+    fn default() -> Self {
+        Self {
+            page: 0,
+            page_size: PAGE_SIZES[2],
+            widths: vec![],
+            measured_rows: 0,
+            sort: None,
+            order: vec![],
+        }
+    }
+}
+
+fn clip_text(s: &str) -> &str {
+    match s.char_indices().nth(MAX_CELL_CHARS) {
+        Some((i, _)) => &s[..i],
+        None => s,
+    }
+}
+
+impl ResultsGrid {
+    /// Forget the page and column sizes of the previous result set
+    pub fn reset(&mut self) {
+        self.page = 0;
+        self.widths.clear();
+        self.measured_rows = 0;
+        self.sort = None;
+        self.order.clear();
+    }
+
+    /// Ascending, then descending, then back to the order the rows came in
+    fn toggle_sort(&mut self, column: usize) {
+        self.sort = match self.sort {
+            Some((c, false)) if c == column => Some((column, true)),
+            Some((c, true)) if c == column => None,
+            _ => Some((column, false)),
+        };
+        self.order.clear();
+    }
+
+    /// Merges the rows that arrived since the last frame into the sorted
+    /// order, so streaming results aren't sorted from scratch every time.
+    /// Nulls always come last.
+    fn sort_rows(&mut self, rows: &[Vec<String>], types: &[ColumnType]) {
+        let (column, descending) = match self.sort {
+            Some(sort) => sort,
+            None => return,
+        };
+        if self.order.len() >= rows.len() {
+            return;
+        }
+        let text = ColumnType::Text;
+        let column_type = types.get(column).unwrap_or(&text);
+        let key = |i: usize| rows[i].get(column).map_or(Value::Null, |cell| column_type.decode(cell));
+        let compare = |a: &Value, b: &Value| match (a, b) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) => Ordering::Greater,
+            (_, Value::Null) => Ordering::Less,
+            (a, b) if descending => b.compare(a),
+            (a, b) => a.compare(b),
+        };
+        let mut added: Vec<(usize, Value)> = (self.order.len()..rows.len()).map(|i| (i, key(i))).collect();
+        added.sort_by(|a, b| compare(&a.1, &b.1));
+
+        // Ties keep the order the rows came in
+        let sorted = std::mem::take(&mut self.order);
+        self.order.reserve(rows.len());
+        let mut added = added.into_iter().peekable();
+        for i in sorted {
+            let old = key(i);
+            while let Some((j, _)) = added.next_if(|(_, new)| compare(new, &old) == Ordering::Less) {
+                self.order.push(j);
+            }
+            self.order.push(i);
+        }
+        self.order.extend(added.map(|(j, _)| j));
+    }
+
+    fn measure(&mut self, ui: &egui::Ui, headers: &[String], rows: &[Vec<String>], header_font: &FontId, cell_font: &FontId) {
+        let sampled = rows.len().min(MEASURED_ROWS);
+        if self.widths.len() == headers.len() && self.measured_rows == sampled {
+            return;
+        }
+        let fonts = ui.fonts();
+        let color = ui.visuals().text_color();
+        let width_of = |text: &str, font: &FontId| {
+            fonts.layout_no_wrap(clip_text(text).to_owned(), font.clone(), color).size().x + CELL_PADDING
+        };
+        // Leave room for the sort arrow
+        self.widths = headers.iter().map(|h| width_of(&format!("{} ▼", h), header_font)).collect();
+        for row in rows.iter().take(sampled) {
+            for (i, cell) in row.iter().enumerate().take(self.widths.len()) {
+                self.widths[i] = self.widths[i].max(width_of(cell, cell_font));
+            }
+        }
+        for width in self.widths.iter_mut() {
+            *width = width.min(MAX_COLUMN_WIDTH);
+        }
+        self.measured_rows = sampled;
+    }
+
+    /// `data` holds the header row first, followed by the records, and
+    /// `types` the type of each column
+    pub fn show(&mut self, ui: &mut egui::Ui, data: &[Vec<String>], types: &[ColumnType]) {
+        let (headers, rows) = match data.split_first() {
+            Some((headers, rows)) => (headers, rows),
+            None => return,
+        };
+        self.sort_rows(rows, types);
+        let pages = ((rows.len() + self.page_size - 1) / self.page_size).max(1);
+        self.page = self.page.min(pages - 1);
+        let first = self.page * self.page_size;
+        let page_len = self.page_size.min(rows.len() - first);
+
+        // Paging controls
+        ui.horizontal(|ui| {
+            if ui.add_enabled(self.page > 0, egui::Button::new("◀ Prev")).clicked() {
+                self.page -= 1;
+            }
+            if ui.add_enabled(self.page + 1 < pages, egui::Button::new("Next ▶")).clicked() {
+                self.page += 1;
+            }
+            ui.label(RichText::new(format!(
+                "Rows {}–{} of {} (page {} of {})",
+                if page_len == 0 { 0 } else { first + 1 },
+                first + page_len,
+                rows.len(),
+                self.page + 1,
+                pages
+            )).font(FontId::proportional(20.0)));
+            let page_size = self.page_size;
+            egui::ComboBox::from_id_source("page_size")
+                .selected_text(format!("{} rows per page", self.page_size))
+                .show_ui(ui, |ui| {
+                    for size in PAGE_SIZES {
+                        ui.selectable_value(&mut self.page_size, size, size.to_string());
+                    }
+                });
+            if page_size != self.page_size {
+                self.page = first / self.page_size;
+            }
+        });
+
+        let header_font = FontId::proportional(24.0);
+        let cell_font = FontId::proportional(20.0);
+        self.measure(ui, headers, rows, &header_font, &cell_font);
+
+        let header_height = ui.fonts().row_height(&header_font) + CELL_PADDING;
+        let row_height = ui.fonts().row_height(&cell_font) + CELL_PADDING / 2.0;
+        let total_width: f32 = self.widths.iter().sum();
+        let widths = &self.widths;
+        let order = &self.order;
+        let row_at = |i: usize| &rows[order.get(i).copied().unwrap_or(i)];
+
+        let text_color = ui.visuals().text_color();
+        let header_styles = vec![(Align2::LEFT_CENTER, text_color); headers.len()];
+        // Numbers line up on the right and links look like links
+        let cell_styles: Vec<(Align2, Color32)> = (0..headers.len())
+            .map(|i| match types.get(i) {
+                Some(t) if t.is_numeric() => (Align2::RIGHT_CENTER, text_color),
+                Some(ColumnType::Url) => (Align2::LEFT_CENTER, ui.visuals().hyperlink_color),
+                _ => (Align2::LEFT_CENTER, text_color),
+            })
+            .collect();
+        let labels: Vec<String> = headers
+            .iter()
+            .enumerate()
+            .map(|(i, header)| match self.sort {
+                Some((column, false)) if column == i => format!("{} ▲", header),
+                Some((column, true)) if column == i => format!("{} ▼", header),
+                _ => header.to_owned(),
+            })
+            .collect();
+        let mut clicked = None;
+
+        egui::ScrollArea::both()
+            .id_source("results_grid")
+            .auto_shrink([false, false])
+            .show_viewport(ui, |ui, viewport| {
+                let size = Vec2::new(total_width, header_height + row_height * page_len as f32);
+                let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+                let painter = ui.painter();
+                let visuals = ui.visuals();
+                let left = rect.left() + viewport.min.x;
+                let right = rect.left() + viewport.max.x;
+
+                let paint_row = |y: f32, height: f32, cells: &[String], font: &FontId, styles: &[(Align2, Color32)]| {
+                    let mut x = rect.left();
+                    for ((cell, width), (align, color)) in cells.iter().zip(widths.iter()).zip(styles) {
+                        if x + width >= left && x <= right {
+                            let cell_rect = Rect::from_min_size(egui::pos2(x, y), Vec2::new(*width, height));
+                            let text_x = if *align == Align2::RIGHT_CENTER {
+                                x + width - CELL_PADDING / 2.0
+                            } else {
+                                x + CELL_PADDING / 2.0
+                            };
+                            painter
+                                .with_clip_rect(cell_rect.intersect(painter.clip_rect()))
+                                .text(egui::pos2(text_x, y + height / 2.0), *align, clip_text(cell), font.clone(), *color);
+                        }
+                        x += width;
+                    }
+                };
+
+                // Rows that intersect the viewport
+                let top = ((viewport.min.y - header_height) / row_height).floor().max(0.0) as usize;
+                let bottom = (((viewport.max.y - header_height) / row_height).ceil() as usize + 1).min(page_len);
+                for i in top..bottom {
+                    let y = rect.top() + header_height + i as f32 * row_height;
+                    if i % 2 == 1 {
+                        let stripe = Rect::from_x_y_ranges(rect.x_range(), y..=y + row_height);
+                        painter.rect_filled(stripe, 0.0, visuals.faint_bg_color);
+                    }
+                    paint_row(y, row_height, row_at(first + i), &cell_font, &cell_styles);
+                }
+
+                // The header stays pinned to the top of the viewport
+                let y = rect.top() + viewport.min.y;
+                let header = Rect::from_x_y_ranges(rect.x_range(), y..=y + header_height);
+                painter.rect_filled(header, 0.0, visuals.extreme_bg_color);
+                paint_row(y, header_height, &labels, &header_font, &header_styles);
+
+                let mut x = rect.left();
+                for (i, width) in widths.iter().enumerate() {
+                    let cell_rect = Rect::from_min_size(egui::pos2(x, y), Vec2::new(*width, header_height));
+                    let response = ui
+                        .interact(cell_rect, ui.id().with(("sort", i)), Sense::click())
+                        .on_hover_cursor(egui::CursorIcon::PointingHand);
+                    if response.clicked() {
+                        clicked = Some(i);
+                    }
+                    x += width;
+                }
+            });
+        if let Some(column) = clicked {
+            self.toggle_sort(column);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(cells: &[&str]) -> Vec<Vec<String>> {
+        cells.iter().map(|cell| vec![cell.to_string()]).collect()
+    }
+
+    #[test]
+    fn merges_rows_that_arrive_later() {
+        let mut grid = ResultsGrid { sort: Some((0, false)), ..Default::default() };
+        let all = rows(&["5", "", "1", "3", "2", "", "1", "4"]);
+        grid.sort_rows(&all[..3], &[ColumnType::Number]);
+        assert_eq!(grid.order, vec![2, 0, 1]);
+        grid.sort_rows(&all, &[ColumnType::Number]);
+        assert_eq!(grid.order, vec![2, 6, 4, 3, 7, 0, 1, 5]);
+
+        let mut descending = ResultsGrid { sort: Some((0, true)), ..Default::default() };
+        descending.sort_rows(&all[..4], &[ColumnType::Number]);
+        descending.sort_rows(&all, &[ColumnType::Number]);
+        assert_eq!(descending.order, vec![0, 7, 3, 4, 2, 6, 1, 5]);
+    }
+}
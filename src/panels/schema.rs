@@ -0,0 +1,156 @@
+use egui::text::{CCursor, CCursorRange};
+use egui::{FontId, RichText};
+
+use crate::util::format_timestamp;
+use crate::socrata::schema::{display_value, CachedContents, DatasetSchema, SchemaColumn};
+use crate::socrata::soql::complete::quote_column;
+
+pub enum SchemaAction {
+    /// Field name to insert into the query
+    Insert(String),
+    Refresh,
+    Close,
+}
+
+/// Side panel showing the current dataset's metadata and columns as a tree
+#[derive(Default)]
+pub struct SchemaPanel {
+    filter: String,
+}
+
+fn stats(ui: &mut egui::Ui, contents: &CachedContents) {
+    let rows = [
+        ("Non-null", contents.non_null.map(|n| n.to_string())),
+        ("Null", contents.null.map(|n| n.to_string())),
+        ("Distinct", contents.cardinality.map(|n| n.to_string())),
+        ("Smallest", contents.smallest.as_ref().map(display_value)),
+        ("Largest", contents.largest.as_ref().map(display_value)),
+    ];
+    for (label, value) in rows {
+        if let Some(value) = value {
+            ui.label(format!("{}: {}", label, value));
+        }
+    }
+    if !contents.top.is_empty() {
+        egui::CollapsingHeader::new("Top values").show(ui, |ui| {
+            for top in &contents.top {
+                match top.count {
+                    Some(count) => ui.label(format!("{} ({})", display_value(&top.item), count)),
+                    None => ui.label(display_value(&top.item)),
+                };
+            }
+        });
+    }
+}
+
+impl SchemaPanel {
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        schema: Option<&DatasetSchema>,
+        error: Option<&String>,
+        loading: bool,
+    ) -> Option<SchemaAction> {
+        let mut action = None;
+        ui.horizontal(|ui| {
+            ui.heading(RichText::new("Schema").font(FontId::proportional(40.0)));
+            if ui.add_enabled(!loading, egui::Button::new("Refresh")).clicked() {
+                action = Some(SchemaAction::Refresh);
+            }
+            if ui.button("Close").clicked() {
+                action = Some(SchemaAction::Close);
+            }
+        });
+        if loading {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("Loading the dataset schema...");
+            });
+        }
+        if let Some(err) = error {
+            ui.colored_label(ui.visuals().error_fg_color, err);
+        }
+        let schema = match schema {
+            Some(schema) => schema,
+            None => return action,
+        };
+
+        egui::ScrollArea::vertical().id_source("schema_tree").show(ui, |ui| {
+            egui::CollapsingHeader::new(RichText::new(&schema.name).strong())
+                .id_source("schema_dataset")
+                .default_open(true)
+                .show(ui, |ui| {
+                    ui.label(format!("ID: {}", schema.id));
+                    if let Some(rows) = schema.row_count() {
+                        ui.label(format!("Rows: {}", rows));
+                    }
+                    if let Some(updated) = schema.rows_updated_at {
+                        ui.label(format!("Last updated: {} UTC", format_timestamp(updated)));
+                    }
+                    if let Some(description) = schema.description.as_ref().filter(|d| !d.trim().is_empty()) {
+                        egui::CollapsingHeader::new("Description").show(ui, |ui| {
+                            ui.label(description.trim());
+                        });
+                    }
+                });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Filter:");
+                ui.text_edit_singleline(&mut self.filter);
+            });
+            ui.label(RichText::new("Double-click a column to insert it into the query.").small());
+            let filter = self.filter.to_lowercase();
+            let columns: Vec<&SchemaColumn> = schema
+                .columns
+                .iter()
+                .filter(|c| c.name.to_lowercase().contains(&filter) || c.field_name.to_lowercase().contains(&filter))
+                .collect();
+            egui::CollapsingHeader::new(format!("Columns ({})", columns.len()))
+                .id_source("schema_columns")
+                .default_open(true)
+                .show(ui, |ui| {
+                    for column in columns {
+                        let header = egui::CollapsingHeader::new(format!("{} · {}", column.field_name, column.data_type))
+                            .id_source(("schema_column", &column.field_name))
+                            .show(ui, |ui| {
+                                ui.label(format!("Name: {}", column.name));
+                                ui.label(format!("Field name: {}", column.field_name));
+                                ui.label(format!("Type: {}", column.data_type));
+                                if let Some(description) = column.description.as_ref().filter(|d| !d.trim().is_empty()) {
+                                    ui.label(description.trim());
+                                }
+                                if let Some(contents) = &column.cached_contents {
+                                    stats(ui, contents);
+                                }
+                            });
+                        let response = header.header_response.on_hover_text(&column.name);
+                        if response.double_clicked() {
+                            action = Some(SchemaAction::Insert(quote_column(&column.field_name)));
+                        }
+                    }
+                });
+        });
+        action
+    }
+}
+
+/// Inserts `insert` at the cursor of the text editor `id`, or at the end when
+/// it has never been focused, and leaves the cursor after it
+pub fn insert_at_cursor(ctx: &egui::Context, id: egui::Id, text: &mut String, insert: &str) {
+    let mut state = egui::TextEdit::load_state(ctx, id).unwrap_or_default();
+    let chars = text.chars().count();
+    let (start, end) = match state.ccursor_range() {
+        Some(range) => {
+            let [a, b] = range.sorted();
+            (a.index.min(chars), b.index.min(chars))
+        }
+        None => (chars, chars),
+    };
+    let byte = |index: usize| text.char_indices().nth(index).map_or(text.len(), |(i, _)| i);
+    let range = byte(start)..byte(end);
+    text.replace_range(range, insert);
+    state.set_ccursor_range(Some(CCursorRange::one(CCursor::new(start + insert.chars().count()))));
+    egui::TextEdit::store_state(ctx, id, state);
+    ctx.memory().request_focus(id);
+}
@@ -1,3 +1,5 @@
+use regex::Regex;
+
 #[allow(dead_code)]
 pub enum AnalysisChannel {
     Data(usize)
@@ -8,18 +10,18 @@ pub enum AnalysisErrCause {
 }
 
 pub enum AnalysisContainer {
-    Data(String)
+    Data(ExplainPlan)
 }
 
 #[derive(Default)]
 pub struct AnalysisResponseData {
-    pub data: Option<String>,
+    pub data: Option<ExplainPlan>,
     pub is_running: bool,
     pub error: Option<String>
 }
 
 impl AnalysisResponseData {
-    pub fn set_data(&mut self, data: String) {
+    pub fn set_data(&mut self, data: ExplainPlan) {
         self.error.take();
         self.data = Some(data);
     }
@@ -28,3 +30,177 @@ impl AnalysisResponseData {
         self.error = Some(e.to_string());
     }
 }
+
+/// Planner estimate attached to a node, e.g. `(cost=0.00..35.50 rows=2550 width=4)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub startup_cost: f64,
+    pub total_cost: f64,
+    pub rows: u64,
+    pub width: u64,
+}
+
+/// Measured values when the plan was run with analyze,
+/// e.g. `(actual time=0.010..0.020 rows=10 loops=1)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Actual {
+    pub startup_time: f64,
+    pub total_time: f64,
+    pub rows: u64,
+    pub loops: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanNode {
+    pub operation: String,
+    pub estimate: Option<Estimate>,
+    pub actual: Option<Actual>,
+    /// Extra lines such as `Filter:` or `Sort Key:` that belong to this node
+    pub details: Vec<String>,
+    pub children: Vec<PlanNode>,
+}
+
+/// The patterns for a node's estimate and actual values, compiled once per plan
+struct NodePatterns {
+    estimate: Regex,
+    actual: Regex,
+}
+
+impl NodePatterns {
+    fn new() -> Self {
+        Self {
+            estimate: Regex::new(r"\(cost=([\d.]+)\.\.([\d.]+) rows=(\d+) width=(\d+)\)").unwrap(),
+            actual: Regex::new(r"\(actual time=([\d.]+)\.\.([\d.]+) rows=(\d+) loops=(\d+)\)").unwrap(),
+        }
+    }
+}
+
+impl PlanNode {
+    fn parse(line: &str, patterns: &NodePatterns) -> Self {
+        let estimate = patterns.estimate.captures(line).map(|c| Estimate {
+            startup_cost: c[1].parse().unwrap_or_default(),
+            total_cost: c[2].parse().unwrap_or_default(),
+            rows: c[3].parse().unwrap_or_default(),
+            width: c[4].parse().unwrap_or_default(),
+        });
+        let actual = patterns.actual.captures(line).map(|c| Actual {
+            startup_time: c[1].parse().unwrap_or_default(),
+            total_time: c[2].parse().unwrap_or_default(),
+            rows: c[3].parse().unwrap_or_default(),
+            loops: c[4].parse().unwrap_or_default(),
+        });
+        let operation = match line.find("  (") {
+            Some(i) => &line[..i],
+            None => line,
+        };
+
+        Self {
+            operation: operation.trim().to_owned(),
+            estimate,
+            actual,
+            details: vec![],
+            children: vec![],
+        }
+    }
+}
+
+/// Typed form of the `explainPlan` text returned by `query_info?analyze=true`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ExplainPlan {
+    pub root: Option<PlanNode>,
+    /// Trailing lines that are not part of the tree, e.g. `Planning Time: 0.1 ms`
+    pub summary: Vec<String>,
+    pub raw: String,
+}
+
+impl ExplainPlan {
+    pub fn parse(raw: &str) -> Self {
+        // Each entry is the indentation of the node's text and the node itself
+        let mut stack: Vec<(usize, PlanNode)> = vec![];
+        let mut root = None;
+        let mut summary = vec![];
+        let patterns = NodePatterns::new();
+
+        fn fold(stack: &mut Vec<(usize, PlanNode)>, root: &mut Option<PlanNode>) {
+            if let Some((_, node)) = stack.pop() {
+                match stack.last_mut() {
+                    Some((_, parent)) => parent.children.push(node),
+                    None => *root = Some(node),
+                }
+            }
+        }
+
+        for line in raw.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let indent = line.len() - line.trim_start().len();
+            if let Some(child) = trimmed.strip_prefix("->") {
+                let indent = indent + 2 + (child.len() - child.trim_start().len());
+                while stack.last().map_or(false, |(i, _)| *i >= indent) {
+                    fold(&mut stack, &mut root);
+                }
+                stack.push((indent, PlanNode::parse(child, &patterns)));
+            } else if stack.is_empty() && root.is_none() {
+                stack.push((indent, PlanNode::parse(trimmed, &patterns)));
+            } else if indent == 0 {
+                summary.push(trimmed.to_owned());
+            } else if let Some((_, node)) = stack.last_mut() {
+                node.details.push(trimmed.to_owned());
+            }
+        }
+        while !stack.is_empty() {
+            fold(&mut stack, &mut root);
+        }
+
+        Self {
+            root,
+            summary,
+            raw: raw.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socrata::ExplainQuery;
+
+    const QUERY_INFO: &str = r#"{"explainPlan": "Limit  (cost=0.00..12.50 rows=100 width=40) (actual time=0.015..0.210 rows=100 loops=1)\n  ->  Sort  (cost=0.00..35.50 rows=2550 width=40) (actual time=0.014..0.180 rows=100 loops=1)\n        Sort Key: borough\n        ->  Seq Scan on trees  (cost=0.00..25.50 rows=2550 width=40)\n              Filter: (status = 'Alive'::text)\n  ->  Index Scan on boroughs  (cost=0.29..8.31 rows=1 width=12)\nPlanning Time: 0.120 ms\nExecution Time: 0.250 ms\n"}"#;
+
+    #[test]
+    fn parses_a_query_info_body() {
+        let query: ExplainQuery = serde_json::from_str(QUERY_INFO).unwrap();
+        let plan = query.plan();
+        let root = plan.root.unwrap();
+        assert_eq!(root.operation, "Limit");
+        assert_eq!(
+            root.estimate,
+            Some(Estimate { startup_cost: 0.0, total_cost: 12.5, rows: 100, width: 40 })
+        );
+        assert_eq!(
+            root.actual,
+            Some(Actual { startup_time: 0.015, total_time: 0.21, rows: 100, loops: 1 })
+        );
+
+        assert_eq!(root.children.len(), 2);
+        let sort = &root.children[0];
+        assert_eq!(sort.operation, "Sort");
+        assert_eq!(sort.details, vec!["Sort Key: borough".to_owned()]);
+        let scan = &sort.children[0];
+        assert_eq!(scan.operation, "Seq Scan on trees");
+        assert_eq!(scan.actual, None);
+        assert_eq!(scan.details, vec!["Filter: (status = 'Alive'::text)".to_owned()]);
+        assert_eq!(root.children[1].operation, "Index Scan on boroughs");
+
+        assert_eq!(plan.summary, vec!["Planning Time: 0.120 ms".to_owned(), "Execution Time: 0.250 ms".to_owned()]);
+    }
+
+    #[test]
+    fn empty_plans_have_no_root() {
+        let plan = ExplainPlan::parse("");
+        assert_eq!(plan.root, None);
+        assert!(plan.summary.is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+use reqwest::RequestBuilder;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMode {
+    #[default]
+    Anonymous,
+    /// Socrata username and password
+    Basic,
+    /// API key ID and secret, sent the same way as a username and password
+    ApiKey,
+    /// OAuth2 access token
+    Bearer,
+}
+
+impl AuthMode {
+    pub const ALL: [AuthMode; 4] = [AuthMode::Anonymous, AuthMode::Basic, AuthMode::ApiKey, AuthMode::Bearer];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AuthMode::Anonymous => "Anonymous",
+            AuthMode::Basic => "Username & password",
+            AuthMode::ApiKey => "API key",
+            AuthMode::Bearer => "OAuth2 bearer token",
+        }
+    }
+}
+
+/// How requests to a Socrata domain are authenticated. Values for every mode
+/// are kept so that switching modes back and forth doesn't lose them.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Auth {
+    pub mode: AuthMode,
+    pub username: String,
+    pub password: String,
+    pub key_id: String,
+    pub key_secret: String,
+    pub token: String,
+    /// Sent as `X-App-Token` with any mode to avoid throttling
+    pub app_token: String,
+}
+
+impl Auth {
+    pub fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        let request = match self.mode {
+            AuthMode::Anonymous => request,
+            AuthMode::Basic => request.basic_auth(&self.username, Some(&self.password)),
+            AuthMode::ApiKey => request.basic_auth(&self.key_id, Some(&self.key_secret)),
+            AuthMode::Bearer => request.bearer_auth(&self.token),
+        };
+        if self.app_token.is_empty() {
+            request
+        } else {
+            request.header("X-App-Token", &self.app_token)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        [&self.username, &self.password, &self.key_id, &self.key_secret, &self.token, &self.app_token]
+            .iter()
+            .all(|value| value.is_empty())
+    }
+}
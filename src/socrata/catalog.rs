@@ -0,0 +1,289 @@
+use reqwest::Url;
+use serde_derive::Deserialize;
+
+use super::{base_url, UrlError};
+
+/// Results asked for at a time
+pub const PAGE_SIZE: usize = 25;
+
+pub enum CatalogErrCause {
+    Data(String),
+}
+
+/// What to look for in a domain's catalog. Empty fields are left out.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CatalogSearch {
+    pub keywords: String,
+    pub category: String,
+    pub tag: String,
+}
+
+/// The Discovery API URL for datasets on `domain`, starting at result `offset`
+pub fn make_catalog_url(domain: &str, search: &CatalogSearch, offset: usize) -> Result<Url, UrlError> {
+    let mut url = base_url(domain)?;
+    let host = url.host_str().unwrap_or_default().to_owned();
+    url.set_path("/api/catalog/v1");
+    {
+        let mut pairs = url.query_pairs_mut();
+        pairs
+            .append_pair("domains", &host)
+            .append_pair("search_context", &host)
+            .append_pair("only", "dataset");
+        for (key, value) in [("q", &search.keywords), ("categories", &search.category), ("tags", &search.tag)] {
+            if !value.trim().is_empty() {
+                pairs.append_pair(key, value.trim());
+            }
+        }
+        pairs
+            .append_pair("limit", &PAGE_SIZE.to_string())
+            .append_pair("offset", &offset.to_string());
+    }
+    Ok(url)
+}
+
+#[derive(Deserialize, Debug)]
+struct Resource {
+    id: String,
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(rename = "updatedAt", default)]
+    updated_at: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct Classification {
+    #[serde(default)]
+    domain_category: Option<String>,
+    #[serde(default)]
+    domain_tags: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CatalogResult {
+    resource: Resource,
+    #[serde(default)]
+    classification: Classification,
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct CatalogResponse {
+    results: Vec<CatalogResult>,
+    #[serde(rename = "resultSetSize", default)]
+    result_set_size: usize,
+}
+
+impl CatalogResponse {
+    pub(super) fn into_page(self, search: &CatalogSearch, offset: usize) -> CatalogPage {
+        let datasets = self
+            .results
+            .into_iter()
+            .map(|r| CatalogDataset {
+                id: r.resource.id,
+                name: r.resource.name,
+                description: r.resource.description,
+                category: r.classification.domain_category,
+                tags: r.classification.domain_tags,
+                updated_at: r.resource.updated_at,
+            })
+            .collect();
+        CatalogPage {
+            search: search.to_owned(),
+            offset,
+            datasets,
+            total: self.result_set_size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatalogDataset {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    /// As sent by the API, e.g. `2023-01-31T12:00:00.000Z`
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatalogPage {
+    /// The search this page answers
+    pub search: CatalogSearch,
+    pub offset: usize,
+    pub datasets: Vec<CatalogDataset>,
+    /// Matches in the whole catalog, not just this page
+    pub total: usize,
+}
+
+/// Results of the latest search, grown page by page
+#[derive(Default)]
+pub struct CatalogResults {
+    pub search: CatalogSearch,
+    pub datasets: Vec<CatalogDataset>,
+    pub total: usize,
+    pub error: Option<String>,
+    pub loading: bool,
+}
+
+impl CatalogResults {
+    pub fn start(&mut self, search: CatalogSearch, offset: usize) {
+        if offset == 0 {
+            self.datasets.clear();
+            self.total = 0;
+        }
+        self.search = search;
+        self.error = None;
+        self.loading = true;
+    }
+
+    pub fn finish(&mut self, result: Result<CatalogPage, String>) {
+        match result {
+            // A page for an older search that was replaced meanwhile
+            Ok(page) if page.search != self.search || page.offset != self.datasets.len() => {}
+            Ok(page) => {
+                self.loading = false;
+                self.datasets.extend(page.datasets);
+                self.total = page.total;
+            }
+            Err(e) => {
+                self.loading = false;
+                self.error = Some(e);
+            }
+        }
+    }
+
+    pub fn has_more(&self) -> bool {
+        self.datasets.len() < self.total
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::auth::Auth;
+    use super::super::client::SocrataClient;
+    use super::super::test_server::stand_in;
+    use super::*;
+
+    const RESULTS: &str = r#"{
+        "results": [
+            {
+                "resource": {
+                    "id": "erm2-nwe9",
+                    "name": "311 Service Requests",
+                    "description": "All 311 requests",
+                    "updatedAt": "2023-06-01T10:00:00.000Z",
+                    "type": "dataset"
+                },
+                "classification": {
+                    "categories": ["social services"],
+                    "domain_category": "Social Services",
+                    "domain_tags": ["311", "complaints"]
+                },
+                "metadata": { "domain": "data.cityofnewyork.us" }
+            },
+            {
+                "resource": { "id": "h9gi-nx95", "name": "Motor Vehicle Collisions" }
+            }
+        ],
+        "resultSetSize": 42,
+        "timings": { "serviceMillis": 12 }
+    }"#;
+
+    #[test]
+    fn url_has_filters_and_paging() {
+        let search = CatalogSearch {
+            keywords: "service requests".to_owned(),
+            category: "Social Services".to_owned(),
+            tag: String::new(),
+        };
+        let url = make_catalog_url("data.cityofnewyork.us", &search, 50).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://data.cityofnewyork.us/api/catalog/v1?domains=data.cityofnewyork.us\
+             &search_context=data.cityofnewyork.us&only=dataset&q=service+requests\
+             &categories=Social+Services&limit=25&offset=50"
+        );
+    }
+
+    #[test]
+    fn url_needs_a_domain() {
+        assert_eq!(make_catalog_url(" ", &CatalogSearch::default(), 0), Err(UrlError::EmptyDomain));
+    }
+
+    #[tokio::test]
+    async fn searches_the_domain() {
+        let (domain, request) = stand_in("200 OK", "application/json", RESULTS).await;
+        let search = CatalogSearch {
+            keywords: "311".to_owned(),
+            category: String::new(),
+            tag: "complaints".to_owned(),
+        };
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let page = client.search_catalog(&search, 0).await.unwrap();
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("GET /api/catalog/v1?domains=127.0.0.1&"), "{}", request);
+        assert!(request.contains("&q=311&tags=complaints&limit=25&offset=0 "), "{}", request);
+
+        assert_eq!(page.total, 42);
+        assert_eq!(page.offset, 0);
+        assert_eq!(page.datasets.len(), 2);
+        assert_eq!(
+            page.datasets[0],
+            CatalogDataset {
+                id: "erm2-nwe9".to_owned(),
+                name: "311 Service Requests".to_owned(),
+                description: "All 311 requests".to_owned(),
+                category: Some("Social Services".to_owned()),
+                tags: vec!["311".to_owned(), "complaints".to_owned()],
+                updated_at: "2023-06-01T10:00:00.000Z".to_owned(),
+            }
+        );
+        assert_eq!(page.datasets[1].id, "h9gi-nx95");
+        assert_eq!(page.datasets[1].category, None);
+    }
+
+    #[tokio::test]
+    async fn reports_failed_searches() {
+        let (domain, _request) = stand_in("500 Internal Server Error", "application/json", r#"{"error": "boom"}"#).await;
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let err = client.search_catalog(&CatalogSearch::default(), 0).await.unwrap_err();
+        assert!(err.to_string().starts_with("Searching the catalog failed with 500"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn pages_are_appended_in_order() {
+        let (domain, _request) = stand_in("200 OK", "application/json", RESULTS).await;
+        let mut results = CatalogResults::default();
+        results.start(CatalogSearch::default(), 0);
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        results.finish(client.search_catalog(&results.search, 0).await.map_err(|e| e.to_string()));
+        assert_eq!(results.datasets.len(), 2);
+        assert!(results.has_more());
+
+        // A stale first page doesn't get appended again
+        results.start(CatalogSearch::default(), 2);
+        let (domain, _request) = stand_in("200 OK", "application/json", RESULTS).await;
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let stale = client.search_catalog(&results.search, 0).await;
+        results.finish(stale.map_err(|e| e.to_string()));
+        assert_eq!(results.datasets.len(), 2);
+        assert!(results.loading);
+    }
+
+    #[tokio::test]
+    async fn pages_of_other_searches_are_ignored() {
+        let (domain, _request) = stand_in("200 OK", "application/json", RESULTS).await;
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let old = CatalogSearch { keywords: "311".to_owned(), ..Default::default() };
+        let page = client.search_catalog(&old, 0).await.unwrap();
+
+        let mut results = CatalogResults::default();
+        results.start(CatalogSearch { keywords: "collisions".to_owned(), ..Default::default() }, 0);
+        results.finish(Ok(page));
+        assert!(results.datasets.is_empty());
+        assert!(results.loading);
+    }
+}
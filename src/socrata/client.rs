@@ -0,0 +1,657 @@
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io::{self, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use reqwest::header::{HeaderMap, CONTENT_TYPE, RANGE};
+use reqwest::{Client, RequestBuilder, Response, StatusCode, Url};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use super::analysis::ExplainPlan;
+use super::auth::Auth;
+use super::catalog::{make_catalog_url, CatalogPage, CatalogResponse, CatalogSearch};
+use super::csv_stream::CsvStream;
+use super::download::{resume_point, DownloadMarker, ResumePoint};
+use super::paging::{PagedQuery, Paging};
+use super::schema::DatasetSchema;
+use super::types::Column;
+use super::{base_url, make_analyze_url, make_query, make_schema_url, ExplainQuery, UrlError};
+
+/// How often progress is reported while streaming a response
+pub const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub enum ClientError {
+    Url(UrlError),
+    Http(reqwest::Error),
+    Io(io::Error),
+    /// The server answered with an error status
+    Status {
+        action: &'static str,
+        status: StatusCode,
+        body: String,
+    },
+    /// A CSV was asked for but something else came back
+    UnexpectedContent {
+        content_type: String,
+        body: String,
+    },
+    /// The response could not be understood
+    Invalid(String),
+    Canceled,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Url(e) => write!(f, "{}", e),
+            ClientError::Http(e) => write!(f, "{}", e),
+            ClientError::Io(e) => write!(f, "{}", e),
+            ClientError::Status { action, status, body } => write!(f, "{} failed with {}: {}", action, status, body),
+            ClientError::UnexpectedContent { content_type, body } => {
+                write!(f, "Expected CSV; found {}: {}", content_type, body)
+            }
+            ClientError::Invalid(e) => write!(f, "{}", e),
+            ClientError::Canceled => write!(f, "Canceled"),
+        }
+    }
+}
+
+impl Error for ClientError {}
+
+impl From<UrlError> for ClientError {
+    fn from(e: UrlError) -> Self {
+        ClientError::Url(e)
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+/// Stops a running request at its next chunk or page once canceled.
+/// Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Resolves once the token is canceled, checking every [`FLUSH_INTERVAL`]
+    async fn canceled(&self) {
+        while !self.is_canceled() {
+            tokio::time::sleep(FLUSH_INTERVAL).await;
+        }
+    }
+
+    fn check(&self) -> Result<(), ClientError> {
+        if self.is_canceled() {
+            Err(ClientError::Canceled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// What a streaming request has received since it last reported
+pub enum Progress {
+    Bytes(usize),
+    /// Parsed records; the very first ones reported start with the header row
+    Rows(Vec<Vec<String>>),
+    /// Pages fetched so far when paging through a query
+    Page(usize),
+    /// The result's columns and their types, reported before any rows
+    Columns(Vec<Column>),
+}
+
+/// Talks to one Socrata domain. Cheap to clone; clones share connections.
+#[derive(Clone)]
+pub struct SocrataClient {
+    http: Client,
+    domain: String,
+    auth: Auth,
+}
+
+impl SocrataClient {
+    pub fn new(domain: &str, auth: Auth) -> Result<Self, ClientError> {
+        base_url(domain)?;
+        Ok(Self {
+            http: Client::new(),
+            domain: domain.to_owned(),
+            auth,
+        })
+    }
+
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    fn get(&self, url: Url) -> RequestBuilder {
+        self.auth.apply(self.http.get(url))
+    }
+
+    /// Runs `soql` against `dataset`, streaming rows to `progress` as they are
+    /// parsed. Returns the number of records, not counting the header row.
+    pub async fn query<F, Fut>(&self, dataset: &str, soql: &str, cancel: &CancelToken, mut progress: F) -> Result<usize, ClientError>
+    where
+        F: FnMut(Progress) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let url = make_query(&self.domain, dataset, soql)?;
+        let mut response = ok_or_status(self.get(url).send().await?, "Query").await?;
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_owned();
+        if !content_type.contains("text/csv") {
+            let body = response.text().await.unwrap_or_default();
+            return Err(ClientError::UnexpectedContent { content_type, body });
+        }
+
+        if let Some(columns) = response_columns(response.headers()) {
+            progress(Progress::Columns(columns)).await;
+        }
+
+        let mut parser = CsvStream::default();
+        let mut total_rows = 0;
+        let mut pending_rows = vec![];
+        let mut pending_bytes = 0;
+        let mut last_flush = Instant::now();
+        while let Some(a_chunk) = response.chunk().await? {
+            cancel.check()?;
+            pending_bytes += a_chunk.len();
+            pending_rows.extend(parser.feed(&a_chunk));
+
+            // Reporting may wait on the caller, so batch them up
+            if last_flush.elapsed() >= FLUSH_INTERVAL {
+                progress(Progress::Bytes(pending_bytes)).await;
+                pending_bytes = 0;
+                total_rows += pending_rows.len();
+                progress(Progress::Rows(std::mem::take(&mut pending_rows))).await;
+                last_flush = Instant::now();
+            }
+        }
+        pending_rows.extend(parser.finish());
+        cancel.check()?;
+        progress(Progress::Bytes(pending_bytes)).await;
+        total_rows += pending_rows.len();
+        progress(Progress::Rows(pending_rows)).await;
+        // The header row is not a record
+        Ok(total_rows.saturating_sub(1))
+    }
+
+    async fn fetch_page(self, url: Url) -> Result<(usize, Vec<Vec<String>>, Option<Vec<Column>>), ClientError> {
+        let response = ok_or_status(self.get(url).send().await?, "Page request").await?;
+        let columns = response_columns(response.headers());
+        let body = response.bytes().await?;
+        let mut parser = CsvStream::default();
+        let mut rows = parser.feed(&body);
+        rows.extend(parser.finish());
+        Ok((body.len(), rows, columns))
+    }
+
+    /// Fetches `query` with `LIMIT`/`OFFSET`, `paging.concurrency` pages at a
+    /// time, reporting every page in order. Only the first page keeps its
+    /// header row. Returns the number of records.
+    pub async fn query_paged<F, Fut>(&self, dataset: &str, query: &PagedQuery, paging: &Paging, cancel: &CancelToken, mut progress: F) -> Result<usize, ClientError>
+    where
+        F: FnMut(Progress) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        if let Some(reason) = query.unpageable() {
+            return Err(ClientError::Invalid(reason.into()));
+        }
+        let page_size = paging.page_size.max(1);
+        let mut total_rows = 0;
+        let mut pages = 0;
+        let mut next_page = 0;
+        'pages: loop {
+            // Request the next few pages at the same time
+            let mut window = vec![];
+            while window.len() < paging.concurrency.max(1) {
+                match query.page(next_page, page_size) {
+                    Some(soql) => {
+                        let url = make_query(&self.domain, dataset, &soql)?;
+                        window.push(tokio::spawn(self.clone().fetch_page(url)));
+                        next_page += 1;
+                    }
+                    None => break,
+                }
+            }
+            if window.is_empty() {
+                break;
+            }
+
+            let mut window = window.into_iter();
+            while let Some(mut task) = window.next() {
+                let page = tokio::select! {
+                    page = &mut task => page.map_err(|e| ClientError::Invalid(e.to_string())).and_then(|page| page),
+                    _ = cancel.canceled() => {
+                        task.abort();
+                        Err(ClientError::Canceled)
+                    }
+                };
+                // Nothing after a failed page is reported, so stop fetching
+                let (bytes, mut rows, columns) = match page {
+                    Ok(page) => page,
+                    Err(e) => {
+                        window.for_each(|task| task.abort());
+                        return Err(e);
+                    }
+                };
+                let records = rows.len().saturating_sub(1);
+                if pages > 0 && !rows.is_empty() {
+                    rows.remove(0);
+                }
+                if let Some(columns) = columns.filter(|_| pages == 0) {
+                    progress(Progress::Columns(columns)).await;
+                }
+                total_rows += records;
+                pages += 1;
+                progress(Progress::Bytes(bytes)).await;
+                progress(Progress::Rows(rows)).await;
+                progress(Progress::Page(pages)).await;
+                // A short page means there is nothing left to fetch
+                if records < page_size {
+                    window.for_each(|task| task.abort());
+                    break 'pages;
+                }
+            }
+        }
+        Ok(total_rows)
+    }
+
+    /// Streams the results of `query` as CSV into `path`. With `resume`, a
+    /// partial file left by an earlier attempt at the same download is
+    /// continued rather than replaced; a [`DownloadMarker`] next to the file
+    /// tells which download left it. Only [`Progress::Bytes`] is reported.
+    /// Returns the file size.
+    pub async fn download<F, Fut>(&self, dataset: &str, query: &PagedQuery, path: &Path, resume: bool, cancel: &CancelToken, mut progress: F) -> Result<u64, ClientError>
+    where
+        F: FnMut(Progress) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let soql = query
+            .remaining(0)
+            .ok_or_else(|| ClientError::Invalid("query has no rows to download".into()))?;
+        let marker = DownloadMarker {
+            domain: self.domain.to_owned(),
+            dataset: dataset.to_owned(),
+            query: soql.to_owned(),
+        };
+        // Any other file at the path is replaced, never appended to
+        let point = if resume && marker.matches(path) { resume_point(path)? } else { ResumePoint::default() };
+        let mut request = self.get(make_query(&self.domain, dataset, &soql)?);
+        if point.bytes > 0 {
+            request = request.header(RANGE, format!("bytes={}-", point.bytes));
+        }
+        let mut response = request.send().await?;
+
+        // Drop the header row when appending to records already on disk
+        let mut skip_header = false;
+        if point.bytes > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+            // The server ignored the range, so fetch what comes after the records we have
+            let soql = match query.remaining(point.records.saturating_sub(1)) {
+                Some(soql) => soql,
+                None => {
+                    DownloadMarker::remove(path)?;
+                    return Ok(point.bytes);
+                }
+            };
+            response = self.get(make_query(&self.domain, dataset, &soql)?).send().await?;
+            skip_header = true;
+        }
+        let mut response = ok_or_status(response, "Download").await?;
+
+        marker.save(path)?;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(path)
+            .await?;
+        // Cut off any half written record left by the previous attempt
+        file.set_len(point.bytes).await?;
+        file.seek(SeekFrom::End(0)).await?;
+        let mut file = tokio::io::BufWriter::new(file);
+
+        let mut written = point.bytes;
+        let mut pending_bytes = 0;
+        let mut last_flush = Instant::now();
+        while let Some(a_chunk) = response.chunk().await? {
+            if cancel.is_canceled() {
+                file.flush().await?;
+                return Err(ClientError::Canceled);
+            }
+            let mut bytes = &a_chunk[..];
+            if skip_header {
+                match bytes.iter().position(|b| *b == b'\n') {
+                    Some(i) => {
+                        bytes = &bytes[i + 1..];
+                        skip_header = false;
+                    }
+                    None => continue,
+                }
+            }
+            file.write_all(bytes).await?;
+            written += bytes.len() as u64;
+            pending_bytes += a_chunk.len();
+            if last_flush.elapsed() >= FLUSH_INTERVAL {
+                progress(Progress::Bytes(pending_bytes)).await;
+                pending_bytes = 0;
+                last_flush = Instant::now();
+            }
+        }
+        file.flush().await?;
+        DownloadMarker::remove(path)?;
+        progress(Progress::Bytes(pending_bytes)).await;
+        Ok(written)
+    }
+
+    /// Asks Socrata how it would run `soql`
+    pub async fn analyze(&self, dataset: &str, soql: &str, cancel: &CancelToken) -> Result<ExplainPlan, ClientError> {
+        let url = make_analyze_url(&self.domain, dataset, soql)?;
+        let request = async {
+            let response = ok_or_status(self.get(url).send().await?, "Analysis").await?;
+            Ok(response.json::<ExplainQuery>().await?.plan())
+        };
+        tokio::select! {
+            plan = request => plan,
+            _ = cancel.canceled() => Err(ClientError::Canceled),
+        }
+    }
+
+    /// The dataset's name, columns and column statistics
+    pub async fn metadata(&self, dataset: &str) -> Result<DatasetSchema, ClientError> {
+        let url = make_schema_url(&self.domain, dataset)?;
+        let response = ok_or_status(self.get(url).send().await?, "Loading the dataset schema").await?;
+        Ok(response.json::<DatasetSchema>().await?)
+    }
+
+    /// One page of datasets on the domain matching `search`, starting at result `offset`
+    pub async fn search_catalog(&self, search: &CatalogSearch, offset: usize) -> Result<CatalogPage, ClientError> {
+        let url = make_catalog_url(&self.domain, search, offset)?;
+        let response = ok_or_status(self.get(url).send().await?, "Searching the catalog").await?;
+        Ok(response.json::<CatalogResponse>().await?.into_page(search, offset))
+    }
+}
+
+/// The result columns from the `X-SODA2-Fields` and `X-SODA2-Types` headers, when sent
+fn response_columns(headers: &HeaderMap) -> Option<Vec<Column>> {
+    let fields = headers.get("X-SODA2-Fields")?.to_str().ok()?;
+    let types = headers.get("X-SODA2-Types")?.to_str().ok()?;
+    Column::from_headers(fields, types)
+}
+
+/// Turns an error status into a [`ClientError::Status`] with the response body
+async fn ok_or_status(response: Response, action: &'static str) -> Result<Response, ClientError> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(ClientError::Status { action, status, body })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::{ready, Ready};
+
+    use super::super::test_server::{stand_in, stand_in_replies, stand_in_silent, stand_in_with_headers, Reply};
+    use super::super::types::ColumnType;
+    use super::*;
+
+    const CSV: &str = "\"name\",\"count\"\n\"Brooklyn\",\"12\"\n\"Bronx\",\"7\"\n";
+
+    /// Collects the rows reported to it
+    fn collect(rows: &mut Vec<Vec<String>>) -> impl FnMut(Progress) -> Ready<()> + '_ {
+        move |progress| {
+            if let Progress::Rows(batch) = progress {
+                rows.extend(batch);
+            }
+            ready(())
+        }
+    }
+
+    #[test]
+    fn needs_a_valid_domain() {
+        assert!(matches!(
+            SocrataClient::new("", Auth::default()),
+            Err(ClientError::Url(UrlError::EmptyDomain))
+        ));
+    }
+
+    #[tokio::test]
+    async fn streams_query_rows() {
+        let (domain, request) = stand_in("200 OK", "text/csv; charset=utf-8", CSV).await;
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let mut rows = vec![];
+        let records = client
+            .query("abcd-1234", "select name, count", &CancelToken::default(), collect(&mut rows))
+            .await
+            .unwrap();
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("GET /resource/abcd-1234.csv?%24query=select+name%2C+count "), "{}", request);
+        assert_eq!(records, 2);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[1], vec!["Brooklyn".to_owned(), "12".to_owned()]);
+    }
+
+    #[tokio::test]
+    async fn reports_result_columns() {
+        let headers = "X-SODA2-Fields: [\"name\",\"count\"]\r\nX-SODA2-Types: [\"text\",\"number\"]\r\n";
+        let (domain, _request) = stand_in_with_headers("200 OK", "text/csv", headers, CSV).await;
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let mut columns = vec![];
+        let mut rows_first = false;
+        let progress = |progress| {
+            match progress {
+                Progress::Columns(c) => columns = c,
+                Progress::Rows(_) => rows_first |= columns.is_empty(),
+                _ => {}
+            }
+            ready(())
+        };
+        client
+            .query("abcd-1234", "select name, count", &CancelToken::default(), progress)
+            .await
+            .unwrap();
+        assert!(!rows_first);
+        assert_eq!(columns[1], Column { field: "count".into(), data_type: ColumnType::Number });
+    }
+
+    #[tokio::test]
+    async fn canceled_queries_stop() {
+        let (domain, _request) = stand_in("200 OK", "text/csv", CSV).await;
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let cancel = CancelToken::default();
+        cancel.clone().cancel();
+        let mut rows = vec![];
+        let result = client.query("abcd-1234", "select name", &cancel, collect(&mut rows)).await;
+        assert!(matches!(result, Err(ClientError::Canceled)));
+        assert!(rows.is_empty());
+    }
+
+    #[tokio::test]
+    async fn pages_stop_at_the_first_error() {
+        let ok = Reply { status: "200 OK", content_type: "text/csv", headers: "", body: CSV };
+        let failed = Reply { status: "500 Internal Server Error", content_type: "text/plain", headers: "", body: "" };
+        let (domain, requests) = stand_in_replies(vec![ok, failed]).await;
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let paging = Paging { enabled: true, page_size: 2, concurrency: 1 };
+        let mut rows = vec![];
+        let result = client
+            .query_paged("abcd-1234", &PagedQuery::new("select name, count"), &paging, &CancelToken::default(), collect(&mut rows))
+            .await;
+        assert!(matches!(result, Err(ClientError::Status { .. })), "{:?}", result);
+        assert_eq!(requests.await.unwrap().len(), 2);
+        assert_eq!(rows.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn canceling_stops_waiting_for_pages() {
+        let domain = stand_in_silent().await;
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let cancel = CancelToken::default();
+        let canceler = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(FLUSH_INTERVAL).await;
+            canceler.cancel();
+        });
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            client.query_paged("abcd-1234", &PagedQuery::new("select name"), &Paging::default(), &cancel, collect(&mut vec![])),
+        )
+        .await
+        .expect("query_paged ignored the cancel");
+        assert!(matches!(result, Err(ClientError::Canceled)));
+    }
+
+    #[tokio::test]
+    async fn canceling_stops_an_analysis() {
+        let domain = stand_in_silent().await;
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let cancel = CancelToken::default();
+        cancel.cancel();
+        let result = tokio::time::timeout(Duration::from_secs(5), client.analyze("abcd-1234", "select name", &cancel))
+            .await
+            .expect("analyze ignored the cancel");
+        assert!(matches!(result, Err(ClientError::Canceled)));
+    }
+
+    #[tokio::test]
+    async fn reports_error_statuses() {
+        let (domain, _request) = stand_in("400 Bad Request", "application/json", r#"{"message":"no such column"}"#).await;
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let err = client
+            .query("abcd-1234", "select nope", &CancelToken::default(), collect(&mut vec![]))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), r#"Query failed with 400 Bad Request: {"message":"no such column"}"#);
+    }
+
+    #[tokio::test]
+    async fn rejects_responses_that_are_not_csv() {
+        let (domain, _request) = stand_in("200 OK", "text/html", "<html></html>").await;
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let err = client
+            .query("abcd-1234", "select name", &CancelToken::default(), collect(&mut vec![]))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Expected CSV; found text/html: <html></html>");
+    }
+
+    /// A fresh download path in the temp directory, holding `partial` and,
+    /// if given, the marker of a download of `SELECT name` from `domain`
+    fn partial_download(name: &str, partial: &str, domain: Option<&str>) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("soqlstudio-client-{}-{}", std::process::id(), name));
+        std::fs::write(&path, partial).unwrap();
+        DownloadMarker::remove(&path).unwrap();
+        if let Some(domain) = domain {
+            let marker = DownloadMarker {
+                domain: domain.to_owned(),
+                dataset: "abcd-1234".into(),
+                query: PagedQuery::new("SELECT name").remaining(0).unwrap(),
+            };
+            marker.save(&path).unwrap();
+        }
+        path
+    }
+
+    fn csv_reply(status: &'static str, body: &'static str) -> Reply {
+        Reply { status, content_type: "text/csv", headers: "", body }
+    }
+
+    #[tokio::test]
+    async fn resumes_downloads_with_a_range() {
+        let (domain, requests) = stand_in_replies(vec![csv_reply("206 Partial Content", "\"Bronx\"\n")]).await;
+        let path = partial_download("range.csv", "\"name\"\n\"Brooklyn\"\n\"Bro", Some(&domain));
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let query = PagedQuery::new("SELECT name");
+        let size = client
+            .download("abcd-1234", &query, &path, true, &CancelToken::default(), |_| ready(()))
+            .await
+            .unwrap();
+
+        let requests = requests.await.unwrap();
+        assert!(requests[0].to_ascii_lowercase().contains("range: bytes=18-"), "{}", requests[0]);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, "\"name\"\n\"Brooklyn\"\n\"Bronx\"\n");
+        assert_eq!(size, contents.len() as u64);
+        assert!(!DownloadMarker::path(&path).exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resumes_downloads_with_an_offset_when_ranges_are_ignored() {
+        let replies = vec![
+            csv_reply("200 OK", "\"name\"\n\"Brooklyn\"\n\"Bronx\"\n"),
+            csv_reply("200 OK", "\"name\"\n\"Bronx\"\n"),
+        ];
+        let (domain, requests) = stand_in_replies(replies).await;
+        let path = partial_download("offset.csv", "\"name\"\n\"Brooklyn\"\n", Some(&domain));
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let query = PagedQuery::new("SELECT name");
+        client
+            .download("abcd-1234", &query, &path, true, &CancelToken::default(), |_| ready(()))
+            .await
+            .unwrap();
+
+        let requests = requests.await.unwrap();
+        let second = requests[1].lines().next().unwrap();
+        assert!(second.contains("ORDER+BY+%3Aid+OFFSET+1 "), "{}", second);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "\"name\"\n\"Brooklyn\"\n\"Bronx\"\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn replaces_files_left_by_other_downloads() {
+        let (domain, requests) = stand_in_replies(vec![csv_reply("200 OK", "\"name\"\n\"Bronx\"\n")]).await;
+        let path = partial_download("unrelated.csv", "id,total\n1,2\n3,4\n", None);
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let query = PagedQuery::new("SELECT name");
+        client
+            .download("abcd-1234", &query, &path, true, &CancelToken::default(), |_| ready(()))
+            .await
+            .unwrap();
+
+        let requests = requests.await.unwrap();
+        assert!(!requests[0].to_ascii_lowercase().contains("range:"), "{}", requests[0]);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "\"name\"\n\"Bronx\"\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn loads_metadata() {
+        let body = r#"{"id": "abcd-1234", "name": "Boroughs", "columns": [
+            {"name": "Name", "fieldName": "name", "dataTypeName": "text"}
+        ]}"#;
+        let (domain, request) = stand_in("200 OK", "application/json", body).await;
+        let client = SocrataClient::new(&domain, Auth::default()).unwrap();
+        let schema = client.metadata("abcd-1234").await.unwrap();
+        assert!(request.await.unwrap().starts_with("GET /api/views/abcd-1234.json "));
+        assert_eq!(schema.name, "Boroughs");
+        assert_eq!(schema.columns[0].field_name, "name");
+    }
+}
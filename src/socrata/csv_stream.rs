@@ -0,0 +1,155 @@
+use csv_core::{ReadRecordResult, Reader};
+
+/// Incremental CSV parser which accepts the response body chunk by chunk
+/// and hands back every record completed so far.
+pub struct CsvStream {
+    reader: Reader,
+    output: Vec<u8>,
+    output_len: usize,
+    ends: Vec<usize>,
+    ends_len: usize,
+}
+
+impl Default for CsvStream {
+    fn default() -> Self {
+        Self {
+            reader: Reader::new(),
+            output: vec![0; 4096],
+            output_len: 0,
+            ends: vec![0; 64],
+            ends_len: 0,
+        }
+    }
+}
+
+impl CsvStream {
+    /// Parse the next chunk of the body. A record split across chunks is
+    /// returned once the chunk containing its end has been fed.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<Vec<String>> {
+        self.read(chunk, false)
+    }
+
+    /// Signal the end of the body and return the trailing record, if any
+    pub fn finish(&mut self) -> Vec<Vec<String>> {
+        self.read(&[], true)
+    }
+
+    fn read(&mut self, mut input: &[u8], eof: bool) -> Vec<Vec<String>> {
+        let mut records = vec![];
+        // csv_core treats an empty input as the end of the stream
+        while !input.is_empty() || eof {
+            let (result, read, written, ended) = self.reader.read_record(
+                input,
+                &mut self.output[self.output_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            input = &input[read..];
+            self.output_len += written;
+            self.ends_len += ended;
+            match result {
+                ReadRecordResult::InputEmpty => {}
+                ReadRecordResult::OutputFull => {
+                    let len = self.output.len();
+                    self.output.resize(len * 2, 0);
+                }
+                ReadRecordResult::OutputEndsFull => {
+                    let len = self.ends.len();
+                    self.ends.resize(len * 2, 0);
+                }
+                ReadRecordResult::Record => records.push(self.take_record()),
+                ReadRecordResult::End => break,
+            }
+        }
+        records
+    }
+
+    fn take_record(&mut self) -> Vec<String> {
+        let mut start = 0;
+        let record = self.ends[..self.ends_len]
+            .iter()
+            .map(|&end| {
+                let field = String::from_utf8_lossy(&self.output[start..end]).into_owned();
+                start = end;
+                field
+            })
+            .collect();
+        self.output_len = 0;
+        self.ends_len = 0;
+        record
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(record: &[&str]) -> Vec<String> {
+        record.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// Feeds `body` in chunks of `size` bytes and collects every record
+    fn parse_in_chunks(body: &[u8], size: usize) -> Vec<Vec<String>> {
+        let mut parser = CsvStream::default();
+        let mut records = vec![];
+        for chunk in body.chunks(size) {
+            records.extend(parser.feed(chunk));
+        }
+        records.extend(parser.finish());
+        records
+    }
+
+    #[test]
+    fn records_split_across_chunks() {
+        let body = b"\"name\",\"count\"\n\"Brooklyn\",\"12\"\n\"Bronx\",\"7\"\n";
+        for size in 1..body.len() {
+            assert_eq!(
+                parse_in_chunks(body, size),
+                vec![strings(&["name", "count"]), strings(&["Brooklyn", "12"]), strings(&["Bronx", "7"])],
+                "chunks of {} bytes",
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn quoted_fields_split_across_chunks() {
+        let body = "\"note\",\"id\"\n\"line one\nline \"\"two\"\", too\",\"1\"\n".as_bytes();
+        for size in 1..body.len() {
+            assert_eq!(
+                parse_in_chunks(body, size),
+                vec![strings(&["note", "id"]), strings(&["line one\nline \"two\", too", "1"])],
+                "chunks of {} bytes",
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn last_record_without_newline() {
+        let mut parser = CsvStream::default();
+        assert_eq!(parser.feed(b"a,b\n1,2"), vec![strings(&["a", "b"])]);
+        assert_eq!(parser.finish(), vec![strings(&["1", "2"])]);
+    }
+
+    #[test]
+    fn multibyte_characters_split_across_chunks() {
+        let body = "\"borough\"\n\"Bronx ✓ Ñ\"\n".as_bytes();
+        for size in 1..body.len() {
+            assert_eq!(
+                parse_in_chunks(body, size),
+                vec![strings(&["borough"]), strings(&["Bronx ✓ Ñ"])],
+                "chunks of {} bytes",
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn long_records_grow_the_buffers() {
+        let field = "x".repeat(10_000);
+        let header: Vec<String> = (0..100).map(|i| format!("c{}", i)).collect();
+        let body = format!("{}\n{}\n", header.join(","), field);
+        let records = parse_in_chunks(body.as_bytes(), 1000);
+        assert_eq!(records, vec![header, vec![field]]);
+    }
+}
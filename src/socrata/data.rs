@@ -1,10 +1,31 @@
+use std::sync::Arc;
 use std::time::Duration;
 
+use super::client::Progress;
+use super::schema::DatasetSchema;
+use super::types::{column_types, Column, ColumnType};
 
 #[allow(dead_code)]
 pub enum Channel {
     Data(usize),
     Elapsed(Duration),
+    /// Records parsed so far; the very first one is the header row
+    Rows(Vec<Vec<String>>),
+    /// Number of pages fetched so far when paging through a query
+    Page(usize),
+    /// Columns and types of the results, from the response headers
+    Columns(Vec<Column>),
+}
+
+impl From<Progress> for Channel {
+    fn from(progress: Progress) -> Self {
+        match progress {
+            Progress::Bytes(b) => Channel::Data(b),
+            Progress::Rows(rows) => Channel::Rows(rows),
+            Progress::Page(p) => Channel::Page(p),
+            Progress::Columns(columns) => Channel::Columns(columns),
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -15,24 +36,43 @@ pub enum ErrCause {
 
 #[allow(dead_code)]
 pub enum Container {
-    Data(Vec<Vec<String>>),
+    /// Number of records received once the download has completed
+    Rows(usize),
+    /// Bytes on disk once a download to file has completed
+    Downloaded(u64),
     Elapsed(Duration),
 }
 
 #[derive(Default)]
 pub struct ResponseData {
-    pub data: Option<Vec<Vec<String>>>,
+    /// Shared so that exports can write the rows without copying them
+    pub data: Option<Arc<Vec<Vec<String>>>>,
+    /// Column types the server reported; empty when it left them out
+    pub columns: Vec<Column>,
     pub file_size: usize,
     pub tmp_file_size: usize,
+    pub pages: usize,
     pub is_running: bool,
     pub error: Option<String>,
-    pub seed: usize,
+    pub message: Option<String>,
 }
 
 impl ResponseData {
-    pub fn set_data(&mut self, data: Vec<Vec<String>>) {
-        self.error.take();
-        self.data = Some(data);
+    pub fn append_rows(&mut self, rows: Vec<Vec<String>>) {
+        match self.data.as_mut() {
+            // Only copies when an export of the rows so far is still running
+            Some(data) => Arc::make_mut(data).extend(rows),
+            None => self.data = Some(Arc::new(rows)),
+        }
+    }
+
+    /// The type of each column of `data`, falling back on `schema` for the
+    /// ones the server didn't report
+    pub fn column_types(&self, schema: Option<&DatasetSchema>) -> Vec<ColumnType> {
+        match self.data.as_ref().and_then(|data| data.first()) {
+            Some(header) => column_types(header, &self.columns, schema),
+            None => vec![],
+        }
     }
 
     pub fn set_error(&mut self, e: impl ToString) {
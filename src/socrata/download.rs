@@ -0,0 +1,155 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use csv_core::{ReadRecordResult, Reader};
+use serde_derive::{Deserialize, Serialize};
+
+/// What a download is fetching, kept in a file next to it until it completes
+/// so that a partial file is only ever continued by the same download
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct DownloadMarker {
+    pub domain: String,
+    pub dataset: String,
+    pub query: String,
+}
+
+impl DownloadMarker {
+    /// `data.csv` is marked by `data.csv.resume`
+    pub fn path(download: &Path) -> PathBuf {
+        let mut name = download.file_name().unwrap_or_default().to_owned();
+        name.push(".resume");
+        download.with_file_name(name)
+    }
+
+    /// Whether the partial file at `download` was left by this same download
+    pub fn matches(&self, download: &Path) -> bool {
+        fs::read_to_string(Self::path(download))
+            .ok()
+            .and_then(|marker| serde_json::from_str::<DownloadMarker>(&marker).ok())
+            .map_or(false, |marker| &marker == self)
+    }
+
+    pub fn save(&self, download: &Path) -> io::Result<()> {
+        fs::write(Self::path(download), serde_json::to_string(self)?)
+    }
+
+    /// Forgets the marker once the download is complete
+    pub fn remove(download: &Path) -> io::Result<()> {
+        match fs::remove_file(Self::path(download)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Where a partial download left off
+#[derive(Debug, Default, PartialEq)]
+pub struct ResumePoint {
+    /// Length of the file up to the end of the last complete record
+    pub bytes: u64,
+    /// Complete records on disk, including the header row
+    pub records: usize,
+}
+
+/// Scans a partially downloaded CSV file for the last complete record.
+/// A missing file resumes from the start.
+pub fn resume_point(path: &Path) -> io::Result<ResumePoint> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ResumePoint::default()),
+        Err(e) => return Err(e),
+    };
+    let mut reader = Reader::new();
+    let mut point = ResumePoint::default();
+    let mut consumed = 0u64;
+    let mut input = vec![0; 64 * 1024];
+    let mut output = vec![0; 64 * 1024];
+    let mut ends = vec![0; 1024];
+    loop {
+        let len = file.read(&mut input)?;
+        // Never pass an empty slice, csv_core would treat it as a final record
+        if len == 0 {
+            break;
+        }
+        let mut chunk = &input[..len];
+        while !chunk.is_empty() {
+            let (result, read, _, _) = reader.read_record(chunk, &mut output, &mut ends);
+            chunk = &chunk[read..];
+            consumed += read as u64;
+            match result {
+                ReadRecordResult::Record => {
+                    point.records += 1;
+                    point.bytes = consumed;
+                }
+                // Field contents are not needed, only where records end
+                ReadRecordResult::OutputFull | ReadRecordResult::OutputEndsFull => {
+                    if read == 0 {
+                        output.resize(output.len() * 2, 0);
+                        ends.resize(ends.len() * 2, 0);
+                    }
+                }
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => {}
+            }
+        }
+    }
+    Ok(point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh path in the temp directory, without the file or its marker
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("soqlstudio-download-{}-{}", std::process::id(), name));
+        let _ = fs::remove_file(&path);
+        let _ = DownloadMarker::remove(&path);
+        path
+    }
+
+    #[test]
+    fn missing_files_start_over() {
+        let path = temp_path("missing.csv");
+        assert_eq!(resume_point(&path).unwrap(), ResumePoint::default());
+    }
+
+    #[test]
+    fn finds_the_last_complete_record() {
+        let path = temp_path("partial.csv");
+        let complete = "\"name\",\"note\"\n\"Bronx\",\"two\nlines\"\n";
+        fs::write(&path, format!("{}\"Brookl", complete)).unwrap();
+        assert_eq!(
+            resume_point(&path).unwrap(),
+            ResumePoint { bytes: complete.len() as u64, records: 2 }
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn complete_files_resume_at_the_end() {
+        let path = temp_path("complete.csv");
+        fs::write(&path, "a\n1\n2\n").unwrap();
+        assert_eq!(resume_point(&path).unwrap(), ResumePoint { bytes: 6, records: 3 });
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn markers_only_match_the_same_download() {
+        let path = temp_path("marked.csv");
+        let marker = DownloadMarker {
+            domain: "data.example.com".into(),
+            dataset: "abcd-1234".into(),
+            query: "SELECT a ORDER BY :id".into(),
+        };
+        assert!(!marker.matches(&path));
+        marker.save(&path).unwrap();
+        assert!(marker.matches(&path));
+        let other = DownloadMarker { query: "SELECT b ORDER BY :id".into(), ..marker.clone() };
+        assert!(!other.matches(&path));
+
+        DownloadMarker::remove(&path).unwrap();
+        assert!(!marker.matches(&path));
+        DownloadMarker::remove(&path).unwrap();
+    }
+}
@@ -1,7 +1,25 @@
+use std::error::Error;
+use std::fmt;
+
 use regex::Regex;
+use reqwest::Url;
 use serde_derive::{Deserialize, Serialize};
 pub mod data;
 pub mod analysis;
+pub mod csv_stream;
+pub mod paging;
+pub mod download;
+pub mod auth;
+pub mod schema;
+pub mod catalog;
+pub mod soql;
+pub mod client;
+pub mod types;
+mod sanitize;
+#[cfg(test)]
+mod test_server;
+
+use sanitize::sanitize;
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct ExplainQuery {
@@ -9,20 +27,183 @@ pub struct ExplainQuery {
     pub explain_plan: String
 }
 
-fn sanitize(q: &str) -> String {
-    let rm_spaces = Regex::new(r"[\s]+").unwrap();
-    let a = q.replace("\n", " ");
-    let b = a.as_str().replace("\t", "");
-    let c = b.as_str().trim();
-    let d = rm_spaces.replace_all(c, " ");
-    d.to_string()
+impl ExplainQuery {
+    pub fn plan(&self) -> analysis::ExplainPlan {
+        analysis::ExplainPlan::parse(&self.explain_plan)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum UrlError {
+    EmptyDomain,
+    InvalidDomain(String),
+    UnsupportedScheme(String),
+    InvalidDataset(String),
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UrlError::EmptyDomain => write!(f, "Enter a domain, e.g. data.cityofnewyork.us"),
+            UrlError::InvalidDomain(d) => write!(f, "'{}' is not a valid domain", d),
+            UrlError::UnsupportedScheme(s) => write!(f, "Only http and https are supported, not '{}'", s),
+            UrlError::InvalidDataset(d) => write!(f, "'{}' is not a dataset ID like abcd-1234", d),
+        }
+    }
+}
+
+impl Error for UrlError {}
+
+/// Parses `domain` as a bare host (`data.example.gov`, optionally with a port)
+/// or a full `http(s)://` origin. Anything beyond the origin is rejected.
+pub fn base_url(domain: &str) -> Result<Url, UrlError> {
+    let domain = domain.trim().trim_end_matches('/');
+    if domain.is_empty() {
+        return Err(UrlError::EmptyDomain);
+    }
+    let with_scheme = if domain.contains("://") {
+        domain.to_owned()
+    } else {
+        format!("https://{}", domain)
+    };
+    let url = Url::parse(&with_scheme).map_err(|_| UrlError::InvalidDomain(domain.to_owned()))?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(UrlError::UnsupportedScheme(url.scheme().to_owned()));
+    }
+    let is_origin = url.host().is_some()
+        && url.path() == "/"
+        && url.query().is_none()
+        && url.fragment().is_none()
+        && url.username().is_empty()
+        && url.password().is_none();
+    if !is_origin {
+        return Err(UrlError::InvalidDomain(domain.to_owned()));
+    }
+    Ok(url)
+}
+
+/// Checks for a four-by-four dataset ID such as `abcd-1234`
+pub fn validate_dataset(dataset: &str) -> Result<&str, UrlError> {
+    let four_by_four = Regex::new(r"^[a-z0-9]{4}-[a-z0-9]{4}$").unwrap();
+    let dataset = dataset.trim();
+    if four_by_four.is_match(dataset) {
+        Ok(dataset)
+    } else {
+        Err(UrlError::InvalidDataset(dataset.to_owned()))
+    }
+}
+
+pub fn make_query(domain: &str, dataset: &str, query: &str) -> Result<Url, UrlError> {
+    let mut url = base_url(domain)?;
+    url.set_path(&format!("/resource/{}.csv", validate_dataset(dataset)?));
+    url.query_pairs_mut().append_pair("$query", &sanitize(query));
+    Ok(url)
 }
-pub fn make_query(domain: &str, dataset: &str, query: &str) -> String {
-    let query_string = format!("https://{}/resource/{}.csv?$query={}", domain, dataset, sanitize(query));
-    query_string
+
+pub fn make_analyze_url(domain: &str, dataset: &str, query: &str) -> Result<Url, UrlError> {
+    let mut url = base_url(domain)?;
+    url.set_path(&format!("/api/views/{}/query_info", validate_dataset(dataset)?));
+    url.query_pairs_mut()
+        .append_pair("analyze", "true")
+        .append_pair("query", &sanitize(query));
+    Ok(url)
+}
+
+pub fn make_schema_url(domain: &str, dataset: &str) -> Result<Url, UrlError> {
+    let mut url = base_url(domain)?;
+    url.set_path(&format!("/api/views/{}.json", validate_dataset(dataset)?));
+    Ok(url)
 }
 
-pub fn make_analyze_url(domain: &str, dataset: &str, query: &str) -> String {
-    let query_string = format!("https://{}/api/views/{}/query_info?analyze=true&query={}", domain, dataset, sanitize(query));
-    query_string
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query_param(url: &Url, name: &str) -> String {
+        url.query_pairs().find(|(k, _)| k == name).map(|(_, v)| v.into_owned()).unwrap()
+    }
+
+    #[test]
+    fn domains_become_origins() {
+        assert_eq!(base_url("data.example.gov").unwrap().as_str(), "https://data.example.gov/");
+        assert_eq!(base_url("  data.example.gov/ ").unwrap().as_str(), "https://data.example.gov/");
+        assert_eq!(base_url("https://data.example.gov").unwrap().as_str(), "https://data.example.gov/");
+
+        let local = base_url("http://localhost:8080/").unwrap();
+        assert_eq!((local.scheme(), local.port()), ("http", Some(8080)));
+        assert_eq!(base_url("data.example.gov:8443").unwrap().port(), Some(8443));
+        // The default port is dropped
+        assert_eq!(base_url("https://data.example.gov:443").unwrap().port(), None);
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_domain() {
+        assert_eq!(base_url(""), Err(UrlError::EmptyDomain));
+        assert_eq!(base_url(" / "), Err(UrlError::EmptyDomain));
+        assert_eq!(base_url("ftp://data.example.gov"), Err(UrlError::UnsupportedScheme("ftp".into())));
+        for domain in [
+            "data example.gov",
+            "data.example.gov/resource",
+            "data.example.gov?x=1",
+            "data.example.gov#top",
+            "user:pass@data.example.gov",
+            "data.example.gov:99999",
+        ] {
+            assert_eq!(base_url(domain), Err(UrlError::InvalidDomain(domain.into())), "{}", domain);
+        }
+    }
+
+    #[test]
+    fn datasets_are_four_by_fours() {
+        assert_eq!(validate_dataset(" abcd-1234 "), Ok("abcd-1234"));
+        for dataset in ["", "ABCD-1234", "abcd1234", "abcd-12345", "abcd-1234/rows", "../abcd-1234", "abcd-1234?x=1"] {
+            assert_eq!(validate_dataset(dataset), Err(UrlError::InvalidDataset(dataset.trim().into())), "{}", dataset);
+        }
+        assert_eq!(
+            make_query("data.example.gov", "abcd-1234.json", "SELECT a"),
+            Err(UrlError::InvalidDataset("abcd-1234.json".into()))
+        );
+    }
+
+    #[test]
+    fn url_errors_read_well() {
+        assert_eq!(UrlError::EmptyDomain.to_string(), "Enter a domain, e.g. data.cityofnewyork.us");
+        assert_eq!(UrlError::InvalidDomain("x y".into()).to_string(), "'x y' is not a valid domain");
+        assert_eq!(UrlError::UnsupportedScheme("ftp".into()).to_string(), "Only http and https are supported, not 'ftp'");
+        assert_eq!(UrlError::InvalidDataset("abc".into()).to_string(), "'abc' is not a dataset ID like abcd-1234");
+    }
+
+    #[test]
+    fn queries_are_encoded() {
+        let query = "SELECT name WHERE note = 'A&B #1 + 50% café' AND x = \"?\"";
+        let url = make_query("data.example.gov", "abcd-1234", query).unwrap();
+        assert_eq!(url.path(), "/resource/abcd-1234.csv");
+        let raw = url.query().unwrap();
+        assert!(raw.starts_with("%24query=SELECT+name+WHERE"), "{}", raw);
+        for encoded in ["A%26B", "%231", "%2B+50%25", "caf%C3%A9", "%22%3F%22"] {
+            assert!(raw.contains(encoded), "{} in {}", encoded, raw);
+        }
+        assert!(!raw.contains('#') && !raw.contains(' '));
+        // Decoding gives back exactly the query that was sent
+        assert_eq!(query_param(&url, "$query"), query);
+    }
+
+    #[test]
+    fn queries_are_sanitized_before_encoding() {
+        let url = make_query("data.example.gov", "abcd-1234", "SELECT a -- & not this\n  WHERE b = '--&'").unwrap();
+        assert_eq!(query_param(&url, "$query"), "SELECT a WHERE b = '--&'");
+    }
+
+    #[test]
+    fn analyze_urls_carry_the_query() {
+        let url = make_analyze_url("http://localhost:8080", "abcd-1234", "SELECT a WHERE b = '1&2'").unwrap();
+        assert_eq!(url.origin().ascii_serialization(), "http://localhost:8080");
+        assert_eq!(url.path(), "/api/views/abcd-1234/query_info");
+        assert_eq!(query_param(&url, "analyze"), "true");
+        assert_eq!(query_param(&url, "query"), "SELECT a WHERE b = '1&2'");
+        assert!(url.query().unwrap().contains("1%262"));
+
+        assert_eq!(make_schema_url("data.example.gov", "abcd-1234").unwrap().as_str(), "https://data.example.gov/api/views/abcd-1234.json");
+        assert!(make_analyze_url("", "abcd-1234", "SELECT a").is_err());
+    }
 }
@@ -0,0 +1,330 @@
+use serde_derive::{Deserialize, Serialize};
+
+use super::soql::{tokenize, Token, TokenKind, FUNCTIONS};
+
+/// Settings for fetching a query page by page with `LIMIT`/`OFFSET`
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Paging {
+    pub enabled: bool,
+    pub page_size: usize,
+    /// How many pages are requested at the same time
+    pub concurrency: usize,
+}
+
+impl Default for Paging {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            page_size: 50_000,
+            concurrency: 4,
+        }
+    }
+}
+
+/// A sanitized query rewritten so that it can be fetched one page at a time
+#[derive(Debug, PartialEq)]
+pub struct PagedQuery {
+    base: String,
+    offset: usize,
+    limit: Option<usize>,
+    /// Why the pages would not come in a stable order, if they wouldn't
+    unpageable: Option<&'static str>,
+}
+
+/// Keywords that end a clause, e.g. the select list or the `GROUP BY` keys
+const CLAUSE_ENDS: [&str; 12] = [
+    "from", "join", "left", "right", "full", "where", "group", "having", "order", "search", "limit", "offset",
+];
+
+/// Whether the tokens at `i` are `first` followed by `second`, e.g. `ORDER BY`
+fn is_pair(tokens: &[Token], i: usize, first: &str, second: &str) -> bool {
+    tokens[i].is_keyword(first) && tokens.get(i + 1).map_or(false, |t| t.is_keyword(second))
+}
+
+/// Nesting depth before each token, counting parentheses
+fn depths(tokens: &[Token]) -> Vec<usize> {
+    let mut depth = 0usize;
+    tokens
+        .iter()
+        .map(|token| {
+            let before = depth;
+            match token.kind {
+                TokenKind::LParen => depth += 1,
+                TokenKind::RParen => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            before
+        })
+        .collect()
+}
+
+/// Whether an aggregate such as `count(*)` is called, not counting window
+/// functions like `count(*) OVER (...)`, which keep every row
+fn calls_aggregate(tokens: &[Token]) -> bool {
+    let depths = depths(tokens);
+    (0..tokens.len()).any(|i| {
+        let name = match &tokens[i].kind {
+            TokenKind::Ident(name) => name,
+            _ => return false,
+        };
+        if !matches!(tokens.get(i + 1).map(|t| &t.kind), Some(TokenKind::LParen)) {
+            return false;
+        }
+        if !FUNCTIONS.iter().any(|f| f.aggregate && f.name.eq_ignore_ascii_case(name)) {
+            return false;
+        }
+        let close = (i + 2..tokens.len()).find(|&j| depths[j] == depths[i] + 1 && tokens[j].kind == TokenKind::RParen);
+        !matches!(close.and_then(|j| tokens.get(j + 1)), Some(t) if t.is_keyword("over"))
+    })
+}
+
+/// The columns of a `SELECT DISTINCT`, as an `ORDER BY` refers to them: the
+/// alias when there is one, otherwise the expression. `None` when `*` is
+/// selected, as the columns aren't known then.
+fn distinct_columns(stage: &[Token], depths: &[usize], query: &str) -> Option<String> {
+    let select = (0..stage.len()).find(|&i| depths[i] == 0 && is_pair(stage, i, "select", "distinct"))?;
+    let end = (select + 2..stage.len())
+        .find(|&j| depths[j] == 0 && CLAUSE_ENDS.iter().any(|k| stage[j].is_keyword(k)))
+        .unwrap_or(stage.len());
+    let mut columns = vec![];
+    let mut item = vec![];
+    for i in select + 2..=end {
+        if i < end && !(depths[i] == 0 && stage[i].kind == TokenKind::Comma) {
+            if !matches!(stage[i].kind, TokenKind::Comment(_)) {
+                item.push(&stage[i]);
+            }
+            continue;
+        }
+        let column = match item.as_slice() {
+            [.., last] if matches!(&last.kind, TokenKind::Operator("*")) || last.kind == TokenKind::SystemIdent(":*".into()) => return None,
+            [.., as_, alias] if as_.is_keyword("as") => &query[alias.span.clone()],
+            [first, .., last] => &query[first.span.start..last.span.end],
+            [only] => &query[only.span.clone()],
+            [] => return None,
+        };
+        columns.push(column);
+        item.clear();
+    }
+    Some(columns.join(", "))
+}
+
+impl PagedQuery {
+    /// Sanitizes the query and strips a trailing `LIMIT`/`OFFSET`, keeping them
+    /// as the window to page through. An `ORDER BY` is added when there is none
+    /// so that pages are stable between requests: the `GROUP BY` keys of a
+    /// grouped query, the selected columns of a `DISTINCT` one, otherwise
+    /// `:id`. Aggregates without a `GROUP BY` and set operations can't be
+    /// ordered by `:id`, so they are left as they are, and `SELECT DISTINCT *`
+    /// can't be paged at all. Queries that don't tokenize are only sanitized.
+    pub fn new(query: &str) -> Self {
+        let mut base = super::sanitize(query);
+        let mut limit = None;
+        let mut offset = 0;
+        let mut tokens = match tokenize(&base) {
+            Ok(tokens) => tokens,
+            Err(_) => return Self { base, offset, limit, unpageable: None },
+        };
+
+        // LIMIT and OFFSET may come in either order
+        let mut offset_seen = false;
+        while tokens.len() >= 2 {
+            let value = match &tokens[tokens.len() - 1].kind {
+                TokenKind::Number(n) => n.parse().ok(),
+                _ => None,
+            };
+            let keyword = &tokens[tokens.len() - 2];
+            match value {
+                Some(value) if keyword.is_keyword("limit") && limit.is_none() => limit = Some(value),
+                Some(value) if keyword.is_keyword("offset") && !offset_seen => {
+                    offset = value;
+                    offset_seen = true;
+                }
+                _ => break,
+            }
+            base.truncate(keyword.span.start);
+            tokens.truncate(tokens.len() - 2);
+        }
+        base.truncate(base.trim_end().len());
+
+        // Only the last stage of a piped query decides the row order
+        let depths = depths(&tokens);
+        let stage_start = (0..tokens.len())
+            .rev()
+            .find(|&i| depths[i] == 0 && tokens[i].kind == TokenKind::Operator("|>"))
+            .map_or(0, |i| i + 1);
+        let stage = &tokens[stage_start..];
+        let depths = &depths[stage_start..];
+        let top_level = || (0..stage.len()).filter(|&i| depths[i] == 0);
+
+        let ordered = top_level().any(|i| is_pair(stage, i, "order", "by"));
+        let distinct = top_level().any(|i| is_pair(stage, i, "select", "distinct"));
+        let set_operation = top_level().any(|i| ["union", "intersect", "minus"].iter().any(|op| stage[i].is_keyword(op)));
+        let group_keys = top_level().find(|&i| is_pair(stage, i, "group", "by")).map(|i| {
+            let first = i + 2;
+            let end = (first..stage.len())
+                .find(|&j| {
+                    depths[j] == 0
+                        && CLAUSE_ENDS.iter().any(|k| stage[j].is_keyword(k))
+                })
+                .unwrap_or(stage.len());
+            match stage.get(first..end) {
+                Some([first, .., last]) => base[first.span.start..last.span.end].to_owned(),
+                Some([only]) => base[only.span.clone()].to_owned(),
+                _ => String::new(),
+            }
+        });
+
+        let mut unpageable = None;
+        let order = match group_keys {
+            Some(keys) if !keys.is_empty() => Some(keys),
+            Some(_) => None,
+            None if set_operation => None,
+            None if distinct => {
+                let columns = distinct_columns(stage, depths, &base);
+                if columns.is_none() && !ordered {
+                    unpageable = Some("SELECT DISTINCT * has no stable order to page by; name the columns, add an ORDER BY or turn paging off");
+                }
+                columns
+            }
+            None if calls_aggregate(stage) => None,
+            None => Some(":id".to_owned()),
+        };
+        if let Some(order) = order.filter(|_| !ordered) {
+            base = format!("{} ORDER BY {}", base, order);
+        }
+
+        Self { base, offset, limit, unpageable }
+    }
+
+    /// Why the query can't be fetched page by page, if it can't
+    pub fn unpageable(&self) -> Option<&'static str> {
+        self.unpageable
+    }
+
+    /// The query for page `index`, or `None` once the original limit is exhausted
+    pub fn page(&self, index: usize, page_size: usize) -> Option<String> {
+        let start = index * page_size;
+        let size = match self.limit {
+            Some(limit) if start >= limit => return None,
+            Some(limit) => page_size.min(limit - start),
+            None => page_size,
+        };
+        Some(format!("{} LIMIT {} OFFSET {}", self.base, size, self.offset + start))
+    }
+
+    /// The query for everything after the first `rows` records, or `None`
+    /// when the original limit has already been reached
+    pub fn remaining(&self, rows: usize) -> Option<String> {
+        let base = match self.limit {
+            Some(limit) if rows >= limit => return None,
+            Some(limit) => format!("{} LIMIT {}", self.base, limit - rows),
+            None => self.base.to_owned(),
+        };
+        Some(format!("{} OFFSET {}", base, self.offset + rows))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base(query: &str) -> String {
+        PagedQuery::new(query).base
+    }
+
+    #[test]
+    fn adds_a_stable_order() {
+        assert_eq!(base("SELECT a WHERE b > 1"), "SELECT a WHERE b > 1 ORDER BY :id");
+        assert_eq!(base("SELECT a ORDER BY a DESC"), "SELECT a ORDER BY a DESC");
+        assert_eq!(
+            base("SELECT a, count(*) GROUP BY a HAVING count(*) > 1"),
+            "SELECT a, count(*) GROUP BY a HAVING count(*) > 1 ORDER BY a"
+        );
+        assert_eq!(
+            base("SELECT a, count(*) OVER (PARTITION BY a)"),
+            "SELECT a, count(*) OVER (PARTITION BY a) ORDER BY :id"
+        );
+    }
+
+    #[test]
+    fn leaves_queries_that_cannot_be_ordered_by_id() {
+        assert_eq!(base("SELECT count(*) WHERE a = 1"), "SELECT count(*) WHERE a = 1");
+        assert_eq!(base("SELECT max(a) + 1"), "SELECT max(a) + 1");
+        assert_eq!(base("SELECT a UNION SELECT a FROM @efgh-5678"), "SELECT a UNION SELECT a FROM @efgh-5678");
+    }
+
+    #[test]
+    fn orders_distinct_queries_by_their_columns() {
+        assert_eq!(base("SELECT DISTINCT a"), "SELECT DISTINCT a ORDER BY a");
+        assert_eq!(
+            base("SELECT DISTINCT a, upper(b) AS c, coalesce(d, 'x') -- last\nWHERE e > 1"),
+            "SELECT DISTINCT a, upper(b) AS c, coalesce(d, 'x') WHERE e > 1 ORDER BY a, c, coalesce(d, 'x')"
+        );
+        assert_eq!(base("SELECT DISTINCT a ORDER BY a DESC"), "SELECT DISTINCT a ORDER BY a DESC");
+        assert_eq!(PagedQuery::new("SELECT DISTINCT a").unpageable(), None);
+    }
+
+    #[test]
+    fn refuses_to_page_distinct_star() {
+        let query = PagedQuery::new("SELECT DISTINCT * WHERE a = 1");
+        assert_eq!(query.base, "SELECT DISTINCT * WHERE a = 1");
+        assert!(query.unpageable().is_some());
+        assert_eq!(PagedQuery::new("SELECT DISTINCT * ORDER BY a").unpageable(), None);
+    }
+
+    #[test]
+    fn ignores_keywords_in_strings_and_comments() {
+        assert_eq!(base("SELECT a WHERE note = 'order by'"), "SELECT a WHERE note = 'order by' ORDER BY :id");
+        assert_eq!(base("SELECT a WHERE note = 'x |> y'"), "SELECT a WHERE note = 'x |> y' ORDER BY :id");
+        assert_eq!(base("SELECT a WHERE `group by` = 1"), "SELECT a WHERE `group by` = 1 ORDER BY :id");
+        let query = PagedQuery::new("SELECT a -- order by\nLIMIT 10");
+        assert_eq!(query.base, "SELECT a ORDER BY :id");
+        assert_eq!(query.limit, Some(10));
+    }
+
+    #[test]
+    fn only_the_last_stage_is_ordered() {
+        assert_eq!(base("SELECT a, b |> SELECT a ORDER BY a"), "SELECT a, b |> SELECT a ORDER BY a");
+        assert_eq!(base("SELECT a ORDER BY a |> SELECT count(a)"), "SELECT a ORDER BY a |> SELECT count(a)");
+    }
+
+    #[test]
+    fn strips_trailing_limit_and_offset() {
+        for query in ["SELECT a LIMIT 25 OFFSET 5", "SELECT a OFFSET 5 LIMIT 25"] {
+            let query = PagedQuery::new(query);
+            assert_eq!(query, PagedQuery { base: "SELECT a ORDER BY :id".into(), offset: 5, limit: Some(25), unpageable: None });
+        }
+        // Only the trailing clauses are the window; a LIMIT inside a subquery stays
+        let query = PagedQuery::new("SELECT a JOIN (SELECT b LIMIT 5) AS t ON a = t.b");
+        assert_eq!(query.limit, None);
+        assert!(query.base.contains("LIMIT 5)"));
+    }
+
+    #[test]
+    fn unlexable_queries_are_only_sanitized() {
+        assert_eq!(
+            PagedQuery::new("SELECT  'open"),
+            PagedQuery { base: "SELECT 'open".into(), offset: 0, limit: None, unpageable: None }
+        );
+    }
+
+    #[test]
+    fn pages_within_the_limit() {
+        let query = PagedQuery::new("SELECT a LIMIT 25 OFFSET 5");
+        assert_eq!(query.page(0, 10).unwrap(), "SELECT a ORDER BY :id LIMIT 10 OFFSET 5");
+        assert_eq!(query.page(2, 10).unwrap(), "SELECT a ORDER BY :id LIMIT 5 OFFSET 25");
+        assert_eq!(query.page(3, 10), None);
+
+        let unlimited = PagedQuery::new("SELECT a");
+        assert_eq!(unlimited.page(4, 10).unwrap(), "SELECT a ORDER BY :id LIMIT 10 OFFSET 40");
+    }
+
+    #[test]
+    fn remaining_rows() {
+        let query = PagedQuery::new("SELECT a LIMIT 25 OFFSET 5");
+        assert_eq!(query.remaining(0).unwrap(), "SELECT a ORDER BY :id LIMIT 25 OFFSET 5");
+        assert_eq!(query.remaining(20).unwrap(), "SELECT a ORDER BY :id LIMIT 5 OFFSET 25");
+        assert_eq!(query.remaining(25), None);
+        assert_eq!(PagedQuery::new("SELECT a").remaining(7).unwrap(), "SELECT a ORDER BY :id OFFSET 7");
+    }
+}
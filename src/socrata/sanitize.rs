@@ -0,0 +1,155 @@
+/// Normalizes a query before it is sent: comments are dropped, runs of
+/// whitespace become a single space, and quoted text is kept exactly as typed.
+///
+/// Single quoted strings (with `''` escapes), double quoted strings and
+/// backtick quoted column names are copied verbatim, including any `--`
+/// or whitespace inside them.
+pub fn sanitize(q: &str) -> String {
+    let mut out = String::with_capacity(q.len());
+    let mut chars = q.chars().peekable();
+    // A space is only written once the next token shows up, so separators
+    // never pile up and there is nothing to trim at the end
+    let mut pending_space = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' => {
+                if pending_space && !out.is_empty() {
+                    out.push(' ');
+                }
+                pending_space = false;
+                out.push(c);
+                while let Some(l) = chars.next() {
+                    out.push(l);
+                    if l == c {
+                        // A doubled quote is an escaped quote, not the end
+                        if chars.peek() == Some(&c) {
+                            out.push(chars.next().unwrap());
+                        } else {
+                            break;
+                        }
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => {
+                for l in chars.by_ref() {
+                    if l == '\n' {
+                        break;
+                    }
+                }
+                pending_space = true;
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut prev = '\0';
+                for l in chars.by_ref() {
+                    if prev == '*' && l == '/' {
+                        break;
+                    }
+                    prev = l;
+                }
+                pending_space = true;
+            }
+            c if c.is_whitespace() => pending_space = true,
+            c => {
+                if pending_space && !out.is_empty() {
+                    out.push(' ');
+                }
+                pending_space = false;
+                out.push(c);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sanitize;
+
+    #[test]
+    fn collapses_whitespace() {
+        assert_eq!(sanitize("  SELECT\ta,\n\n  b\r\nWHERE  x > 1 \n"), "SELECT a, b WHERE x > 1");
+    }
+
+    #[test]
+    fn tabs_separate_tokens() {
+        assert_eq!(sanitize("SELECT\ta\tFROM"), "SELECT a FROM");
+    }
+
+    #[test]
+    fn keeps_whitespace_inside_literals() {
+        assert_eq!(
+            sanitize("SELECT * WHERE name = 'New   York\tCity'\n  AND x = 1"),
+            "SELECT * WHERE name = 'New   York\tCity' AND x = 1"
+        );
+    }
+
+    #[test]
+    fn keeps_newlines_inside_literals() {
+        assert_eq!(sanitize("WHERE a = 'line one\nline two'"), "WHERE a = 'line one\nline two'");
+    }
+
+    #[test]
+    fn line_comment_only_hides_its_own_line() {
+        assert_eq!(
+            sanitize("SELECT a -- the id\nWHERE b = 1 -- filter\nLIMIT 5"),
+            "SELECT a WHERE b = 1 LIMIT 5"
+        );
+    }
+
+    #[test]
+    fn comment_at_end_without_newline() {
+        assert_eq!(sanitize("SELECT a -- trailing"), "SELECT a");
+    }
+
+    #[test]
+    fn dashes_inside_literal_are_not_a_comment() {
+        assert_eq!(sanitize("WHERE a = '--not a comment' AND b = 2"), "WHERE a = '--not a comment' AND b = 2");
+    }
+
+    #[test]
+    fn escaped_quotes() {
+        assert_eq!(
+            sanitize("WHERE name = 'O''Brien  -- x'   AND b = 1"),
+            "WHERE name = 'O''Brien  -- x' AND b = 1"
+        );
+        assert_eq!(sanitize("WHERE a = ''  AND b = ''''"), "WHERE a = '' AND b = ''''");
+    }
+
+    #[test]
+    fn double_quoted_and_backtick_text() {
+        assert_eq!(
+            sanitize("SELECT `my   column`, \"a -- b\"\nWHERE 1 = 1"),
+            "SELECT `my   column`, \"a -- b\" WHERE 1 = 1"
+        );
+    }
+
+    #[test]
+    fn block_comments() {
+        assert_eq!(sanitize("SELECT /* all\n of it */ *\nWHERE a/**/= 1"), "SELECT * WHERE a = 1");
+        assert_eq!(sanitize("SELECT a /* never closed"), "SELECT a");
+    }
+
+    #[test]
+    fn minus_is_not_a_comment() {
+        assert_eq!(sanitize("SELECT a - 1, -b"), "SELECT a - 1, -b");
+    }
+
+    #[test]
+    fn unterminated_literal_is_kept() {
+        assert_eq!(sanitize("WHERE a = 'open  ended"), "WHERE a = 'open  ended");
+    }
+
+    #[test]
+    fn comment_between_tokens_separates_them() {
+        assert_eq!(sanitize("SELECT a--x\nFROM"), "SELECT a FROM");
+        assert_eq!(sanitize("-- heading\n-- more\nSELECT 1"), "SELECT 1");
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(sanitize(""), "");
+        assert_eq!(sanitize("  \n -- only a comment\n"), "");
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize as _, Deserializer};
+use serde_derive::Deserialize;
+use serde_json::Value;
+
+pub enum SchemaErrCause {
+    Data(String),
+}
+
+/// System columns every dataset has, with their types
+pub const SYSTEM_COLUMNS: [(&str, &str); 3] = [
+    (":id", "row_identifier"),
+    (":created_at", "fixed_timestamp"),
+    (":updated_at", "fixed_timestamp"),
+];
+
+/// Socrata writes counts as numbers or as strings depending on the dataset
+fn lenient_count<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    })
+}
+
+/// A value from the column statistics, without JSON quoting
+pub fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.to_owned(),
+        other => other.to_string(),
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct TopValue {
+    pub item: Value,
+    #[serde(default, deserialize_with = "lenient_count")]
+    pub count: Option<u64>,
+}
+
+/// Statistics Socrata keeps about a column's contents
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CachedContents {
+    #[serde(default, deserialize_with = "lenient_count")]
+    pub non_null: Option<u64>,
+    #[serde(default, deserialize_with = "lenient_count")]
+    pub null: Option<u64>,
+    #[serde(default, deserialize_with = "lenient_count")]
+    pub cardinality: Option<u64>,
+    #[serde(default)]
+    pub smallest: Option<Value>,
+    #[serde(default)]
+    pub largest: Option<Value>,
+    #[serde(default)]
+    pub top: Vec<TopValue>,
+}
+
+/// A column as described by `/api/views/{id}.json`
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SchemaColumn {
+    pub name: String,
+    #[serde(rename = "fieldName")]
+    pub field_name: String,
+    #[serde(rename = "dataTypeName", default)]
+    pub data_type: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(rename = "cachedContents", default)]
+    pub cached_contents: Option<CachedContents>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct DatasetSchema {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Seconds since the Unix epoch when the rows last changed
+    #[serde(rename = "rowsUpdatedAt", default)]
+    pub rows_updated_at: Option<u64>,
+    #[serde(default)]
+    pub columns: Vec<SchemaColumn>,
+}
+
+impl DatasetSchema {
+    /// The dataset's own columns followed by the system columns it doesn't list
+    pub fn queryable_columns(&self) -> Vec<SchemaColumn> {
+        let mut columns = self.columns.to_owned();
+        for (field_name, data_type) in SYSTEM_COLUMNS {
+            if !columns.iter().any(|c| c.field_name == field_name) {
+                columns.push(SchemaColumn {
+                    name: field_name.to_owned(),
+                    field_name: field_name.to_owned(),
+                    data_type: data_type.to_owned(),
+                    description: None,
+                    cached_contents: None,
+                });
+            }
+        }
+        columns
+    }
+
+    /// Rows counted by the column statistics, when Socrata has computed them
+    pub fn row_count(&self) -> Option<u64> {
+        self.columns
+            .iter()
+            .filter_map(|c| c.cached_contents.as_ref())
+            .filter_map(|c| Some(c.non_null? + c.null.unwrap_or(0)))
+            .max()
+    }
+}
+
+/// Schemas fetched this session, by domain and dataset
+#[derive(Default)]
+pub struct SchemaCache {
+    schemas: HashMap<String, DatasetSchema>,
+    errors: HashMap<String, String>,
+    pending: Option<String>,
+}
+
+impl SchemaCache {
+    pub fn key(domain: &str, dataset: &str) -> String {
+        format!("{}/{}", domain.trim().trim_end_matches('/'), dataset.trim())
+    }
+
+    pub fn get(&self, domain: &str, dataset: &str) -> Option<&DatasetSchema> {
+        self.schemas.get(&Self::key(domain, dataset))
+    }
+
+    pub fn error(&self, domain: &str, dataset: &str) -> Option<&String> {
+        self.errors.get(&Self::key(domain, dataset))
+    }
+
+    pub fn is_pending(&self, domain: &str, dataset: &str) -> bool {
+        self.pending.as_deref() == Some(Self::key(domain, dataset).as_str())
+    }
+
+    /// Whether nothing is known yet about the dataset and no fetch is running
+    pub fn needs_fetch(&self, domain: &str, dataset: &str) -> bool {
+        let key = Self::key(domain, dataset);
+        self.pending.is_none() && !self.schemas.contains_key(&key) && !self.errors.contains_key(&key)
+    }
+
+    pub fn start(&mut self, domain: &str, dataset: &str) {
+        self.pending = Some(Self::key(domain, dataset));
+    }
+
+    pub fn finish(&mut self, result: Result<DatasetSchema, String>) {
+        if let Some(key) = self.pending.take() {
+            match result {
+                Ok(schema) => {
+                    self.errors.remove(&key);
+                    self.schemas.insert(key, schema);
+                }
+                Err(e) => {
+                    self.errors.insert(key, e);
+                }
+            }
+        }
+    }
+
+    /// Drops what is known about the dataset so it is fetched again
+    pub fn forget(&mut self, domain: &str, dataset: &str) {
+        let key = Self::key(domain, dataset);
+        self.schemas.remove(&key);
+        self.errors.remove(&key);
+    }
+}
@@ -0,0 +1,311 @@
+use std::ops::Range;
+
+use super::lexer::{tokenize, Token, TokenKind};
+use super::{is_keyword, FUNCTIONS};
+use crate::socrata::schema::SchemaColumn;
+
+const MAX_ITEMS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompletionKind {
+    Column,
+    Function,
+    Keyword,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub label: String,
+    /// What replaces the word being typed
+    pub insert: String,
+    pub detail: String,
+    pub kind: CompletionKind,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Completions {
+    /// Byte range of the word being typed, replaced on insert
+    pub range: Range<usize>,
+    pub items: Vec<Completion>,
+}
+
+/// The clause the cursor is in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Clause {
+    Start,
+    Select,
+    From,
+    Join,
+    On,
+    Where,
+    GroupBy,
+    Having,
+    OrderBy,
+    Search,
+    Limit,
+    Offset,
+    /// `PARTITION BY` of a window
+    Partition,
+}
+
+/// Clauses that may follow each other, in order
+const NEXT_CLAUSES: [(Clause, &str); 12] = [
+    (Clause::Select, "SELECT"),
+    (Clause::From, "FROM"),
+    (Clause::Join, "JOIN"),
+    (Clause::Join, "LEFT JOIN"),
+    (Clause::Where, "WHERE"),
+    (Clause::GroupBy, "GROUP BY"),
+    (Clause::Having, "HAVING"),
+    (Clause::OrderBy, "ORDER BY"),
+    (Clause::Search, "SEARCH"),
+    (Clause::Limit, "LIMIT"),
+    (Clause::Offset, "OFFSET"),
+    (Clause::Start, "|>"),
+];
+
+fn clause_of(tokens: &[Token], i: usize) -> Option<Clause> {
+    let t = &tokens[i];
+    let followed_by = |keyword: &str| tokens.get(i + 1).map_or(false, |t| t.is_keyword(keyword));
+    let clause = if t.is_keyword("SELECT") {
+        Clause::Select
+    } else if t.is_keyword("FROM") {
+        Clause::From
+    } else if t.is_keyword("JOIN") {
+        Clause::Join
+    } else if t.is_keyword("ON") {
+        Clause::On
+    } else if t.is_keyword("WHERE") {
+        Clause::Where
+    } else if t.is_keyword("GROUP") && followed_by("BY") {
+        Clause::GroupBy
+    } else if t.is_keyword("HAVING") {
+        Clause::Having
+    } else if t.is_keyword("ORDER") && followed_by("BY") {
+        Clause::OrderBy
+    } else if t.is_keyword("SEARCH") {
+        Clause::Search
+    } else if t.is_keyword("LIMIT") {
+        Clause::Limit
+    } else if t.is_keyword("OFFSET") {
+        Clause::Offset
+    } else if t.is_keyword("PARTITION") && followed_by("BY") {
+        Clause::Partition
+    } else {
+        return None;
+    };
+    Some(clause)
+}
+
+/// Whether the token finishes a value, so an operator or clause comes next
+fn ends_operand(tokens: &[Token], i: usize) -> bool {
+    match &tokens[i].kind {
+        TokenKind::Ident(word) => {
+            !is_keyword(word)
+                || ["NULL", "TRUE", "FALSE", "ASC", "DESC", "FIRST", "LAST"]
+                    .iter()
+                    .any(|k| k.eq_ignore_ascii_case(word))
+        }
+        TokenKind::QuotedIdent(_)
+        | TokenKind::SystemIdent(_)
+        | TokenKind::Table(_)
+        | TokenKind::Number(_)
+        | TokenKind::String(_)
+        | TokenKind::RParen => true,
+        // `SELECT *`
+        TokenKind::Operator("*") => {
+            i == 0 || tokens[i - 1].is_keyword("SELECT") || tokens[i - 1].is_keyword("DISTINCT") || tokens[i - 1].kind == TokenKind::Comma
+        }
+        _ => false,
+    }
+}
+
+/// The field name as written in a query, backticked when it isn't a plain word
+pub fn quote_column(field_name: &str) -> String {
+    let plain = field_name.starts_with(':')
+        || (field_name.chars().next().map_or(false, |c| c.is_alphabetic() || c == '_')
+            && field_name.chars().all(|c| c.is_alphanumeric() || c == '_')
+            && !is_keyword(field_name));
+    if plain {
+        field_name.to_owned()
+    } else {
+        format!("`{}`", field_name.replace('`', "``"))
+    }
+}
+
+fn keyword(word: &str, detail: &str) -> Completion {
+    Completion {
+        label: word.to_owned(),
+        insert: word.to_owned(),
+        detail: detail.to_owned(),
+        kind: CompletionKind::Keyword,
+    }
+}
+
+/// Completions for the word that ends at byte `cursor` of `src`, given the
+/// dataset's columns. Nothing is offered inside strings and comments.
+pub fn complete(src: &str, cursor: usize, columns: &[SchemaColumn]) -> Completions {
+    let none = Completions {
+        range: cursor..cursor,
+        items: vec![],
+    };
+    if cursor > src.len() || !src.is_char_boundary(cursor) {
+        return none;
+    }
+    let mut tokens = match tokenize(&src[..cursor]) {
+        Ok(tokens) => tokens,
+        // Most likely an unterminated string or comment the cursor is in
+        Err(_) => return none,
+    };
+    if matches!(tokens.last(), Some(Token { kind: TokenKind::Comment(_), span }) if span.end == cursor) {
+        return none;
+    }
+    tokens.retain(|t| !matches!(t.kind, TokenKind::Comment(_)));
+
+    // The word being typed, if any
+    let (prefix, range) = match tokens.last() {
+        Some(t) if t.span.end == cursor => match &t.kind {
+            TokenKind::Ident(word) | TokenKind::SystemIdent(word) => (word.to_owned(), t.span.to_owned()),
+            // Right after a number, string or name that is already complete
+            TokenKind::Number(_) | TokenKind::String(_) | TokenKind::QuotedIdent(_) | TokenKind::Table(_) => {
+                return none;
+            }
+            _ => (String::new(), cursor..cursor),
+        },
+        _ => (String::new(), cursor..cursor),
+    };
+    if !prefix.is_empty() {
+        tokens.pop();
+    }
+    // `@alias.` is followed by a column of another dataset, which we don't know
+    if matches!(tokens.last().map(|t| &t.kind), Some(TokenKind::Dot)) {
+        return none;
+    }
+
+    // Innermost clause, where each open parenthesis may start its own
+    let mut scopes: Vec<Option<Clause>> = vec![None];
+    let mut grouped = false;
+    for i in 0..tokens.len() {
+        match &tokens[i].kind {
+            TokenKind::LParen => scopes.push(None),
+            TokenKind::RParen if scopes.len() > 1 => {
+                scopes.pop();
+            }
+            TokenKind::Operator("|>") => {
+                scopes = vec![None];
+                grouped = false;
+            }
+            _ if ["UNION", "INTERSECT", "MINUS"].iter().any(|k| tokens[i].is_keyword(k)) => {
+                *scopes.last_mut().unwrap() = None;
+            }
+            _ => {
+                if let Some(clause) = clause_of(&tokens, i) {
+                    grouped |= clause == Clause::GroupBy && scopes.len() == 1;
+                    *scopes.last_mut().unwrap() = Some(clause);
+                }
+            }
+        }
+    }
+    let clause = scopes.iter().rev().flatten().next().copied().unwrap_or(Clause::Start);
+    // A GROUP BY further down also makes aggregates the likely choice
+    if !grouped {
+        if let Ok(rest) = tokenize(&src[cursor..]) {
+            grouped = rest
+                .iter()
+                .take_while(|t| t.kind != TokenKind::Operator("|>"))
+                .zip(rest.iter().skip(1))
+                .any(|(a, b)| a.is_keyword("GROUP") && b.is_keyword("BY"));
+        }
+    }
+
+    let last = tokens.len().checked_sub(1);
+    let after_operand = last.map_or(false, |i| ends_operand(&tokens, i));
+    if last.map_or(false, |i| tokens[i].is_keyword("AS")) {
+        // Naming an alias
+        return none;
+    }
+
+    let mut items = vec![];
+    if clause == Clause::Start && !after_operand {
+        for (_, word) in NEXT_CLAUSES.iter().filter(|(c, w)| *c != Clause::Start && *w != "LEFT JOIN") {
+            items.push(keyword(word, "clause"));
+        }
+    } else if after_operand {
+        let operators: &[&str] = match clause {
+            Clause::Select => &["AS", "AND", "OR"],
+            Clause::Where | Clause::Having | Clause::On => &["AND", "OR", "NOT", "IS NULL", "IS NOT NULL", "IN", "LIKE", "BETWEEN"],
+            Clause::OrderBy => &["ASC", "DESC", "NULLS FIRST", "NULLS LAST"],
+            Clause::From | Clause::Join => &["AS", "ON"],
+            _ => &[],
+        };
+        for word in operators {
+            items.push(keyword(word, "operator"));
+        }
+        for (next, word) in NEXT_CLAUSES {
+            // Joins and the next stage may follow anything in their own part of the query
+            let allowed = match next {
+                Clause::Start => true,
+                Clause::Join => clause <= Clause::On,
+                _ => next > clause && clause != Clause::Partition,
+            };
+            if allowed && next != Clause::Select {
+                items.push(keyword(word, "clause"));
+            }
+        }
+    } else if matches!(clause, Clause::From | Clause::Join | Clause::Search | Clause::Limit | Clause::Offset) {
+        // A table, search term or number comes next
+        return none;
+    } else {
+        let aggregates_allowed = matches!(clause, Clause::Select | Clause::Having | Clause::OrderBy);
+        let functions = FUNCTIONS.iter().filter(|f| aggregates_allowed || !f.aggregate).map(|f| Completion {
+            label: f.signature(),
+            insert: format!("{}(", f.name),
+            detail: if f.aggregate { "aggregate".to_owned() } else { "function".to_owned() },
+            kind: CompletionKind::Function,
+        });
+        let (aggregates, others): (Vec<Completion>, Vec<Completion>) = functions.partition(|c| c.detail == "aggregate");
+        let columns = columns.iter().map(|c| Completion {
+            label: c.field_name.to_owned(),
+            insert: quote_column(&c.field_name),
+            detail: if c.name == c.field_name {
+                c.data_type.to_owned()
+            } else {
+                format!("{} · {}", c.data_type, c.name)
+            },
+            kind: CompletionKind::Column,
+        });
+        // After GROUP BY the columns mostly go through aggregates
+        if grouped && clause != Clause::GroupBy {
+            items.extend(aggregates);
+            items.extend(columns);
+        } else {
+            items.extend(columns);
+            items.extend(aggregates);
+        }
+        items.extend(others);
+        if clause == Clause::Select && last.map_or(false, |i| tokens[i].is_keyword("SELECT")) {
+            items.push(keyword("DISTINCT", "keyword"));
+        }
+        for word in ["NOT", "NULL", "TRUE", "FALSE"] {
+            items.push(keyword(word, "keyword"));
+        }
+    }
+
+    // Words starting with the prefix first, then ones merely containing it
+    let lower = prefix.to_lowercase();
+    let name_of = |c: &Completion| c.insert.trim_start_matches('`').to_lowercase();
+    let mut matching: Vec<Completion> = items.iter().filter(|c| name_of(c).starts_with(&lower)).cloned().collect();
+    if !lower.is_empty() {
+        matching.extend(
+            items
+                .into_iter()
+                .filter(|c| !name_of(c).starts_with(&lower) && name_of(c).contains(&lower)),
+        );
+    }
+    // Nothing to offer if the word is already complete
+    if matching.len() == 1 && matching[0].insert.eq_ignore_ascii_case(&prefix) {
+        return none;
+    }
+    matching.truncate(MAX_ITEMS);
+    Completions { range, items: matching }
+}
@@ -0,0 +1,482 @@
+use super::ast::*;
+use super::complete::quote_column;
+use super::lexer::{tokenize, Token, TokenKind};
+use super::{parse, SoqlError};
+
+/// Indentation of `case()` arguments and subqueries
+const INDENT: usize = 4;
+
+fn string_literal(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+fn table(name: &str) -> String {
+    format!("@{}", name)
+}
+
+fn is_multiline(s: &str) -> bool {
+    s.contains('\n')
+}
+
+fn binary_op(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Or => "OR",
+        BinaryOp::And => "AND",
+        BinaryOp::Eq => "=",
+        BinaryOp::NotEq => "!=",
+        BinaryOp::Lt => "<",
+        BinaryOp::LtEq => "<=",
+        BinaryOp::Gt => ">",
+        BinaryOp::GtEq => ">=",
+        BinaryOp::Concat => "||",
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Mod => "%",
+        BinaryOp::Pow => "^",
+    }
+}
+
+fn set_op(op: SetOp) -> &'static str {
+    match op {
+        SetOp::Union => "UNION",
+        SetOp::UnionAll => "UNION ALL",
+        SetOp::Intersect => "INTERSECT",
+        SetOp::IntersectAll => "INTERSECT ALL",
+        SetOp::Minus => "MINUS",
+        SetOp::MinusAll => "MINUS ALL",
+    }
+}
+
+fn join_kind(kind: JoinKind) -> &'static str {
+    match kind {
+        JoinKind::Inner => "JOIN",
+        JoinKind::Left => "LEFT JOIN",
+        JoinKind::Right => "RIGHT JOIN",
+        JoinKind::Full => "FULL JOIN",
+    }
+}
+
+/// The operands of a chain like `a AND b AND c`
+fn flatten(expr: &Expr, op: BinaryOp) -> Vec<&Expr> {
+    match expr {
+        Expr::Binary { left, op: o, right } if *o == op => {
+            let mut operands = flatten(left, op);
+            operands.push(right);
+            operands
+        }
+        other => vec![other],
+    }
+}
+
+/// Renders expressions; `indent` is the column the current line starts at
+fn expr(e: &Expr, indent: usize) -> String {
+    match e {
+        Expr::Column(c) => {
+            let name = quote_column(&c.name);
+            match &c.qualifier {
+                Some(q) => format!("{}.{}", table(q), name),
+                None => name,
+            }
+        }
+        Expr::Number(n) => n.to_owned(),
+        Expr::String(s) => string_literal(s),
+        Expr::Bool(true) => "TRUE".to_owned(),
+        Expr::Bool(false) => "FALSE".to_owned(),
+        Expr::Null => "NULL".to_owned(),
+        Expr::Star => "*".to_owned(),
+        Expr::Call(call) => self::call(call, indent),
+        Expr::Unary { op, expr: inner } => {
+            let inner = expr(inner, indent);
+            match op {
+                UnaryOp::Not => format!("NOT {}", inner),
+                // `--` would start a comment
+                UnaryOp::Neg if inner.starts_with('-') => format!("- {}", inner),
+                UnaryOp::Neg => format!("-{}", inner),
+                UnaryOp::Plus => format!("+{}", inner),
+            }
+        }
+        Expr::Binary { left, op, right } => {
+            format!("{} {} {}", expr(left, indent), binary_op(*op), expr(right, indent))
+        }
+        Expr::IsNull { expr: inner, negated } => {
+            format!("{} IS {}NULL", expr(inner, indent), if *negated { "NOT " } else { "" })
+        }
+        Expr::In { expr: inner, list, negated } => format!(
+            "{} {}IN ({})",
+            expr(inner, indent),
+            if *negated { "NOT " } else { "" },
+            list.iter().map(|e| expr(e, indent)).collect::<Vec<_>>().join(", ")
+        ),
+        Expr::Like { expr: inner, pattern, negated } => format!(
+            "{} {}LIKE {}",
+            expr(inner, indent),
+            if *negated { "NOT " } else { "" },
+            expr(pattern, indent)
+        ),
+        Expr::Between { expr: inner, low, high, negated } => format!(
+            "{} {}BETWEEN {} AND {}",
+            expr(inner, indent),
+            if *negated { "NOT " } else { "" },
+            expr(low, indent),
+            expr(high, indent)
+        ),
+        Expr::Cast { expr: inner, ty } => format!("{}::{}", expr(inner, indent), ty.to_lowercase()),
+        Expr::Paren(inner) => format!("({})", expr(inner, indent)),
+    }
+}
+
+fn call(call: &Call, indent: usize) -> String {
+    let name = call.name.to_lowercase();
+    // case(condition, value, ...) gets a line per pair
+    let mut text = if name == "case" && call.args.len() > 2 {
+        let inner = indent + INDENT;
+        let pairs: Vec<String> = call
+            .args
+            .chunks(2)
+            .map(|pair| pair.iter().map(|e| expr(e, inner)).collect::<Vec<_>>().join(", "))
+            .collect();
+        format!(
+            "case(\n{pad}{}\n{})",
+            pairs.join(&format!(",\n{}", " ".repeat(inner))),
+            " ".repeat(indent),
+            pad = " ".repeat(inner)
+        )
+    } else {
+        let args: Vec<String> = call.args.iter().map(|e| expr(e, indent)).collect();
+        format!("{}({}{})", name, if call.distinct { "DISTINCT " } else { "" }, args.join(", "))
+    };
+    if let Some(window) = &call.window {
+        let mut parts = vec![];
+        if !window.partition_by.is_empty() {
+            let exprs: Vec<String> = window.partition_by.iter().map(|e| expr(e, indent)).collect();
+            parts.push(format!("PARTITION BY {}", exprs.join(", ")));
+        }
+        if !window.order_by.is_empty() {
+            parts.push(format!("ORDER BY {}", order_by(&window.order_by, indent)));
+        }
+        text.push_str(&format!(" OVER ({})", parts.join(" ")));
+    }
+    text
+}
+
+fn order_by(items: &[OrderBy], indent: usize) -> String {
+    let items: Vec<String> = items
+        .iter()
+        .map(|o| {
+            let mut text = expr(&o.expr, indent);
+            if o.descending {
+                text.push_str(" DESC");
+            }
+            match o.nulls {
+                Some(Nulls::First) => text.push_str(" NULLS FIRST"),
+                Some(Nulls::Last) => text.push_str(" NULLS LAST"),
+                None => {}
+            }
+            text
+        })
+        .collect();
+    items.join(", ")
+}
+
+struct Printer {
+    out: String,
+    indent: usize,
+}
+
+impl Printer {
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.out.push_str(&" ".repeat(self.indent));
+    }
+
+    fn query(&mut self, query: &Query) {
+        match query {
+            Query::Select(select) => self.select(select),
+            Query::Compound { left, op, right } => {
+                self.query(left);
+                self.newline();
+                self.out.push_str(set_op(*op));
+                self.newline();
+                self.query(right);
+            }
+            Query::Pipe { left, right } => {
+                self.query(left);
+                self.newline();
+                self.out.push_str("|>");
+                self.newline();
+                self.query(right);
+            }
+        }
+    }
+
+    /// Starts a clause on its own line, except for the first one
+    fn clause(&mut self, keyword: &str) {
+        if !self.out.is_empty() && !self.out.ends_with(&format!("\n{}", " ".repeat(self.indent))) {
+            self.newline();
+        }
+        self.out.push_str(keyword);
+        self.out.push(' ');
+    }
+
+    fn select(&mut self, s: &Select) {
+        if !s.columns.is_empty() {
+            let keyword = if s.distinct { "SELECT DISTINCT" } else { "SELECT" };
+            self.clause(keyword);
+            self.select_list(&s.columns, self.indent + keyword.len() + 1);
+        }
+        if let Some(from) = &s.from {
+            self.clause("FROM");
+            self.out.push_str(&table(&from.name));
+            if let Some(a) = &from.alias {
+                self.out.push_str(&format!(" AS {}", table(a)));
+            }
+        }
+        for join in &s.joins {
+            self.clause(join_kind(join.kind));
+            match &join.source {
+                JoinSource::Table(name) => self.out.push_str(&table(name)),
+                JoinSource::Query(query) => {
+                    let mut sub = Printer {
+                        out: String::new(),
+                        indent: self.indent + INDENT,
+                    };
+                    sub.query(query);
+                    self.out.push_str(&format!("(\n{}{}", " ".repeat(self.indent + INDENT), sub.out));
+                    self.newline();
+                    self.out.push(')');
+                }
+            }
+            if let Some(a) = &join.alias {
+                self.out.push_str(&format!(" AS {}", table(a)));
+            }
+            self.out.push_str(" ON ");
+            let on = expr(&join.on, self.indent);
+            self.out.push_str(&on);
+        }
+        if let Some(filter) = &s.filter {
+            self.condition("WHERE", filter);
+        }
+        if !s.group_by.is_empty() {
+            self.clause("GROUP BY");
+            let exprs: Vec<String> = s.group_by.iter().map(|e| expr(e, self.indent)).collect();
+            self.out.push_str(&exprs.join(", "));
+        }
+        if let Some(having) = &s.having {
+            self.condition("HAVING", having);
+        }
+        if !s.order_by.is_empty() {
+            self.clause("ORDER BY");
+            let items = order_by(&s.order_by, self.indent);
+            self.out.push_str(&items);
+        }
+        if let Some(search) = &s.search {
+            self.clause("SEARCH");
+            self.out.push_str(&string_literal(search));
+        }
+        if let Some(limit) = s.limit {
+            self.clause("LIMIT");
+            self.out.push_str(&limit.to_string());
+        }
+        if let Some(offset) = s.offset {
+            self.clause("OFFSET");
+            self.out.push_str(&offset.to_string());
+        }
+    }
+
+    /// One item per line, starting at `column`, with aliases lined up
+    fn select_list(&mut self, items: &[SelectItem], column: usize) {
+        let rendered: Vec<(String, Option<String>)> = items
+            .iter()
+            .map(|item| match item {
+                SelectItem::All => ("*".to_owned(), None),
+                SelectItem::System => (":*".to_owned(), None),
+                SelectItem::Table(name) => (format!("{}.*", table(name)), None),
+                SelectItem::Expr { expr: e, alias: a } => (expr(e, column), a.as_deref().map(quote_column)),
+            })
+            .collect();
+        let width = rendered
+            .iter()
+            .filter(|(text, a)| a.is_some() && !is_multiline(text))
+            .map(|(text, _)| text.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (i, (text, a)) in rendered.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(&format!(",\n{}", " ".repeat(column)));
+            }
+            self.out.push_str(text);
+            if let Some(a) = a {
+                let pad = if is_multiline(text) { 0 } else { width - text.chars().count() };
+                self.out.push_str(&format!("{} AS {}", " ".repeat(pad), a));
+            }
+        }
+    }
+
+    /// `WHERE`/`HAVING` with each top level `AND` or `OR` on its own line,
+    /// right-aligned with the clause keyword
+    fn condition(&mut self, keyword: &str, condition: &Expr) {
+        self.clause(keyword);
+        let column = self.indent + keyword.len() + 1;
+        let op = match condition {
+            Expr::Binary { op: BinaryOp::Or, .. } => BinaryOp::Or,
+            _ => BinaryOp::And,
+        };
+        let word = binary_op(op);
+        for (i, operand) in flatten(condition, op).into_iter().enumerate() {
+            if i > 0 {
+                let pad = keyword.len().saturating_sub(word.len());
+                self.newline();
+                self.out.push_str(&format!("{}{} ", " ".repeat(pad), word));
+            }
+            self.out.push_str(&expr(operand, column));
+        }
+    }
+}
+
+/// What a token must match in the formatted query, ignoring case and
+/// spellings that mean the same thing
+fn token_key(token: &Token) -> String {
+    match &token.kind {
+        TokenKind::Ident(word) => word.to_lowercase(),
+        TokenKind::Operator("<>") => "!=".to_owned(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// For each token of `a`, the index of the same token in `b`, by longest
+/// common subsequence
+fn match_tokens(a: &[Token], b: &[Token]) -> Vec<Option<usize>> {
+    let a_keys: Vec<String> = a.iter().map(token_key).collect();
+    let b_keys: Vec<String> = b.iter().map(token_key).collect();
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if a_keys[i] == b_keys[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut matches = vec![None; a.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a_keys[i] == b_keys[j] {
+            matches[i] = Some(j);
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    matches
+}
+
+/// The token a line break at byte `at` belongs to, such as a multi-line string
+fn token_around(tokens: &[Token], at: usize) -> Option<&Token> {
+    tokens.iter().find(|t| t.span.start <= at && at < t.span.end)
+}
+
+/// Start of the line `at` is on, not counting line breaks inside tokens
+fn line_start(s: &str, tokens: &[Token], mut at: usize) -> usize {
+    loop {
+        match s[..at].rfind('\n') {
+            Some(i) => match token_around(tokens, i) {
+                Some(t) => at = t.span.start,
+                None => return i + 1,
+            },
+            None => return 0,
+        }
+    }
+}
+
+/// End of the line `at` is on, not counting line breaks inside tokens
+fn line_end(s: &str, tokens: &[Token], mut at: usize) -> usize {
+    loop {
+        match s[at..].find('\n').map(|i| at + i) {
+            Some(i) => match token_around(tokens, i) {
+                Some(t) => at = t.span.end,
+                None => return i,
+            },
+            None => return s.len(),
+        }
+    }
+}
+
+/// Puts the comments of `src` back into `formatted`. Comments on a line of
+/// their own stay above the token that followed them; the others follow the
+/// token they came after.
+fn restore_comments(src: &str, formatted: String) -> String {
+    let tokens = match tokenize(src) {
+        Ok(tokens) => tokens,
+        Err(_) => return formatted,
+    };
+    if !tokens.iter().any(|t| matches!(t.kind, TokenKind::Comment(_))) {
+        return formatted;
+    }
+    let output = match tokenize(&formatted) {
+        Ok(output) => output,
+        Err(_) => return formatted,
+    };
+    let code: Vec<Token> = tokens.iter().filter(|t| !matches!(t.kind, TokenKind::Comment(_))).cloned().collect();
+    let matches = match_tokens(&code, &output);
+
+    // (byte offset in the output, text to insert)
+    let mut inserts: Vec<(usize, String)> = vec![];
+    let mut seen = 0;
+    for token in &tokens {
+        let text = match token.kind {
+            TokenKind::Comment(_) => src[token.span.clone()].trim_end(),
+            _ => {
+                seen += 1;
+                continue;
+            }
+        };
+        let is_line_comment = text.starts_with("--");
+        let own_line = src[line_start(src, &tokens, token.span.start)..token.span.start].trim().is_empty();
+        let next = matches[seen..].iter().flatten().next();
+        let prev = matches[..seen].iter().rev().flatten().next();
+        match (own_line, next, prev) {
+            (true, Some(&next), _) | (false, Some(&next), None) => {
+                let at = line_start(&formatted, &output, output[next].span.start);
+                let indent: String = formatted[at..].chars().take_while(|c| *c == ' ').collect();
+                inserts.push((at, format!("{}{}\n", indent, text)));
+            }
+            (_, _, Some(&prev)) => {
+                let end = output[prev].span.end;
+                if is_line_comment {
+                    inserts.push((line_end(&formatted, &output, end), format!(" {}", text)));
+                } else {
+                    inserts.push((end, format!(" {}", text)));
+                }
+            }
+            (_, None, None) => inserts.push((formatted.len(), format!("\n{}", text))),
+        }
+    }
+
+    let mut formatted = formatted;
+    // Later comments first so earlier offsets stay valid, and comments
+    // sharing an offset end up in their original order
+    inserts.sort_by_key(|(at, _)| *at);
+    for (at, text) in inserts.into_iter().rev() {
+        formatted.insert_str(at, &text);
+    }
+    formatted
+}
+
+/// Rewrites a query in the canonical layout: upper case keywords, a line per
+/// clause, aligned select lists and indented `case()` calls and subqueries.
+/// Comments are kept; a query that doesn't parse is returned as an error.
+pub fn format_query(src: &str) -> Result<String, SoqlError> {
+    let query = parse(src)?;
+    let mut printer = Printer {
+        out: String::new(),
+        indent: 0,
+    };
+    printer.query(&query);
+    Ok(restore_comments(src, printer.out))
+}
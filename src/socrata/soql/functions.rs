@@ -0,0 +1,110 @@
+/// A function Socrata provides
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Function {
+    pub name: &'static str,
+    /// Arguments as shown in completions, e.g. `location, lat, lon, meters`
+    pub args: &'static str,
+    pub aggregate: bool,
+}
+
+impl Function {
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.args)
+    }
+}
+
+const fn f(name: &'static str, args: &'static str) -> Function {
+    Function {
+        name,
+        args,
+        aggregate: false,
+    }
+}
+
+const fn agg(name: &'static str, args: &'static str) -> Function {
+    Function {
+        name,
+        args,
+        aggregate: true,
+    }
+}
+
+pub const FUNCTIONS: [Function; 71] = [
+    agg("avg", "number"),
+    agg("count", "value | *"),
+    agg("max", "value"),
+    agg("min", "value"),
+    agg("sum", "number"),
+    agg("stddev_pop", "number"),
+    agg("stddev_samp", "number"),
+    agg("median", "value"),
+    agg("regr_intercept", "y, x"),
+    agg("regr_r2", "y, x"),
+    agg("regr_slope", "y, x"),
+    agg("extent", "geometry"),
+    agg("convex_hull", "geometry"),
+    // Windows, used with OVER (...)
+    f("row_number", ""),
+    f("rank", ""),
+    f("dense_rank", ""),
+    f("first_value", "value"),
+    f("last_value", "value"),
+    f("lag", "value, offset?, default?"),
+    f("lead", "value, offset?, default?"),
+    f("ntile", "buckets"),
+    // Text
+    f("lower", "text"),
+    f("upper", "text"),
+    f("trim", "text"),
+    f("trim_leading", "text"),
+    f("trim_trailing", "text"),
+    f("left_pad", "text, length, fill"),
+    f("right_pad", "text, length, fill"),
+    f("starts_with", "text, prefix"),
+    f("contains", "text, needle"),
+    f("unaccent", "text"),
+    f("split_part", "text, delimiter, index"),
+    f("chr", "code"),
+    f("length", "text"),
+    f("replace", "text, from, to"),
+    f("is_empty", "geometry"),
+    // Numbers
+    f("abs", "number"),
+    f("ceil", "number"),
+    f("floor", "number"),
+    f("round", "number, digits?"),
+    f("ln", "number"),
+    f("signed_magnitude_10", "number"),
+    f("signed_magnitude_linear", "number, bucket"),
+    // Dates
+    f("date_trunc_y", "timestamp"),
+    f("date_trunc_ym", "timestamp"),
+    f("date_trunc_ymd", "timestamp"),
+    f("date_extract_y", "timestamp"),
+    f("date_extract_m", "timestamp"),
+    f("date_extract_d", "timestamp"),
+    f("date_extract_hh", "timestamp"),
+    f("date_extract_mm", "timestamp"),
+    f("date_extract_ss", "timestamp"),
+    f("date_extract_dow", "timestamp"),
+    f("date_extract_woy", "timestamp"),
+    f("date_diff_d", "timestamp, timestamp"),
+    f("to_floating_timestamp", "timestamp, zone"),
+    f("get_utc_date", ""),
+    // Geospatial
+    f("within_box", "location, nw_lat, nw_lon, se_lat, se_lon"),
+    f("within_circle", "location, lat, lon, meters"),
+    f("within_polygon", "location, wkt"),
+    f("intersects", "geometry, wkt"),
+    f("distance_in_meters", "location, point"),
+    f("num_points", "geometry"),
+    f("simplify", "geometry, tolerance"),
+    f("simplify_preserve_topology", "geometry, tolerance"),
+    f("snap_to_grid", "geometry, size"),
+    // Conditionals
+    f("case", "condition, value, ..."),
+    f("coalesce", "value, ..."),
+    f("greatest", "value, ..."),
+    f("least", "value, ..."),
+    f("nullif", "value, value"),
+];
@@ -0,0 +1,250 @@
+use std::fmt;
+use std::ops::Range;
+
+use super::SoqlError;
+
+/// Operators from longest to shortest so the first match wins
+const OPERATORS: [&str; 16] = [
+    "|>", "||", "::", "!=", "<>", "<=", ">=", "=", "<", ">", "+", "-", "*", "/", "%", "^",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    /// A plain word; keywords are words too and are told apart by the parser
+    Ident(String),
+    /// A backtick quoted column name, without the backticks
+    QuotedIdent(String),
+    /// `:id`, `:@computed_region_abcd` or `:*`, colon included
+    SystemIdent(String),
+    /// `@abcd-1234` or `@alias`, without the `@`
+    Table(String),
+    Number(String),
+    /// The unescaped contents of a quoted string
+    String(String),
+    Operator(&'static str),
+    LParen,
+    RParen,
+    Comma,
+    Dot,
+    Comment(String),
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenKind::Ident(s) => write!(f, "`{}`", s),
+            TokenKind::QuotedIdent(s) => write!(f, "`{}`", s),
+            TokenKind::SystemIdent(s) => write!(f, "`{}`", s),
+            TokenKind::Table(s) => write!(f, "`@{}`", s),
+            TokenKind::Number(s) => write!(f, "number {}", s),
+            TokenKind::String(s) => write!(f, "string '{}'", s),
+            TokenKind::Operator(s) => write!(f, "`{}`", s),
+            TokenKind::LParen => write!(f, "`(`"),
+            TokenKind::RParen => write!(f, "`)`"),
+            TokenKind::Comma => write!(f, "`,`"),
+            TokenKind::Dot => write!(f, "`.`"),
+            TokenKind::Comment(_) => write!(f, "comment"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// Byte range in the query
+    pub span: Range<usize>,
+}
+
+impl Token {
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.kind, TokenKind::Ident(s) if s.eq_ignore_ascii_case(keyword))
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+struct Lexer<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.src[self.pos..].chars().nth(n)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !f(c) {
+                break;
+            }
+            self.bump();
+        }
+        &self.src[start..self.pos]
+    }
+
+    /// Reads up to the closing `quote`, where a doubled quote stands for itself
+    fn quoted(&mut self, quote: char, start: usize) -> Result<String, SoqlError> {
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some(c) if c == quote => {
+                    if self.peek() == Some(quote) {
+                        self.bump();
+                        value.push(quote);
+                    } else {
+                        return Ok(value);
+                    }
+                }
+                Some(c) => value.push(c),
+                None => {
+                    let what = if quote == '`' { "column name" } else { "string" };
+                    return Err(SoqlError::new(format!("Unterminated {}", what), start..self.pos));
+                }
+            }
+        }
+    }
+
+    fn number(&mut self) -> String {
+        let start = self.pos;
+        self.eat_while(|c| c.is_ascii_digit());
+        if self.peek() == Some('.') && self.peek_nth(1).map_or(true, |c| !is_ident_start(c)) {
+            self.bump();
+            self.eat_while(|c| c.is_ascii_digit());
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            let digits_at = match self.peek_nth(1) {
+                Some('+' | '-') => 2,
+                _ => 1,
+            };
+            if self.peek_nth(digits_at).map_or(false, |c| c.is_ascii_digit()) {
+                for _ in 0..digits_at {
+                    self.bump();
+                }
+                self.eat_while(|c| c.is_ascii_digit());
+            }
+        }
+        self.src[start..self.pos].to_owned()
+    }
+
+    /// The name after `@`. Dataset IDs like `abcd-1234` keep their dash.
+    fn table(&mut self) -> String {
+        let start = self.pos;
+        let name = self.eat_while(is_ident_char);
+        let rest = &self.src[self.pos..];
+        let four_by_four = name.chars().count() == 4
+            && rest.starts_with('-')
+            && rest.chars().skip(1).take(4).filter(|c| c.is_ascii_alphanumeric()).count() == 4
+            && !rest.chars().nth(5).map_or(false, is_ident_char);
+        if four_by_four {
+            for _ in 0..5 {
+                self.bump();
+            }
+        }
+        self.src[start..self.pos].to_owned()
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, SoqlError> {
+        self.eat_while(char::is_whitespace);
+        let start = self.pos;
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        let rest = &self.src[self.pos..];
+        let kind = if rest.starts_with("--") {
+            let text = self.eat_while(|c| c != '\n');
+            TokenKind::Comment(text[2..].to_owned())
+        } else if let Some(body) = rest.strip_prefix("/*") {
+            match body.find("*/") {
+                Some(end) => {
+                    self.pos += end + 4;
+                    TokenKind::Comment(body[..end].to_owned())
+                }
+                None => {
+                    return Err(SoqlError::new("Unterminated comment", start..self.src.len()));
+                }
+            }
+        } else if c == '\'' || c == '"' {
+            self.bump();
+            TokenKind::String(self.quoted(c, start)?)
+        } else if c == '`' {
+            self.bump();
+            TokenKind::QuotedIdent(self.quoted(c, start)?)
+        } else if c.is_ascii_digit() || (c == '.' && self.peek_nth(1).map_or(false, |c| c.is_ascii_digit())) {
+            TokenKind::Number(self.number())
+        } else if is_ident_start(c) {
+            TokenKind::Ident(self.eat_while(is_ident_char).to_owned())
+        } else if c == ':' && self.peek_nth(1) != Some(':') {
+            self.bump();
+            match self.peek() {
+                Some('*') => {
+                    self.bump();
+                    TokenKind::SystemIdent(":*".to_owned())
+                }
+                Some(c) if c == '@' || is_ident_start(c) => {
+                    self.bump();
+                    self.eat_while(is_ident_char);
+                    TokenKind::SystemIdent(self.src[start..self.pos].to_owned())
+                }
+                _ => return Err(SoqlError::new("Expected a system column name after `:`", start..self.pos)),
+            }
+        } else if c == '@' {
+            self.bump();
+            let name = self.table();
+            if name.is_empty() {
+                return Err(SoqlError::new("Expected a table name after `@`", start..self.pos));
+            }
+            TokenKind::Table(name)
+        } else if c == '(' {
+            self.bump();
+            TokenKind::LParen
+        } else if c == ')' {
+            self.bump();
+            TokenKind::RParen
+        } else if c == ',' {
+            self.bump();
+            TokenKind::Comma
+        } else if c == '.' {
+            self.bump();
+            TokenKind::Dot
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            self.pos += op.len();
+            TokenKind::Operator(op)
+        } else {
+            self.bump();
+            return Err(SoqlError::new(format!("Unexpected character `{}`", c), start..self.pos));
+        };
+        Ok(Some(Token {
+            kind,
+            span: start..self.pos,
+        }))
+    }
+}
+
+/// Splits a query into tokens, comments included
+pub fn tokenize(src: &str) -> Result<Vec<Token>, SoqlError> {
+    let mut lexer = Lexer { src, pos: 0 };
+    let mut tokens = vec![];
+    while let Some(token) = lexer.next_token()? {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
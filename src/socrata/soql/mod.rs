@@ -0,0 +1,79 @@
+//! Lexer and parser for SoQL, the Socrata query language.
+//!
+//! `tokenize` turns a query into tokens with byte spans and `parse` builds
+//! an `ast::Query` from them. Both report a `SoqlError` pointing at the
+//! part of the query that could not be understood. `format_query` prints
+//! a parsed query back in a canonical layout.
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+
+pub mod ast;
+pub mod complete;
+mod format;
+mod functions;
+mod lexer;
+mod parser;
+#[cfg(test)]
+mod tests;
+
+pub use lexer::{tokenize, Token, TokenKind};
+pub use parser::parse;
+pub use format::format_query;
+pub use functions::FUNCTIONS;
+
+/// Words that can't be used as unquoted column names
+pub const KEYWORDS: [&str; 36] = [
+    "SELECT", "DISTINCT", "FROM", "WHERE", "GROUP", "BY", "HAVING", "ORDER", "ASC", "DESC",
+    "NULLS", "LIMIT", "OFFSET", "SEARCH", "AND", "OR", "NOT", "IS", "NULL", "TRUE", "FALSE",
+    "IN", "LIKE", "BETWEEN", "AS", "UNION", "INTERSECT", "MINUS", "ALL", "JOIN", "ON", "LEFT",
+    "RIGHT", "FULL", "INNER", "OUTER",
+];
+
+pub fn is_keyword(word: &str) -> bool {
+    KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(word))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoqlError {
+    pub message: String,
+    /// Byte range of the query the error points at
+    pub span: Range<usize>,
+}
+
+impl SoqlError {
+    pub fn new(message: impl ToString, span: Range<usize>) -> Self {
+        Self {
+            message: message.to_string(),
+            span,
+        }
+    }
+
+    /// The part of `src` to mark. Errors at a missing token have an empty
+    /// span, so the last character before it is marked instead.
+    pub fn marker(&self, src: &str) -> Range<usize> {
+        if !self.span.is_empty() {
+            return self.span.clone();
+        }
+        match src[..self.span.start.min(src.len())].trim_end().char_indices().last() {
+            Some((i, c)) => i..i + c.len_utf8(),
+            None => self.span.clone(),
+        }
+    }
+
+    /// 1-based line and column of the start of the error
+    pub fn line_col(&self, src: &str) -> (usize, usize) {
+        let before = &src[..self.span.start.min(src.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+        (line, column)
+    }
+}
+
+impl fmt::Display for SoqlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for SoqlError {}
@@ -0,0 +1,653 @@
+use super::ast::*;
+use super::lexer::{tokenize, Token, TokenKind};
+use super::{is_keyword, SoqlError};
+
+const CLAUSE_ORDER: &str = "SELECT, FROM, JOIN, WHERE, GROUP BY, HAVING, ORDER BY, SEARCH, LIMIT, OFFSET";
+const CLAUSES: [&str; 11] = [
+    "SELECT", "FROM", "JOIN", "WHERE", "GROUP", "HAVING", "ORDER", "SEARCH", "LIMIT", "OFFSET", "DISTINCT",
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Length of the query, where errors about a missing token point
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + n)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn at_keyword(&self, keyword: &str) -> bool {
+        self.peek().map_or(false, |t| t.is_keyword(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.at_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), SoqlError> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("`{}`", keyword)))
+        }
+    }
+
+    fn at(&self, kind: &TokenKind) -> bool {
+        self.peek().map_or(false, |t| &t.kind == kind)
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        let found = self.at(kind);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, kind: &TokenKind) -> Result<(), SoqlError> {
+        if self.eat(kind) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&kind.to_string()))
+        }
+    }
+
+    fn eat_operator(&mut self, operator: &str) -> bool {
+        let found = self.peek().map_or(false, |t| matches!(t.kind, TokenKind::Operator(o) if o == operator));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn unexpected(&self, expected: &str) -> SoqlError {
+        match self.peek() {
+            Some(t) => SoqlError::new(format!("Expected {} but found {}", expected, t.kind), t.span.clone()),
+            None => SoqlError::new(format!("Expected {} at the end of the query", expected), self.end..self.end),
+        }
+    }
+
+    fn comma_separated<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, SoqlError>) -> Result<Vec<T>, SoqlError> {
+        let mut items = vec![item(self)?];
+        while self.eat(&TokenKind::Comma) {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    fn query(&mut self) -> Result<Query, SoqlError> {
+        let mut left = self.compound()?;
+        while self.eat_operator("|>") {
+            let right = self.compound()?;
+            left = Query::Pipe {
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+        Ok(left)
+    }
+
+    fn compound(&mut self) -> Result<Query, SoqlError> {
+        let mut left = Query::Select(Box::new(self.select()?));
+        loop {
+            let op = if self.eat_keyword("UNION") {
+                if self.eat_keyword("ALL") { SetOp::UnionAll } else { SetOp::Union }
+            } else if self.eat_keyword("INTERSECT") {
+                if self.eat_keyword("ALL") { SetOp::IntersectAll } else { SetOp::Intersect }
+            } else if self.eat_keyword("MINUS") {
+                if self.eat_keyword("ALL") { SetOp::MinusAll } else { SetOp::Minus }
+            } else {
+                return Ok(left);
+            };
+            let right = Query::Select(Box::new(self.select()?));
+            left = Query::Compound {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            };
+        }
+    }
+
+    fn select(&mut self) -> Result<Select, SoqlError> {
+        let start = self.pos;
+        let mut select = Select::default();
+        if self.eat_keyword("SELECT") {
+            select.distinct = self.eat_keyword("DISTINCT");
+            select.columns = self.comma_separated(Self::select_item)?;
+        }
+        if self.eat_keyword("FROM") {
+            select.from = Some(self.table_ref()?);
+        }
+        while let Some(kind) = self.join_kind() {
+            select.joins.push(self.join(kind)?);
+        }
+        if self.eat_keyword("WHERE") {
+            select.filter = Some(self.expr()?);
+        }
+        if self.eat_keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            select.group_by = self.comma_separated(Self::expr)?;
+        }
+        if self.eat_keyword("HAVING") {
+            select.having = Some(self.expr()?);
+        }
+        // SEARCH may come before or after ORDER BY
+        if self.eat_keyword("SEARCH") {
+            select.search = Some(self.search()?);
+        }
+        if self.eat_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            select.order_by = self.comma_separated(Self::order_by)?;
+        }
+        if select.search.is_none() && self.eat_keyword("SEARCH") {
+            select.search = Some(self.search()?);
+        }
+        // LIMIT and OFFSET may come in either order
+        for _ in 0..2 {
+            if select.limit.is_none() && self.eat_keyword("LIMIT") {
+                select.limit = Some(self.whole_number("LIMIT")?);
+            }
+            if select.offset.is_none() && self.eat_keyword("OFFSET") {
+                select.offset = Some(self.whole_number("OFFSET")?);
+            }
+        }
+        if self.pos == start {
+            return Err(self.unexpected("`SELECT`"));
+        }
+        Ok(select)
+    }
+
+    fn select_item(&mut self) -> Result<SelectItem, SoqlError> {
+        if self.eat_operator("*") {
+            return Ok(SelectItem::All);
+        }
+        if self.eat(&TokenKind::SystemIdent(":*".to_owned())) {
+            return Ok(SelectItem::System);
+        }
+        if let (Some(TokenKind::Table(table)), Some(TokenKind::Dot), Some(TokenKind::Operator("*"))) = (
+            self.peek().map(|t| &t.kind),
+            self.peek_nth(1).map(|t| &t.kind),
+            self.peek_nth(2).map(|t| &t.kind),
+        ) {
+            let table = table.to_owned();
+            self.pos += 3;
+            return Ok(SelectItem::Table(table));
+        }
+        let expr = self.expr()?;
+        let alias = if self.eat_keyword("AS") {
+            Some(self.alias()?)
+        } else {
+            None
+        };
+        Ok(SelectItem::Expr { expr, alias })
+    }
+
+    /// A column alias or a table alias, with or without its `@`
+    fn alias(&mut self) -> Result<String, SoqlError> {
+        match self.peek().map(|t| t.kind.clone()) {
+            Some(TokenKind::Ident(name)) if !is_keyword(&name) => {
+                self.pos += 1;
+                Ok(name)
+            }
+            Some(TokenKind::QuotedIdent(name)) | Some(TokenKind::Table(name)) => {
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected("an alias")),
+        }
+    }
+
+    fn table_name(&mut self) -> Result<String, SoqlError> {
+        match self.peek().map(|t| t.kind.clone()) {
+            Some(TokenKind::Table(name)) => {
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected("a table like `@abcd-1234`")),
+        }
+    }
+
+    fn table_ref(&mut self) -> Result<TableRef, SoqlError> {
+        let name = self.table_name()?;
+        let alias = if self.eat_keyword("AS") {
+            Some(self.alias()?)
+        } else {
+            None
+        };
+        Ok(TableRef { name, alias })
+    }
+
+    fn join_kind(&mut self) -> Option<JoinKind> {
+        if self.eat_keyword("JOIN") {
+            return Some(JoinKind::Inner);
+        }
+        let kind = if self.at_keyword("INNER") {
+            JoinKind::Inner
+        } else if self.at_keyword("LEFT") {
+            JoinKind::Left
+        } else if self.at_keyword("RIGHT") {
+            JoinKind::Right
+        } else if self.at_keyword("FULL") {
+            JoinKind::Full
+        } else {
+            return None;
+        };
+        let outer = kind != JoinKind::Inner && self.peek_nth(1).map_or(false, |t| t.is_keyword("OUTER"));
+        let join_at = if outer { 2 } else { 1 };
+        if !self.peek_nth(join_at).map_or(false, |t| t.is_keyword("JOIN")) {
+            return None;
+        }
+        self.pos += join_at + 1;
+        Some(kind)
+    }
+
+    fn join(&mut self, kind: JoinKind) -> Result<Join, SoqlError> {
+        let source = if self.eat(&TokenKind::LParen) {
+            let query = self.query()?;
+            self.expect(&TokenKind::RParen)?;
+            JoinSource::Query(Box::new(query))
+        } else {
+            JoinSource::Table(self.table_name()?)
+        };
+        let alias = if self.eat_keyword("AS") {
+            Some(self.alias()?)
+        } else {
+            None
+        };
+        self.expect_keyword("ON")?;
+        let on = self.expr()?;
+        Ok(Join { kind, source, alias, on })
+    }
+
+    fn search(&mut self) -> Result<String, SoqlError> {
+        match self.peek().map(|t| t.kind.clone()) {
+            Some(TokenKind::String(text)) => {
+                self.pos += 1;
+                Ok(text)
+            }
+            _ => Err(self.unexpected("a quoted search term")),
+        }
+    }
+
+    fn whole_number(&mut self, clause: &str) -> Result<u64, SoqlError> {
+        match self.peek().map(|t| t.kind.clone()) {
+            Some(TokenKind::Number(n)) => match n.parse() {
+                Ok(n) => {
+                    self.pos += 1;
+                    Ok(n)
+                }
+                Err(_) => Err(SoqlError::new(
+                    format!("{} needs a whole number, not {}", clause, n),
+                    self.peek().unwrap().span.clone(),
+                )),
+            },
+            _ => Err(self.unexpected(&format!("a number after {}", clause))),
+        }
+    }
+
+    fn order_by(&mut self) -> Result<OrderBy, SoqlError> {
+        let expr = self.expr()?;
+        let descending = if self.eat_keyword("DESC") {
+            true
+        } else {
+            self.eat_keyword("ASC");
+            false
+        };
+        let nulls = if self.eat_keyword("NULLS") {
+            if self.eat_keyword("FIRST") {
+                Some(Nulls::First)
+            } else if self.eat_keyword("LAST") {
+                Some(Nulls::Last)
+            } else {
+                return Err(self.unexpected("`FIRST` or `LAST`"));
+            }
+        } else {
+            None
+        };
+        Ok(OrderBy { expr, descending, nulls })
+    }
+
+    fn expr(&mut self) -> Result<Expr, SoqlError> {
+        self.or()
+    }
+
+    fn or(&mut self) -> Result<Expr, SoqlError> {
+        let mut left = self.and()?;
+        while self.eat_keyword("OR") {
+            let right = self.and()?;
+            left = binary(left, BinaryOp::Or, right);
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, SoqlError> {
+        let mut left = self.not()?;
+        while self.eat_keyword("AND") {
+            let right = self.not()?;
+            left = binary(left, BinaryOp::And, right);
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr, SoqlError> {
+        if self.eat_keyword("NOT") {
+            let expr = self.not()?;
+            return Ok(Expr::Unary {
+                op: UnaryOp::Not,
+                expr: Box::new(expr),
+            });
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, SoqlError> {
+        let mut left = self.concat()?;
+        loop {
+            let op = match self.peek().map(|t| &t.kind) {
+                Some(TokenKind::Operator("=")) => Some(BinaryOp::Eq),
+                Some(TokenKind::Operator("!=")) | Some(TokenKind::Operator("<>")) => Some(BinaryOp::NotEq),
+                Some(TokenKind::Operator("<")) => Some(BinaryOp::Lt),
+                Some(TokenKind::Operator("<=")) => Some(BinaryOp::LtEq),
+                Some(TokenKind::Operator(">")) => Some(BinaryOp::Gt),
+                Some(TokenKind::Operator(">=")) => Some(BinaryOp::GtEq),
+                _ => None,
+            };
+            if let Some(op) = op {
+                self.pos += 1;
+                let right = self.concat()?;
+                left = binary(left, op, right);
+                continue;
+            }
+            if self.eat_keyword("IS") {
+                let negated = self.eat_keyword("NOT");
+                self.expect_keyword("NULL")?;
+                left = Expr::IsNull {
+                    expr: Box::new(left),
+                    negated,
+                };
+                continue;
+            }
+            let negated = self.at_keyword("NOT")
+                && self
+                    .peek_nth(1)
+                    .map_or(false, |t| t.is_keyword("IN") || t.is_keyword("LIKE") || t.is_keyword("BETWEEN"));
+            if negated {
+                self.pos += 1;
+            }
+            if self.eat_keyword("IN") {
+                self.expect(&TokenKind::LParen)?;
+                let list = self.comma_separated(Self::expr)?;
+                self.expect(&TokenKind::RParen)?;
+                left = Expr::In {
+                    expr: Box::new(left),
+                    list,
+                    negated,
+                };
+            } else if self.eat_keyword("LIKE") {
+                let pattern = self.concat()?;
+                left = Expr::Like {
+                    expr: Box::new(left),
+                    pattern: Box::new(pattern),
+                    negated,
+                };
+            } else if self.eat_keyword("BETWEEN") {
+                let low = self.concat()?;
+                self.expect_keyword("AND")?;
+                let high = self.concat()?;
+                left = Expr::Between {
+                    expr: Box::new(left),
+                    low: Box::new(low),
+                    high: Box::new(high),
+                    negated,
+                };
+            } else {
+                return Ok(left);
+            }
+        }
+    }
+
+    fn concat(&mut self) -> Result<Expr, SoqlError> {
+        let mut left = self.additive()?;
+        while self.eat_operator("||") {
+            let right = self.additive()?;
+            left = binary(left, BinaryOp::Concat, right);
+        }
+        Ok(left)
+    }
+
+    fn additive(&mut self) -> Result<Expr, SoqlError> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = if self.eat_operator("+") {
+                BinaryOp::Add
+            } else if self.eat_operator("-") {
+                BinaryOp::Sub
+            } else {
+                return Ok(left);
+            };
+            let right = self.multiplicative()?;
+            left = binary(left, op, right);
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, SoqlError> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat_operator("*") {
+                BinaryOp::Mul
+            } else if self.eat_operator("/") {
+                BinaryOp::Div
+            } else if self.eat_operator("%") {
+                BinaryOp::Mod
+            } else {
+                return Ok(left);
+            };
+            let right = self.unary()?;
+            left = binary(left, op, right);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, SoqlError> {
+        let op = if self.eat_operator("-") {
+            UnaryOp::Neg
+        } else if self.eat_operator("+") {
+            UnaryOp::Plus
+        } else {
+            return self.power();
+        };
+        let expr = self.unary()?;
+        Ok(Expr::Unary {
+            op,
+            expr: Box::new(expr),
+        })
+    }
+
+    /// `^` binds tighter than a sign and groups to the right, so `-2 ^ 3 ^ 2` is `-(2 ^ (3 ^ 2))`
+    fn power(&mut self) -> Result<Expr, SoqlError> {
+        let base = self.cast()?;
+        if self.eat_operator("^") {
+            let exponent = self.unary()?;
+            return Ok(binary(base, BinaryOp::Pow, exponent));
+        }
+        Ok(base)
+    }
+
+    fn cast(&mut self) -> Result<Expr, SoqlError> {
+        let mut expr = self.primary()?;
+        while self.eat_operator("::") {
+            let ty = match self.peek().map(|t| t.kind.clone()) {
+                Some(TokenKind::Ident(ty)) => {
+                    self.pos += 1;
+                    ty
+                }
+                _ => return Err(self.unexpected("a type name")),
+            };
+            expr = Expr::Cast {
+                expr: Box::new(expr),
+                ty,
+            };
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, SoqlError> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return Err(self.unexpected("an expression")),
+        };
+        match token.kind {
+            TokenKind::Number(n) => {
+                self.pos += 1;
+                Ok(Expr::Number(n))
+            }
+            TokenKind::String(s) => {
+                self.pos += 1;
+                Ok(Expr::String(s))
+            }
+            TokenKind::Ident(word) => {
+                if word.eq_ignore_ascii_case("TRUE") || word.eq_ignore_ascii_case("FALSE") {
+                    self.pos += 1;
+                    return Ok(Expr::Bool(word.eq_ignore_ascii_case("TRUE")));
+                }
+                if word.eq_ignore_ascii_case("NULL") {
+                    self.pos += 1;
+                    return Ok(Expr::Null);
+                }
+                if is_keyword(&word) {
+                    return Err(self.unexpected("an expression"));
+                }
+                self.pos += 1;
+                if self.at(&TokenKind::LParen) {
+                    return self.call(word);
+                }
+                Ok(Expr::Column(Column {
+                    qualifier: None,
+                    name: word,
+                }))
+            }
+            TokenKind::QuotedIdent(name) => {
+                self.pos += 1;
+                Ok(Expr::Column(Column { qualifier: None, name }))
+            }
+            TokenKind::SystemIdent(name) if name != ":*" => {
+                self.pos += 1;
+                Ok(Expr::Column(Column { qualifier: None, name }))
+            }
+            TokenKind::Table(table) => {
+                self.pos += 1;
+                self.expect(&TokenKind::Dot)?;
+                match self.peek().map(|t| t.kind.clone()) {
+                    Some(TokenKind::Ident(name)) if !is_keyword(&name) => {
+                        self.pos += 1;
+                        Ok(Expr::Column(Column {
+                            qualifier: Some(table),
+                            name,
+                        }))
+                    }
+                    Some(TokenKind::QuotedIdent(name)) | Some(TokenKind::SystemIdent(name)) if name != ":*" => {
+                        self.pos += 1;
+                        Ok(Expr::Column(Column {
+                            qualifier: Some(table),
+                            name,
+                        }))
+                    }
+                    _ => Err(self.unexpected("a column name")),
+                }
+            }
+            TokenKind::LParen => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect(&TokenKind::RParen)?;
+                Ok(Expr::Paren(Box::new(expr)))
+            }
+            _ => Err(self.unexpected("an expression")),
+        }
+    }
+
+    fn call(&mut self, name: String) -> Result<Expr, SoqlError> {
+        self.expect(&TokenKind::LParen)?;
+        let mut call = Call {
+            name,
+            distinct: false,
+            args: vec![],
+            window: None,
+        };
+        if self.eat_operator("*") {
+            call.args.push(Expr::Star);
+        } else if !self.at(&TokenKind::RParen) {
+            call.distinct = self.eat_keyword("DISTINCT");
+            call.args = self.comma_separated(Self::expr)?;
+        }
+        self.expect(&TokenKind::RParen)?;
+        if self.eat_keyword("OVER") {
+            self.expect(&TokenKind::LParen)?;
+            let mut window = Window {
+                partition_by: vec![],
+                order_by: vec![],
+            };
+            if self.eat_keyword("PARTITION") {
+                self.expect_keyword("BY")?;
+                window.partition_by = self.comma_separated(Self::expr)?;
+            }
+            if self.eat_keyword("ORDER") {
+                self.expect_keyword("BY")?;
+                window.order_by = self.comma_separated(Self::order_by)?;
+            }
+            self.expect(&TokenKind::RParen)?;
+            call.window = Some(window);
+        }
+        Ok(Expr::Call(call))
+    }
+}
+
+fn binary(left: Expr, op: BinaryOp, right: Expr) -> Expr {
+    Expr::Binary {
+        left: Box::new(left),
+        op,
+        right: Box::new(right),
+    }
+}
+
+/// Parses a whole query. Comments are skipped.
+pub fn parse(src: &str) -> Result<Query, SoqlError> {
+    let tokens: Vec<Token> = tokenize(src)?
+        .into_iter()
+        .filter(|t| !matches!(t.kind, TokenKind::Comment(_)))
+        .collect();
+    if tokens.is_empty() {
+        return Err(SoqlError::new("The query is empty", 0..src.len()));
+    }
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: src.len(),
+    };
+    let query = parser.query()?;
+    match parser.next() {
+        None => Ok(query),
+        Some(t) if CLAUSES.iter().any(|c| t.is_keyword(c)) => Err(SoqlError::new(
+            format!("Unexpected {}; each clause may appear once, in the order {}", t.kind, CLAUSE_ORDER),
+            t.span,
+        )),
+        Some(t) => Err(SoqlError::new(format!("Unexpected {}", t.kind), t.span)),
+    }
+}
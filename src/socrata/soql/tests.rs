@@ -0,0 +1,1103 @@
+use super::ast::*;
+use super::{parse, tokenize, SoqlError, TokenKind};
+
+fn kinds(src: &str) -> Vec<TokenKind> {
+    tokenize(src).unwrap().into_iter().map(|t| t.kind).collect()
+}
+
+fn ident(s: &str) -> TokenKind {
+    TokenKind::Ident(s.to_owned())
+}
+
+fn select(src: &str) -> Select {
+    match parse(src) {
+        Ok(Query::Select(select)) => *select,
+        other => panic!("expected a single select for {:?}, got {:?}", src, other),
+    }
+}
+
+/// The expression of the only column in `SELECT <expr>`
+fn expr(src: &str) -> Expr {
+    let mut select = select(&format!("SELECT {}", src));
+    assert_eq!(select.columns.len(), 1);
+    match select.columns.remove(0) {
+        SelectItem::Expr { expr, alias: None } => expr,
+        other => panic!("expected an expression, got {:?}", other),
+    }
+}
+
+fn error(src: &str) -> SoqlError {
+    match parse(src) {
+        Err(e) => e,
+        Ok(q) => panic!("expected {:?} to fail, got {:?}", src, q),
+    }
+}
+
+fn col(name: &str) -> Expr {
+    Expr::Column(Column {
+        qualifier: None,
+        name: name.to_owned(),
+    })
+}
+
+fn num(n: &str) -> Expr {
+    Expr::Number(n.to_owned())
+}
+
+fn string(s: &str) -> Expr {
+    Expr::String(s.to_owned())
+}
+
+fn bin(left: Expr, op: BinaryOp, right: Expr) -> Expr {
+    Expr::Binary {
+        left: Box::new(left),
+        op,
+        right: Box::new(right),
+    }
+}
+
+fn unary(op: UnaryOp, expr: Expr) -> Expr {
+    Expr::Unary {
+        op,
+        expr: Box::new(expr),
+    }
+}
+
+fn call(name: &str, args: Vec<Expr>) -> Expr {
+    Expr::Call(Call {
+        name: name.to_owned(),
+        distinct: false,
+        args,
+        window: None,
+    })
+}
+
+fn item(expr: Expr) -> SelectItem {
+    SelectItem::Expr { expr, alias: None }
+}
+
+fn order(expr: Expr, descending: bool) -> OrderBy {
+    OrderBy {
+        expr,
+        descending,
+        nulls: None,
+    }
+}
+
+// Lexer
+
+#[test]
+fn lexes_words_numbers_and_punctuation() {
+    assert_eq!(
+        kinds("SELECT a, b_2 WHERE (x >= 1.5)"),
+        vec![
+            ident("SELECT"),
+            ident("a"),
+            TokenKind::Comma,
+            ident("b_2"),
+            ident("WHERE"),
+            TokenKind::LParen,
+            ident("x"),
+            TokenKind::Operator(">="),
+            TokenKind::Number("1.5".to_owned()),
+            TokenKind::RParen,
+        ]
+    );
+}
+
+#[test]
+fn lexes_every_operator() {
+    assert_eq!(
+        kinds("|> || :: != <> <= >= = < > + - * / % ^"),
+        ["|>", "||", "::", "!=", "<>", "<=", ">=", "=", "<", ">", "+", "-", "*", "/", "%", "^"]
+            .iter()
+            .map(|op| TokenKind::Operator(op))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn lexes_numbers() {
+    for n in ["0", "42", "3.14", ".5", "1e10", "1.5E-3", "2e+4", "7."] {
+        assert_eq!(kinds(n), vec![TokenKind::Number(n.to_owned())], "{}", n);
+    }
+    // An `e` that isn't followed by digits is a separate word
+    assert_eq!(kinds("1east"), vec![TokenKind::Number("1".to_owned()), ident("east")]);
+}
+
+#[test]
+fn lexes_strings_with_escapes() {
+    assert_eq!(kinds("'O''Brien'"), vec![TokenKind::String("O'Brien".to_owned())]);
+    assert_eq!(kinds("''"), vec![TokenKind::String("".to_owned())]);
+    assert_eq!(kinds("\"double \"\" quoted\""), vec![TokenKind::String("double \" quoted".to_owned())]);
+    assert_eq!(kinds("'-- not a comment'"), vec![TokenKind::String("-- not a comment".to_owned())]);
+}
+
+#[test]
+fn lexes_quoted_identifiers() {
+    assert_eq!(kinds("`my column`"), vec![TokenKind::QuotedIdent("my column".to_owned())]);
+    assert_eq!(kinds("`a``b`"), vec![TokenKind::QuotedIdent("a`b".to_owned())]);
+}
+
+#[test]
+fn lexes_system_columns() {
+    assert_eq!(
+        kinds(":id, :created_at, :@computed_region_abcd_1234, :*"),
+        vec![
+            TokenKind::SystemIdent(":id".to_owned()),
+            TokenKind::Comma,
+            TokenKind::SystemIdent(":created_at".to_owned()),
+            TokenKind::Comma,
+            TokenKind::SystemIdent(":@computed_region_abcd_1234".to_owned()),
+            TokenKind::Comma,
+            TokenKind::SystemIdent(":*".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn lexes_cast_apart_from_system_columns() {
+    assert_eq!(
+        kinds("a::number"),
+        vec![ident("a"), TokenKind::Operator("::"), ident("number")]
+    );
+}
+
+#[test]
+fn lexes_tables() {
+    assert_eq!(kinds("@abcd-1234"), vec![TokenKind::Table("abcd-1234".to_owned())]);
+    assert_eq!(
+        kinds("@j.name"),
+        vec![TokenKind::Table("j".to_owned()), TokenKind::Dot, ident("name")]
+    );
+    assert_eq!(kinds("@this"), vec![TokenKind::Table("this".to_owned())]);
+    // Only a four-by-four keeps its dash
+    assert_eq!(
+        kinds("@alias-1"),
+        vec![TokenKind::Table("alias".to_owned()), TokenKind::Operator("-"), TokenKind::Number("1".to_owned())]
+    );
+    assert_eq!(
+        kinds("@abcd-12345"),
+        vec![TokenKind::Table("abcd".to_owned()), TokenKind::Operator("-"), TokenKind::Number("12345".to_owned())]
+    );
+}
+
+#[test]
+fn lexes_comments() {
+    assert_eq!(
+        kinds("a -- one\nb /* two\nlines */ c"),
+        vec![
+            ident("a"),
+            TokenKind::Comment(" one".to_owned()),
+            ident("b"),
+            TokenKind::Comment(" two\nlines ".to_owned()),
+            ident("c"),
+        ]
+    );
+}
+
+#[test]
+fn lexes_unicode_words() {
+    assert_eq!(kinds("straße = 'café'"), vec![
+        ident("straße"),
+        TokenKind::Operator("="),
+        TokenKind::String("café".to_owned()),
+    ]);
+}
+
+#[test]
+fn token_spans_are_byte_ranges() {
+    let src = "SELECT 'é', `x y` -- c\n:id";
+    let tokens = tokenize(src).unwrap();
+    let texts: Vec<&str> = tokens.iter().map(|t| &src[t.span.clone()]).collect();
+    assert_eq!(texts, vec!["SELECT", "'é'", ",", "`x y`", "-- c", ":id"]);
+}
+
+#[test]
+fn keywords_are_case_insensitive() {
+    let tokens = tokenize("sElEcT").unwrap();
+    assert!(tokens[0].is_keyword("SELECT"));
+    assert!(!tokens[0].is_keyword("WHERE"));
+    assert!(super::is_keyword("group"));
+    assert!(!super::is_keyword("groups"));
+}
+
+#[test]
+fn lexer_errors() {
+    let e = tokenize("SELECT 'open").unwrap_err();
+    assert_eq!(e.message, "Unterminated string");
+    assert_eq!(e.span, 7..12);
+    assert_eq!(tokenize("`open").unwrap_err().message, "Unterminated column name");
+    assert_eq!(tokenize("a /* open").unwrap_err().span, 2..9);
+    let e = tokenize("a # b").unwrap_err();
+    assert_eq!(e.message, "Unexpected character `#`");
+    assert_eq!(e.span, 2..3);
+    assert_eq!(tokenize("a : b").unwrap_err().span, 2..3);
+    assert_eq!(tokenize("@ x").unwrap_err().span, 0..1);
+}
+
+// Clauses
+
+#[test]
+fn select_star() {
+    assert_eq!(
+        select("SELECT *"),
+        Select {
+            columns: vec![SelectItem::All],
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn select_columns_with_aliases() {
+    let s = select("SELECT a, b AS bee, `c d` AS `see dee`, :id, :* ");
+    assert_eq!(
+        s.columns,
+        vec![
+            item(col("a")),
+            SelectItem::Expr {
+                expr: col("b"),
+                alias: Some("bee".to_owned())
+            },
+            SelectItem::Expr {
+                expr: col("c d"),
+                alias: Some("see dee".to_owned())
+            },
+            item(col(":id")),
+            SelectItem::System,
+        ]
+    );
+}
+
+#[test]
+fn select_distinct() {
+    let s = select("select distinct borough");
+    assert!(s.distinct);
+    assert_eq!(s.columns, vec![item(col("borough"))]);
+}
+
+#[test]
+fn select_is_optional() {
+    let s = select("WHERE a > 1 LIMIT 5");
+    assert!(s.columns.is_empty());
+    assert_eq!(s.filter, Some(bin(col("a"), BinaryOp::Gt, num("1"))));
+    assert_eq!(s.limit, Some(5));
+}
+
+#[test]
+fn every_clause() {
+    let s = select(
+        "SELECT borough, count(*) AS n WHERE year >= 2020 GROUP BY borough HAVING n > 10 \
+         ORDER BY n DESC, borough SEARCH 'noise' LIMIT 100 OFFSET 200",
+    );
+    assert_eq!(
+        s,
+        Select {
+            distinct: false,
+            columns: vec![
+                item(col("borough")),
+                SelectItem::Expr {
+                    expr: call("count", vec![Expr::Star]),
+                    alias: Some("n".to_owned())
+                },
+            ],
+            from: None,
+            joins: vec![],
+            filter: Some(bin(col("year"), BinaryOp::GtEq, num("2020"))),
+            group_by: vec![col("borough")],
+            having: Some(bin(col("n"), BinaryOp::Gt, num("10"))),
+            order_by: vec![order(col("n"), true), order(col("borough"), false)],
+            search: Some("noise".to_owned()),
+            limit: Some(100),
+            offset: Some(200),
+        }
+    );
+}
+
+#[test]
+fn search_before_order_by() {
+    let s = select("SELECT * SEARCH 'x' ORDER BY a");
+    assert_eq!(s.search, Some("x".to_owned()));
+    assert_eq!(s.order_by, vec![order(col("a"), false)]);
+}
+
+#[test]
+fn offset_before_limit() {
+    let s = select("SELECT * OFFSET 10 LIMIT 5");
+    assert_eq!((s.limit, s.offset), (Some(5), Some(10)));
+}
+
+#[test]
+fn order_by_directions_and_nulls() {
+    let s = select("SELECT * ORDER BY a ASC, b DESC NULLS FIRST, c NULLS LAST");
+    assert_eq!(
+        s.order_by,
+        vec![
+            order(col("a"), false),
+            OrderBy {
+                expr: col("b"),
+                descending: true,
+                nulls: Some(Nulls::First)
+            },
+            OrderBy {
+                expr: col("c"),
+                descending: false,
+                nulls: Some(Nulls::Last)
+            },
+        ]
+    );
+}
+
+#[test]
+fn group_by_expressions() {
+    let s = select("SELECT date_trunc_ym(created) AS m, count(*) GROUP BY date_trunc_ym(created), :id");
+    assert_eq!(s.group_by, vec![call("date_trunc_ym", vec![col("created")]), col(":id")]);
+}
+
+#[test]
+fn from_table() {
+    let s = select("SELECT a FROM @abcd-1234 AS t");
+    assert_eq!(
+        s.from,
+        Some(TableRef {
+            name: "abcd-1234".to_owned(),
+            alias: Some("t".to_owned())
+        })
+    );
+    assert_eq!(select("SELECT a FROM @this").from.unwrap().alias, None);
+}
+
+#[test]
+fn joins() {
+    let s = select(
+        "SELECT a, @d.name, @d.* JOIN @dogs-1234 AS @d ON a = @d.owner \
+         LEFT OUTER JOIN @cats-5678 AS c ON @c.id = b RIGHT JOIN @x ON true FULL JOIN @y ON false INNER JOIN @z ON 1 = 1",
+    );
+    assert_eq!(
+        s.columns,
+        vec![
+            item(col("a")),
+            item(Expr::Column(Column {
+                qualifier: Some("d".to_owned()),
+                name: "name".to_owned()
+            })),
+            SelectItem::Table("d".to_owned()),
+        ]
+    );
+    let kinds: Vec<JoinKind> = s.joins.iter().map(|j| j.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![JoinKind::Inner, JoinKind::Left, JoinKind::Right, JoinKind::Full, JoinKind::Inner]
+    );
+    assert_eq!(s.joins[0].source, JoinSource::Table("dogs-1234".to_owned()));
+    assert_eq!(s.joins[0].alias, Some("d".to_owned()));
+    assert_eq!(
+        s.joins[0].on,
+        bin(
+            col("a"),
+            BinaryOp::Eq,
+            Expr::Column(Column {
+                qualifier: Some("d".to_owned()),
+                name: "owner".to_owned()
+            })
+        )
+    );
+    assert_eq!(s.joins[1].alias, Some("c".to_owned()));
+    assert_eq!(s.joins[2].alias, None);
+}
+
+#[test]
+fn join_subquery() {
+    let s = select("SELECT a JOIN (SELECT id, count(*) AS n FROM @abcd-1234 GROUP BY id) AS j ON @j.id = a");
+    match &s.joins[0].source {
+        JoinSource::Query(q) => match q.as_ref() {
+            Query::Select(inner) => {
+                assert_eq!(inner.from.as_ref().unwrap().name, "abcd-1234");
+                assert_eq!(inner.group_by, vec![col("id")]);
+            }
+            other => panic!("{:?}", other),
+        },
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(s.joins[0].alias, Some("j".to_owned()));
+}
+
+#[test]
+fn left_as_a_column_name_is_not_a_join() {
+    let e = error("SELECT a WHERE left = 1");
+    assert!(e.message.contains("expression"), "{}", e.message);
+}
+
+#[test]
+fn pipes() {
+    let q = parse("SELECT a, count(*) AS n GROUP BY a |> SELECT max(n) |> SELECT *").unwrap();
+    match q {
+        Query::Pipe { left, right } => {
+            assert!(matches!(*right, Query::Select(_)));
+            match *left {
+                Query::Pipe { left, right } => {
+                    assert_eq!(
+                        match *left {
+                            Query::Select(s) => s.group_by,
+                            other => panic!("{:?}", other),
+                        },
+                        vec![col("a")]
+                    );
+                    assert!(matches!(*right, Query::Select(_)));
+                }
+                other => panic!("{:?}", other),
+            }
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn set_operations() {
+    let ops = [
+        ("UNION", SetOp::Union),
+        ("UNION ALL", SetOp::UnionAll),
+        ("INTERSECT", SetOp::Intersect),
+        ("INTERSECT ALL", SetOp::IntersectAll),
+        ("MINUS", SetOp::Minus),
+        ("MINUS ALL", SetOp::MinusAll),
+    ];
+    for (text, expected) in ops {
+        match parse(&format!("SELECT a {} SELECT a FROM @abcd-1234", text)).unwrap() {
+            Query::Compound { op, right, .. } => {
+                assert_eq!(op, expected, "{}", text);
+                match *right {
+                    Query::Select(s) => assert_eq!(s.from.unwrap().name, "abcd-1234"),
+                    other => panic!("{:?}", other),
+                }
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
+
+#[test]
+fn set_operations_group_left_and_bind_tighter_than_pipes() {
+    let q = parse("SELECT a UNION SELECT b FROM @aaaa-aaaa MINUS SELECT c FROM @bbbb-bbbb |> SELECT a").unwrap();
+    match q {
+        Query::Pipe { left, .. } => match *left {
+            Query::Compound { left, op: SetOp::Minus, .. } => {
+                assert!(matches!(*left, Query::Compound { op: SetOp::Union, .. }))
+            }
+            other => panic!("{:?}", other),
+        },
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn comments_are_ignored() {
+    assert_eq!(
+        select("SELECT a -- first\n, /* second */ b\n-- WHERE x = 1\nLIMIT 3"),
+        select("SELECT a, b LIMIT 3")
+    );
+}
+
+#[test]
+fn keywords_any_case() {
+    assert_eq!(
+        select("select a where b is not null order by a desc limit 1"),
+        select("SELECT a WHERE b IS NOT NULL ORDER BY a DESC LIMIT 1")
+    );
+}
+
+// Expressions
+
+#[test]
+fn literals() {
+    assert_eq!(expr("1"), num("1"));
+    assert_eq!(expr("'it''s'"), string("it's"));
+    assert_eq!(expr("TRUE"), Expr::Bool(true));
+    assert_eq!(expr("false"), Expr::Bool(false));
+    assert_eq!(expr("null"), Expr::Null);
+}
+
+#[test]
+fn arithmetic_precedence() {
+    assert_eq!(
+        expr("1 + 2 * 3 - 4 / 5 % 6"),
+        bin(
+            bin(num("1"), BinaryOp::Add, bin(num("2"), BinaryOp::Mul, num("3"))),
+            BinaryOp::Sub,
+            bin(bin(num("4"), BinaryOp::Div, num("5")), BinaryOp::Mod, num("6"))
+        )
+    );
+}
+
+#[test]
+fn power_is_right_associative_and_beats_negation() {
+    assert_eq!(
+        expr("-2 ^ 3 ^ 2"),
+        unary(
+            UnaryOp::Neg,
+            bin(num("2"), BinaryOp::Pow, bin(num("3"), BinaryOp::Pow, num("2")))
+        )
+    );
+    assert_eq!(expr("2 ^ -1"), bin(num("2"), BinaryOp::Pow, unary(UnaryOp::Neg, num("1"))));
+    assert_eq!(expr("+a"), unary(UnaryOp::Plus, col("a")));
+}
+
+#[test]
+fn concatenation_binds_looser_than_arithmetic() {
+    assert_eq!(
+        expr("a || b + 1"),
+        bin(col("a"), BinaryOp::Concat, bin(col("b"), BinaryOp::Add, num("1")))
+    );
+}
+
+#[test]
+fn comparisons() {
+    let ops = [
+        ("=", BinaryOp::Eq),
+        ("!=", BinaryOp::NotEq),
+        ("<>", BinaryOp::NotEq),
+        ("<", BinaryOp::Lt),
+        ("<=", BinaryOp::LtEq),
+        (">", BinaryOp::Gt),
+        (">=", BinaryOp::GtEq),
+    ];
+    for (text, op) in ops {
+        assert_eq!(expr(&format!("a {} 1", text)), bin(col("a"), op, num("1")), "{}", text);
+    }
+}
+
+#[test]
+fn boolean_precedence() {
+    assert_eq!(
+        expr("a = 1 OR b = 2 AND NOT c = 3"),
+        bin(
+            bin(col("a"), BinaryOp::Eq, num("1")),
+            BinaryOp::Or,
+            bin(
+                bin(col("b"), BinaryOp::Eq, num("2")),
+                BinaryOp::And,
+                unary(UnaryOp::Not, bin(col("c"), BinaryOp::Eq, num("3")))
+            )
+        )
+    );
+}
+
+#[test]
+fn parentheses_are_kept() {
+    assert_eq!(
+        expr("(a OR b) AND c"),
+        bin(
+            Expr::Paren(Box::new(bin(col("a"), BinaryOp::Or, col("b")))),
+            BinaryOp::And,
+            col("c")
+        )
+    );
+}
+
+#[test]
+fn is_null() {
+    assert_eq!(
+        expr("a IS NULL"),
+        Expr::IsNull {
+            expr: Box::new(col("a")),
+            negated: false
+        }
+    );
+    assert_eq!(
+        expr("a IS NOT NULL"),
+        Expr::IsNull {
+            expr: Box::new(col("a")),
+            negated: true
+        }
+    );
+}
+
+#[test]
+fn in_lists() {
+    assert_eq!(
+        expr("borough IN ('BRONX', 'QUEENS')"),
+        Expr::In {
+            expr: Box::new(col("borough")),
+            list: vec![string("BRONX"), string("QUEENS")],
+            negated: false
+        }
+    );
+    assert_eq!(
+        expr("a NOT IN (1)"),
+        Expr::In {
+            expr: Box::new(col("a")),
+            list: vec![num("1")],
+            negated: true
+        }
+    );
+}
+
+#[test]
+fn like() {
+    assert_eq!(
+        expr("name NOT LIKE '%PARK%'"),
+        Expr::Like {
+            expr: Box::new(col("name")),
+            pattern: Box::new(string("%PARK%")),
+            negated: true
+        }
+    );
+}
+
+#[test]
+fn between_takes_its_own_and() {
+    assert_eq!(
+        expr("a BETWEEN 1 AND 5 AND b"),
+        bin(
+            Expr::Between {
+                expr: Box::new(col("a")),
+                low: Box::new(num("1")),
+                high: Box::new(num("5")),
+                negated: false
+            },
+            BinaryOp::And,
+            col("b")
+        )
+    );
+    assert!(matches!(expr("a NOT BETWEEN 1 AND 2"), Expr::Between { negated: true, .. }));
+}
+
+#[test]
+fn not_before_a_predicate() {
+    assert_eq!(
+        expr("NOT a IN (1)"),
+        unary(
+            UnaryOp::Not,
+            Expr::In {
+                expr: Box::new(col("a")),
+                list: vec![num("1")],
+                negated: false
+            }
+        )
+    );
+}
+
+#[test]
+fn function_calls() {
+    assert_eq!(expr("now()"), call("now", vec![]));
+    assert_eq!(
+        expr("within_circle(location, 40.7, -73.9, 500)"),
+        call(
+            "within_circle",
+            vec![col("location"), num("40.7"), unary(UnaryOp::Neg, num("73.9")), num("500")]
+        )
+    );
+    assert_eq!(
+        expr("count(DISTINCT a)"),
+        Expr::Call(Call {
+            name: "count".to_owned(),
+            distinct: true,
+            args: vec![col("a")],
+            window: None
+        })
+    );
+    assert_eq!(
+        expr("upper(trim(a))"),
+        call("upper", vec![call("trim", vec![col("a")])])
+    );
+    assert_eq!(
+        expr("case(a > 1, 'big', true, 'small')"),
+        call(
+            "case",
+            vec![bin(col("a"), BinaryOp::Gt, num("1")), string("big"), Expr::Bool(true), string("small")]
+        )
+    );
+}
+
+#[test]
+fn window_functions() {
+    assert_eq!(
+        expr("row_number() OVER (PARTITION BY a, b ORDER BY c DESC)"),
+        Expr::Call(Call {
+            name: "row_number".to_owned(),
+            distinct: false,
+            args: vec![],
+            window: Some(Window {
+                partition_by: vec![col("a"), col("b")],
+                order_by: vec![order(col("c"), true)],
+            })
+        })
+    );
+    assert_eq!(
+        expr("sum(x) over ()"),
+        Expr::Call(Call {
+            name: "sum".to_owned(),
+            distinct: false,
+            args: vec![col("x")],
+            window: Some(Window {
+                partition_by: vec![],
+                order_by: vec![],
+            })
+        })
+    );
+}
+
+#[test]
+fn casts() {
+    assert_eq!(
+        expr("a::number + 1"),
+        bin(
+            Expr::Cast {
+                expr: Box::new(col("a")),
+                ty: "number".to_owned()
+            },
+            BinaryOp::Add,
+            num("1")
+        )
+    );
+    assert_eq!(
+        expr("'1'::number::text"),
+        Expr::Cast {
+            expr: Box::new(Expr::Cast {
+                expr: Box::new(string("1")),
+                ty: "number".to_owned()
+            }),
+            ty: "text".to_owned()
+        }
+    );
+}
+
+#[test]
+fn qualified_columns() {
+    assert_eq!(
+        expr("@j.:id"),
+        Expr::Column(Column {
+            qualifier: Some("j".to_owned()),
+            name: ":id".to_owned()
+        })
+    );
+    assert_eq!(
+        expr("@j.`odd name`"),
+        Expr::Column(Column {
+            qualifier: Some("j".to_owned()),
+            name: "odd name".to_owned()
+        })
+    );
+}
+
+// Errors
+
+#[test]
+fn empty_query() {
+    assert_eq!(error("").message, "The query is empty");
+    assert_eq!(error("  -- nothing\n").message, "The query is empty");
+}
+
+#[test]
+fn missing_expression_points_at_end() {
+    let src = "SELECT a WHERE";
+    let e = error(src);
+    assert_eq!(e.message, "Expected an expression at the end of the query");
+    assert_eq!(e.span, src.len()..src.len());
+}
+
+#[test]
+fn trailing_comma() {
+    let e = error("SELECT a, WHERE b = 1");
+    assert_eq!(e.message, "Expected an expression but found `WHERE`");
+    assert_eq!(e.span, 10..15);
+}
+
+#[test]
+fn unbalanced_parentheses() {
+    assert_eq!(error("SELECT (a + 1").message, "Expected `)` at the end of the query");
+    let e = error("SELECT a + 1)");
+    assert_eq!(e.message, "Unexpected `)`");
+    assert_eq!(e.span, 12..13);
+}
+
+#[test]
+fn clauses_out_of_order() {
+    let e = error("SELECT a LIMIT 5 WHERE b = 1");
+    assert!(e.message.starts_with("Unexpected `WHERE`; each clause may appear once"), "{}", e.message);
+    assert_eq!(e.span, 17..22);
+    assert!(error("SELECT a LIMIT 1 LIMIT 2").message.starts_with("Unexpected `LIMIT`"));
+}
+
+#[test]
+fn missing_by() {
+    assert_eq!(error("SELECT a GROUP a").message, "Expected `BY` but found `a`");
+    assert_eq!(error("SELECT a ORDER a").message, "Expected `BY` but found `a`");
+}
+
+#[test]
+fn bad_limit() {
+    assert_eq!(error("SELECT a LIMIT 1.5").message, "LIMIT needs a whole number, not 1.5");
+    assert_eq!(error("SELECT a OFFSET x").message, "Expected a number after OFFSET but found `x`");
+}
+
+#[test]
+fn bad_search() {
+    assert_eq!(error("SELECT a SEARCH noise").message, "Expected a quoted search term but found `noise`");
+}
+
+#[test]
+fn keyword_as_column() {
+    assert_eq!(error("SELECT from").message, "Expected an expression but found `from`");
+    // Quoting makes it a column
+    assert_eq!(expr("`from`"), col("from"));
+}
+
+#[test]
+fn bad_alias() {
+    assert_eq!(error("SELECT a AS where").message, "Expected an alias but found `where`");
+}
+
+#[test]
+fn incomplete_predicates() {
+    assert_eq!(error("SELECT a WHERE b IS 1").message, "Expected `NULL` but found number 1");
+    assert_eq!(error("SELECT a WHERE b IN 1").message, "Expected `(` but found number 1");
+    assert_eq!(
+        error("SELECT a WHERE b BETWEEN 1 OR 2").message,
+        "Expected `AND` but found `OR`"
+    );
+    assert_eq!(error("SELECT a ORDER BY a NULLS b").message, "Expected `FIRST` or `LAST` but found `b`");
+}
+
+#[test]
+fn bad_join() {
+    assert_eq!(
+        error("SELECT a JOIN dogs ON true").message,
+        "Expected a table like `@abcd-1234` but found `dogs`"
+    );
+    assert_eq!(error("SELECT a JOIN @dogs-1234 a = b").message, "Expected `ON` but found `a`");
+}
+
+#[test]
+fn empty_pipe_stage() {
+    assert_eq!(error("SELECT a |>").message, "Expected `SELECT` at the end of the query");
+    assert_eq!(error("SELECT a UNION").message, "Expected `SELECT` at the end of the query");
+}
+
+#[test]
+fn lexer_errors_surface_from_parse() {
+    let e = error("SELECT a WHERE b = 'x");
+    assert_eq!(e.message, "Unterminated string");
+    assert_eq!(e.span, 19..21);
+}
+
+#[test]
+fn bad_cast() {
+    assert_eq!(error("SELECT a::1").message, "Expected a type name but found number 1");
+}
+
+#[test]
+fn display_is_the_message() {
+    assert_eq!(error("SELECT a,").to_string(), "Expected an expression at the end of the query");
+}
+
+#[test]
+fn marker_falls_back_to_the_last_character() {
+    let src = "SELECT a WHERE  \n";
+    let e = error(src);
+    assert_eq!(&src[e.marker(src)], "E");
+    let src = "SELECT a, WHERE";
+    let e = error(src);
+    assert_eq!(&src[e.marker(src)], "WHERE");
+}
+
+#[test]
+fn line_and_column() {
+    let src = "SELECT a\nWHERE ‘b’ = 1";
+    let e = error(src);
+    assert_eq!(e.line_col(src), (2, 7));
+}
+
+// Completion
+
+fn schema_columns() -> Vec<crate::socrata::schema::SchemaColumn> {
+    [("Borough", "borough", "text"), ("Created Date", "created_date", "calendar_date"), ("Unit Type", "unit type", "text")]
+        .iter()
+        .map(|(name, field_name, data_type)| crate::socrata::schema::SchemaColumn {
+            name: name.to_string(),
+            field_name: field_name.to_string(),
+            data_type: data_type.to_string(),
+            description: None,
+            cached_contents: None,
+        })
+        .collect()
+}
+
+/// Labels offered with the cursor at the end of `src`
+fn labels(src: &str) -> Vec<String> {
+    super::complete::complete(src, src.len(), &schema_columns())
+        .items
+        .into_iter()
+        .map(|c| c.label)
+        .collect()
+}
+
+#[test]
+fn completes_columns_by_prefix() {
+    let completions = super::complete::complete("SELECT bo", 9, &schema_columns());
+    assert_eq!(completions.range, 7..9);
+    assert_eq!(completions.items[0].label, "borough");
+    assert_eq!(completions.items[0].insert, "borough");
+    assert_eq!(completions.items[0].detail, "text · Borough");
+}
+
+#[test]
+fn completion_quotes_odd_field_names() {
+    let completions = super::complete::complete("SELECT uni", 10, &schema_columns());
+    assert_eq!(completions.items[0].insert, "`unit type`");
+}
+
+#[test]
+fn completes_functions_with_signatures() {
+    let completions = super::complete::complete("SELECT within_c", 15, &[]);
+    assert_eq!(completions.items[0].label, "within_circle(location, lat, lon, meters)");
+    assert_eq!(completions.items[0].insert, "within_circle(");
+}
+
+#[test]
+fn no_aggregates_in_where() {
+    assert!(labels("SELECT * WHERE co").iter().all(|l| !l.starts_with("count(")));
+    assert!(labels("SELECT co").iter().any(|l| l.starts_with("count(")));
+}
+
+#[test]
+fn aggregates_come_first_once_grouped() {
+    let grouped = labels("SELECT borough GROUP BY borough HAVING ");
+    assert_eq!(grouped[0], "avg(number)");
+    let ungrouped = labels("SELECT ");
+    assert_eq!(ungrouped[0], "borough");
+    // A GROUP BY after the cursor counts too
+    let src = "SELECT  GROUP BY borough";
+    let items = super::complete::complete(src, 7, &schema_columns()).items;
+    assert_eq!(items[0].label, "avg(number)");
+}
+
+#[test]
+fn group_by_offers_columns() {
+    let items = labels("SELECT count(*) GROUP BY ");
+    assert_eq!(items[0], "borough");
+    assert!(items.iter().all(|l| !l.starts_with("count(")));
+}
+
+#[test]
+fn completes_next_clauses_after_a_value() {
+    assert_eq!(labels("SELECT borough wh"), vec!["WHERE"]);
+    assert_eq!(labels("SELECT borough WHERE borough = 'X' gr"), vec!["GROUP BY"]);
+    assert_eq!(labels("SELECT * ORDER BY borough de"), vec!["DESC"]);
+    assert!(labels("SELECT * WHERE a = 1 ").contains(&"AND".to_owned()));
+    // Clauses never go backwards
+    assert!(labels("SELECT * LIMIT 5 ").iter().all(|l| l != "WHERE"));
+}
+
+#[test]
+fn completes_clauses_at_the_start() {
+    assert_eq!(labels("sel"), vec!["SELECT"]);
+    // Prefix matches come before words that merely contain the text
+    assert_eq!(labels("se"), vec!["SELECT", "SEARCH", "OFFSET"]);
+    assert_eq!(labels("SELECT a |> sel"), vec!["SELECT"]);
+}
+
+#[test]
+fn completes_system_columns() {
+    let columns: Vec<_> = schema_columns();
+    let schema = crate::socrata::schema::DatasetSchema {
+        id: "abcd-1234".to_owned(),
+        name: "Test".to_owned(),
+        description: None,
+        rows_updated_at: None,
+        columns,
+    };
+    let items = super::complete::complete("SELECT :cr", 10, &schema.queryable_columns()).items;
+    assert_eq!(items[0].insert, ":created_at");
+}
+
+#[test]
+fn nothing_inside_strings_comments_or_values() {
+    assert!(labels("SELECT * WHERE a = 'bo").is_empty());
+    assert!(labels("SELECT * -- bo").is_empty());
+    assert!(labels("SELECT * /* bo").is_empty());
+    assert!(labels("SELECT * LIMIT ").is_empty());
+    assert!(labels("SELECT a AS ").is_empty());
+    assert!(labels("SELECT @j.").is_empty());
+    assert!(labels("SELECT * WHERE a = 12").is_empty());
+}
+
+#[test]
+fn complete_word_offers_nothing() {
+    assert!(labels("SELECT borough").is_empty());
+}
+
+#[test]
+fn completion_inside_function_arguments() {
+    assert_eq!(labels("SELECT upper(bo")[0], "borough");
+}
+
+// Formatting
+
+fn formatted(src: &str) -> String {
+    let out = super::format_query(src).unwrap();
+    // Formatting never changes what the query means, and is stable.
+    // Function names are lower cased, so the tests write them that way.
+    assert_eq!(parse(&out).unwrap(), parse(src).unwrap(), "{}", out);
+    assert_eq!(super::format_query(&out).unwrap(), out);
+    out
+}
+
+#[test]
+fn formats_clauses_on_their_own_lines() {
+    assert_eq!(
+        formatted("select borough, count(*) as n where created_date > '2020-01-01' and status='Open' group by borough order by n desc limit 10"),
+        "SELECT borough,\n       count(*) AS n\nWHERE created_date > '2020-01-01'\n  AND status = 'Open'\nGROUP BY borough\nORDER BY n DESC\nLIMIT 10"
+    );
+}
+
+#[test]
+fn aligns_select_aliases() {
+    assert_eq!(
+        formatted("SELECT DISTINCT a AS x, max(long_name) AS y, b"),
+        "SELECT DISTINCT a              AS x,\n                max(long_name) AS y,\n                b"
+    );
+}
+
+#[test]
+fn indents_case_calls() {
+    assert_eq!(
+        formatted("SELECT case(a > 1, 'big', a > 0, case(b, 'x', TRUE, 'y'), TRUE, 'none') AS size"),
+        "SELECT case(\n           a > 1, 'big',\n           a > 0, case(\n               b, 'x',\n               TRUE, 'y'\n           ),\n           TRUE, 'none'\n       ) AS size"
+    );
+}
+
+#[test]
+fn indents_subqueries() {
+    assert_eq!(
+        formatted("SELECT a, @b.c JOIN (SELECT c, d WHERE d > 1) AS @b ON a = @b.d |> SELECT count(*)"),
+        "SELECT a,\n       @b.c\nJOIN (\n    SELECT c,\n           d\n    WHERE d > 1\n) AS @b ON a = @b.d\n|>\nSELECT count(*)"
+    );
+}
+
+#[test]
+fn keeps_literals_as_written() {
+    assert_eq!(
+        formatted("select `unit type`, 'it''s  here', 1.50e3 where x <> \"a  b\" search 'two  words'"),
+        "SELECT `unit type`,\n       'it''s  here',\n       1.50e3\nWHERE x != 'a  b'\nSEARCH 'two  words'"
+    );
+}
+
+#[test]
+fn keeps_comments() {
+    assert_eq!(
+        formatted("-- busiest boroughs\nselect borough, -- the area\n count(*) /* all rows */ as n\n-- only open ones\nwhere status = 'Open'"),
+        "-- busiest boroughs\nSELECT borough, -- the area\n       count(*) /* all rows */ AS n\n-- only open ones\nWHERE status = 'Open'"
+    );
+}
+
+#[test]
+fn keeps_nested_negation_apart() {
+    assert_eq!(formatted("select - -a, -(-1), -b where c > - - 2"), "SELECT - -a,\n       -(-1),\n       -b\nWHERE c > - -2");
+}
+
+#[test]
+fn formatting_needs_a_valid_query() {
+    assert!(super::format_query("SELECT a WHERE").is_err());
+}
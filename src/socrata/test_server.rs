@@ -0,0 +1,84 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// One canned response
+pub struct Reply {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    /// Extra `Name: value\r\n` header lines
+    pub headers: &'static str,
+    pub body: &'static str,
+}
+
+/// Answers a single request with `status` and `body`, and hands back the
+/// request line. The domain to point a client at is returned first.
+pub async fn stand_in(status: &'static str, content_type: &'static str, body: &'static str) -> (String, oneshot::Receiver<String>) {
+    stand_in_with_headers(status, content_type, "", body).await
+}
+
+/// Like [`stand_in`], with extra `Name: value\r\n` header lines in the response
+pub async fn stand_in_with_headers(status: &'static str, content_type: &'static str, headers: &'static str, body: &'static str) -> (String, oneshot::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let domain = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let request = respond(socket, &Reply { status, content_type, headers, body }).await;
+        tx.send(request.lines().next().unwrap_or_default().to_owned()).unwrap();
+    });
+    (domain, rx)
+}
+
+/// Answers one request per reply, in order, and hands back every request
+/// with its headers once all of them have been answered
+pub async fn stand_in_replies(replies: Vec<Reply>) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let domain = format!("http://{}", listener.local_addr().unwrap());
+    let requests = tokio::spawn(async move {
+        let mut requests = vec![];
+        for reply in replies {
+            let (socket, _) = listener.accept().await.unwrap();
+            requests.push(respond(socket, &reply).await);
+        }
+        requests
+    });
+    (domain, requests)
+}
+
+/// Accepts connections and never answers them
+pub async fn stand_in_silent() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let domain = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut sockets = vec![];
+        while let Ok((socket, _)) = listener.accept().await {
+            sockets.push(socket);
+        }
+    });
+    domain
+}
+
+/// Reads the request head and writes `reply`, closing the connection
+async fn respond(mut socket: TcpStream, reply: &Reply) -> String {
+    let mut request = vec![];
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = socket.read(&mut buf).await.unwrap();
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        reply.status,
+        reply.content_type,
+        reply.headers,
+        reply.body.len(),
+        reply.body
+    );
+    socket.write_all(response.as_bytes()).await.unwrap();
+    String::from_utf8_lossy(&request).into_owned()
+}
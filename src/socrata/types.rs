@@ -0,0 +1,448 @@
+use std::cmp::Ordering;
+
+use serde_json::{json, Value as Json};
+
+use super::schema::{DatasetSchema, SYSTEM_COLUMNS};
+
+/// A column's type as Socrata names it in `X-SODA2-Types` and `dataTypeName`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColumnType {
+    Text,
+    Number,
+    Double,
+    Money,
+    Checkbox,
+    /// A date and time without a time zone
+    FloatingTimestamp,
+    /// A date and time in UTC
+    FixedTimestamp,
+    Point,
+    MultiPoint,
+    Line,
+    MultiLine,
+    Polygon,
+    MultiPolygon,
+    Url,
+    /// Anything else, e.g. `row_identifier` or `photo`, which is shown as text
+    Other(String),
+}
+
+impl ColumnType {
+    pub fn from_name(name: &str) -> Self {
+        match name.trim() {
+            "text" => ColumnType::Text,
+            // `percent` is a legacy name for numbers
+            "number" | "percent" => ColumnType::Number,
+            "double" => ColumnType::Double,
+            "money" => ColumnType::Money,
+            "checkbox" => ColumnType::Checkbox,
+            "floating_timestamp" | "calendar_date" | "date" => ColumnType::FloatingTimestamp,
+            "fixed_timestamp" => ColumnType::FixedTimestamp,
+            "point" => ColumnType::Point,
+            "multipoint" => ColumnType::MultiPoint,
+            "line" => ColumnType::Line,
+            "multiline" => ColumnType::MultiLine,
+            "polygon" => ColumnType::Polygon,
+            "multipolygon" => ColumnType::MultiPolygon,
+            "url" => ColumnType::Url,
+            other => ColumnType::Other(other.to_owned()),
+        }
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, ColumnType::Number | ColumnType::Double | ColumnType::Money)
+    }
+
+    pub fn is_geometry(&self) -> bool {
+        matches!(
+            self,
+            ColumnType::Point
+                | ColumnType::MultiPoint
+                | ColumnType::Line
+                | ColumnType::MultiLine
+                | ColumnType::Polygon
+                | ColumnType::MultiPolygon
+        )
+    }
+
+    /// Reads a CSV cell of this type. Empty cells are null, and cells that
+    /// don't parse are kept as text rather than lost.
+    pub fn decode<'a>(&self, raw: &'a str) -> Value<'a> {
+        if raw.is_empty() {
+            return Value::Null;
+        }
+        let decoded = match self {
+            // Socrata numbers are always finite; "NaN" or "inf" is just text
+            ColumnType::Number | ColumnType::Double | ColumnType::Money => raw
+                .trim()
+                .parse()
+                .ok()
+                .filter(|n: &f64| n.is_finite())
+                .map(Value::Number),
+            ColumnType::Checkbox => raw.trim().parse().ok().map(Value::Checkbox),
+            ColumnType::FloatingTimestamp | ColumnType::FixedTimestamp => parse_timestamp(raw).map(Value::Timestamp),
+            ColumnType::Url => Some(Value::Url(raw)),
+            t if t.is_geometry() => Some(Value::Geometry(raw)),
+            _ => None,
+        };
+        decoded.unwrap_or(Value::Text(raw))
+    }
+
+    /// The cell as it belongs in a JSON export: numbers and checkboxes keep
+    /// their type and geometry becomes GeoJSON, as in Socrata's own JSON
+    pub fn to_json(&self, raw: &str) -> Json {
+        match self.decode(raw) {
+            Value::Null => Json::Null,
+            Value::Number(_) => match raw.trim().parse() {
+                Ok(number) => Json::Number(number),
+                Err(_) => Json::String(raw.to_owned()),
+            },
+            Value::Checkbox(b) => Json::Bool(b),
+            Value::Geometry(wkt) => wkt_to_geojson(wkt).unwrap_or_else(|| Json::String(raw.to_owned())),
+            _ => Json::String(raw.to_owned()),
+        }
+    }
+}
+
+/// A decoded cell, borrowing any text from the CSV
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<'a> {
+    Null,
+    Text(&'a str),
+    Number(f64),
+    Checkbox(bool),
+    /// Milliseconds since the Unix epoch
+    Timestamp(i64),
+    /// Well-known text, e.g. `POINT (-73.9 40.7)`
+    Geometry(&'a str),
+    Url(&'a str),
+}
+
+impl Value<'_> {
+    fn rank(&self) -> u8 {
+        match self {
+            Value::Number(_) => 0,
+            Value::Checkbox(_) => 1,
+            Value::Timestamp(_) => 2,
+            Value::Text(_) | Value::Geometry(_) | Value::Url(_) => 3,
+            Value::Null => 4,
+        }
+    }
+
+    /// Orders numbers by value, timestamps by time and checkboxes false first.
+    /// Nulls sort last; cells that didn't decode sort after the ones that did.
+    pub fn compare(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a.total_cmp(b),
+            (Value::Checkbox(a), Value::Checkbox(b)) => a.cmp(b),
+            (Value::Timestamp(a), Value::Timestamp(b)) => a.cmp(b),
+            (Value::Text(a) | Value::Geometry(a) | Value::Url(a), Value::Text(b) | Value::Geometry(b) | Value::Url(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+/// A result column as reported by the `X-SODA2-Fields` and `X-SODA2-Types` headers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub field: String,
+    pub data_type: ColumnType,
+}
+
+impl Column {
+    /// Pairs up the JSON arrays of the two headers. Socrata leaves them out
+    /// when there are too many columns, so they are not always available.
+    pub fn from_headers(fields: &str, types: &str) -> Option<Vec<Column>> {
+        let fields: Vec<String> = serde_json::from_str(fields).ok()?;
+        let types: Vec<String> = serde_json::from_str(types).ok()?;
+        if fields.len() != types.len() {
+            return None;
+        }
+        let columns = fields
+            .into_iter()
+            .zip(types.iter())
+            .map(|(field, data_type)| Column { field, data_type: ColumnType::from_name(data_type) })
+            .collect();
+        Some(columns)
+    }
+}
+
+/// The type of every column in `header`, the first row of the results.
+/// The response's own columns come first, then the dataset's schema; columns
+/// neither knows about, e.g. an aggregate when the headers were left out, are text.
+pub fn column_types(header: &[String], columns: &[Column], schema: Option<&DatasetSchema>) -> Vec<ColumnType> {
+    let schema_type = |name: &str| {
+        let schema = schema?;
+        match schema.columns.iter().find(|c| c.field_name == name) {
+            Some(column) => Some(column.data_type.as_str()),
+            None => SYSTEM_COLUMNS.iter().find(|(field, _)| *field == name).map(|(_, data_type)| *data_type),
+        }
+    };
+    header
+        .iter()
+        .map(|name| match columns.iter().find(|c| &c.field == name) {
+            Some(column) => column.data_type.to_owned(),
+            None => schema_type(name).map_or(ColumnType::Text, ColumnType::from_name),
+        })
+        .collect()
+}
+
+/// Days from 1970-01-01 to the given date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Milliseconds since the Unix epoch of an ISO 8601 date or timestamp such as
+/// `2023-01-31T12:30:00.000` or `2023-01-31T12:30:00Z`. Floating timestamps,
+/// which have no offset, are read as UTC.
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let (date, time) = match s.find(['T', ' ']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let mut date_parts = date.split('-');
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month: i64 = date_parts.next()?.parse().ok()?;
+    let day: i64 = date_parts.next()?.parse().ok()?;
+    if date_parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let mut millis = days_from_civil(year, month, day) * 86_400_000;
+
+    let time = match time {
+        Some(time) => time,
+        None => return Some(millis),
+    };
+    // Split off the offset, if any
+    let (time, offset_minutes) = if let Some(time) = time.strip_suffix('Z') {
+        (time, 0)
+    } else if let Some(i) = time.rfind(['+', '-']) {
+        let (time, offset) = time.split_at(i);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let offset = offset[1..].replace(':', "");
+        if offset.len() != 4 {
+            return None;
+        }
+        let hours: i64 = offset[..2].parse().ok()?;
+        let minutes: i64 = offset[2..].parse().ok()?;
+        (time, sign * (hours * 60 + minutes))
+    } else {
+        (time, 0)
+    };
+
+    let (time, fraction) = match time.split_once('.') {
+        Some((time, fraction)) => (time, Some(fraction)),
+        None => (time, None),
+    };
+    let mut time_parts = time.split(':');
+    let hours: i64 = time_parts.next()?.parse().ok()?;
+    let minutes: i64 = time_parts.next()?.parse().ok()?;
+    let seconds: i64 = match time_parts.next() {
+        Some(seconds) => seconds.parse().ok()?,
+        None => 0,
+    };
+    if time_parts.next().is_some() || hours > 24 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    if let Some(fraction) = fraction {
+        if fraction.is_empty() || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        // Anything below a millisecond is dropped
+        let digits: String = fraction.chars().chain("00".chars()).take(3).collect();
+        millis += digits.parse::<i64>().ok()?;
+    }
+    millis += ((hours * 60 + minutes - offset_minutes) * 60 + seconds) * 1000;
+    Some(millis)
+}
+
+/// Converts well-known text, which is how Socrata writes geometry into CSV,
+/// to a GeoJSON geometry, e.g. `POINT (-73.9 40.7)` to
+/// `{"type": "Point", "coordinates": [-73.9, 40.7]}`
+pub fn wkt_to_geojson(wkt: &str) -> Option<Json> {
+    let wkt = wkt.trim();
+    let open = wkt.find('(')?;
+    let kind = match wkt[..open].trim().to_ascii_uppercase().as_str() {
+        "POINT" => "Point",
+        "MULTIPOINT" => "MultiPoint",
+        "LINESTRING" => "LineString",
+        "MULTILINESTRING" => "MultiLineString",
+        "POLYGON" => "Polygon",
+        "MULTIPOLYGON" => "MultiPolygon",
+        _ => return None,
+    };
+    let mut rest = &wkt[open..];
+    let coordinates = parse_coordinates(&mut rest)?;
+    if !rest.trim().is_empty() {
+        return None;
+    }
+    let coordinates = match (kind, coordinates) {
+        ("Point", Json::Array(mut positions)) if positions.len() == 1 => positions.remove(0),
+        ("Point", _) => return None,
+        // Both `MULTIPOINT (1 2, 3 4)` and `MULTIPOINT ((1 2), (3 4))` are valid
+        ("MultiPoint", Json::Array(points)) => Json::Array(
+            points
+                .into_iter()
+                .map(|point| match point {
+                    Json::Array(mut inner) if inner.len() == 1 && inner[0].is_array() => inner.remove(0),
+                    point => point,
+                })
+                .collect(),
+        ),
+        (_, coordinates) => coordinates,
+    };
+    Some(json!({ "type": kind, "coordinates": coordinates }))
+}
+
+/// Parses a parenthesised list whose items are nested lists or `x y` positions
+fn parse_coordinates(input: &mut &str) -> Option<Json> {
+    *input = input.trim_start().strip_prefix('(')?;
+    let mut items = vec![];
+    loop {
+        *input = input.trim_start();
+        if input.starts_with('(') {
+            items.push(parse_coordinates(input)?);
+        } else {
+            let end = input.find([',', ')'])?;
+            let position = input[..end]
+                .split_whitespace()
+                .map(|n| n.parse().ok().and_then(serde_json::Number::from_f64).map(Json::Number))
+                .collect::<Option<Vec<_>>>()?;
+            if position.len() < 2 {
+                return None;
+            }
+            items.push(Json::Array(position));
+            *input = &input[end..];
+        }
+        *input = input.trim_start();
+        match input.strip_prefix(',') {
+            Some(rest) => *input = rest,
+            None => {
+                *input = input.strip_prefix(')')?;
+                return Some(Json::Array(items));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socrata::schema::SchemaColumn;
+
+    #[test]
+    fn names_map_to_types() {
+        assert_eq!(ColumnType::from_name("number"), ColumnType::Number);
+        assert_eq!(ColumnType::from_name("calendar_date"), ColumnType::FloatingTimestamp);
+        assert_eq!(ColumnType::from_name("multipolygon"), ColumnType::MultiPolygon);
+        assert_eq!(ColumnType::from_name("photo"), ColumnType::Other("photo".into()));
+    }
+
+    #[test]
+    fn decodes_cells() {
+        assert_eq!(ColumnType::Number.decode("12.5"), Value::Number(12.5));
+        assert_eq!(ColumnType::Number.decode(""), Value::Null);
+        assert_eq!(ColumnType::Number.decode("n/a"), Value::Text("n/a"));
+        assert_eq!(ColumnType::Number.decode("NaN"), Value::Text("NaN"));
+        assert_eq!(ColumnType::Checkbox.decode("true"), Value::Checkbox(true));
+        assert_eq!(ColumnType::Text.decode("12"), Value::Text("12"));
+        assert_eq!(ColumnType::Point.decode("POINT (1 2)"), Value::Geometry("POINT (1 2)"));
+        assert_eq!(
+            ColumnType::FloatingTimestamp.decode("1970-01-02T00:00:00.000"),
+            Value::Timestamp(86_400_000)
+        );
+    }
+
+    #[test]
+    fn numbers_sort_by_value() {
+        let mut cells = vec!["10", "", "NaN", "9", "-1.5", "x", "inf", "2"];
+        cells.sort_by(|a, b| ColumnType::Number.decode(a).compare(&ColumnType::Number.decode(b)));
+        assert_eq!(cells, vec!["-1.5", "2", "9", "10", "NaN", "inf", "x", ""]);
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("1970-01-01"), Some(0));
+        assert_eq!(parse_timestamp("2023-01-31T12:00:00.000"), Some(1_675_166_400_000));
+        assert_eq!(parse_timestamp("2023-01-31T12:00:00Z"), Some(1_675_166_400_000));
+        assert_eq!(parse_timestamp("2023-01-31T14:00:00+02:00"), Some(1_675_166_400_000));
+        assert_eq!(parse_timestamp("2023-01-31T12:00:00.5"), Some(1_675_166_400_500));
+        assert_eq!(parse_timestamp("1969-12-31T23:59:59"), Some(-1000));
+        assert_eq!(parse_timestamp("2024-02-29T00:00"), Some(1_709_164_800_000));
+        assert_eq!(parse_timestamp("2023-13-01"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+
+    #[test]
+    fn converts_wkt_to_geojson() {
+        assert_eq!(
+            wkt_to_geojson("POINT (-73.9 40.7)"),
+            Some(json!({"type": "Point", "coordinates": [-73.9, 40.7]}))
+        );
+        assert_eq!(
+            wkt_to_geojson("MULTIPOINT ((1 2), (3 4))"),
+            Some(json!({"type": "MultiPoint", "coordinates": [[1.0, 2.0], [3.0, 4.0]]}))
+        );
+        assert_eq!(
+            wkt_to_geojson("MULTIPOLYGON (((0 0, 1 0, 1 1, 0 0)), ((5 5, 6 5, 6 6, 5 5)))"),
+            Some(json!({"type": "MultiPolygon", "coordinates": [
+                [[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0]]],
+                [[[5.0, 5.0], [6.0, 5.0], [6.0, 6.0], [5.0, 5.0]]]
+            ]}))
+        );
+        assert_eq!(wkt_to_geojson("POINT EMPTY"), None);
+        assert_eq!(wkt_to_geojson("POINT (1 2"), None);
+    }
+
+    #[test]
+    fn cells_become_typed_json() {
+        assert_eq!(ColumnType::Number.to_json("12"), json!(12));
+        assert_eq!(ColumnType::Money.to_json("1.50"), json!(1.5));
+        assert_eq!(ColumnType::Checkbox.to_json("false"), json!(false));
+        assert_eq!(ColumnType::Number.to_json(""), Json::Null);
+        assert_eq!(ColumnType::Text.to_json("12"), json!("12"));
+        assert_eq!(ColumnType::Point.to_json("POINT (1 2)"), json!({"type": "Point", "coordinates": [1.0, 2.0]}));
+    }
+
+    #[test]
+    fn reads_response_headers() {
+        let columns = Column::from_headers(r#"["name","count"]"#, r#"["text","number"]"#).unwrap();
+        assert_eq!(columns[1], Column { field: "count".into(), data_type: ColumnType::Number });
+        assert_eq!(Column::from_headers(r#"["name"]"#, r#"[]"#), None);
+    }
+
+    #[test]
+    fn types_come_from_headers_then_schema() {
+        let schema = DatasetSchema {
+            id: "abcd-1234".into(),
+            name: "Trees".into(),
+            description: None,
+            rows_updated_at: None,
+            columns: vec![SchemaColumn {
+                name: "Planted".into(),
+                field_name: "planted".into(),
+                data_type: "floating_timestamp".into(),
+                description: None,
+                cached_contents: None,
+            }],
+        };
+        let header: Vec<String> = vec!["count".into(), "planted".into(), ":id".into(), "alias".into()];
+        let columns = vec![Column { field: "count".into(), data_type: ColumnType::Number }];
+        assert_eq!(
+            column_types(&header, &columns, Some(&schema)),
+            vec![
+                ColumnType::Number,
+                ColumnType::FloatingTimestamp,
+                ColumnType::Other("row_identifier".into()),
+                ColumnType::Text,
+            ]
+        );
+        assert_eq!(column_types(&header[..1], &[], None), vec![ColumnType::Text]);
+    }
+}
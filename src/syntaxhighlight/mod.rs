@@ -7,10 +7,26 @@ struct Highlighter {
     ts: syntect::highlighting::ThemeSet,
 }
 
+const SOQL_SYNTAX: &str = include_str!("soql.sublime-syntax");
+
+/// The bundled syntaxes plus SoQL, which they don't include
+fn syntax_set() -> syntect::parsing::SyntaxSet {
+    use syntect::parsing::{SyntaxDefinition, SyntaxSet};
+
+    let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+    let functions: Vec<&str> = crate::socrata::soql::FUNCTIONS.iter().map(|f| f.name).collect();
+    let functions = functions.join("|");
+    match SyntaxDefinition::load_from_str(&SOQL_SYNTAX.replace("__FUNCTIONS__", &functions), true, None) {
+        Ok(soql) => builder.add(soql),
+        Err(e) => eprintln!("Unable to load the SoQL syntax: {}", e),
+    }
+    builder.build()
+}
+
 impl Default for Highlighter {
     fn default() -> Self {
         Self {
-            ps: syntect::parsing::SyntaxSet::load_defaults_newlines(),
+            ps: syntax_set(),
             ts: syntect::highlighting::ThemeSet::load_defaults(),
         }
     }
@@ -88,14 +104,14 @@ fn as_byte_range(whole: &str, range: &str) -> std::ops::Range<usize> {
     offset..(offset + range.len())
 }
 
-/// Memoized Code highlighting
-pub fn highlight(ctx: &egui::Context, code: &str, language: &str) -> LayoutJob {
-    impl egui::util::cache::ComputerMut<(&str, &str), LayoutJob> for Highlighter {
-        fn compute(&mut self, (code, lang): (&str, &str)) -> LayoutJob {
-            self.highlight(code, lang)
-        }
+impl egui::util::cache::ComputerMut<(&str, &str), LayoutJob> for Highlighter {
+    fn compute(&mut self, (code, lang): (&str, &str)) -> LayoutJob {
+        self.highlight(code, lang)
     }
+}
 
+/// Memoized Code highlighting
+pub fn highlight(ctx: &egui::Context, code: &str, language: &str) -> LayoutJob {
     type HighlightCache = egui::util::cache::FrameCache<LayoutJob, Highlighter>;
 
     let mut memory = ctx.memory();
@@ -105,3 +121,33 @@ pub fn highlight(ctx: &egui::Context, code: &str, language: &str) -> LayoutJob {
 
 
 
+
+/// Underlines `range` of an already highlighted job, splitting sections at its edges
+pub fn underline(job: &mut LayoutJob, range: std::ops::Range<usize>, stroke: egui::Stroke) {
+    // The range may be stale by a frame while the user is typing
+    if range.end > job.text.len() || !job.text.is_char_boundary(range.start) || !job.text.is_char_boundary(range.end) {
+        return;
+    }
+    let mut sections = Vec::with_capacity(job.sections.len() + 2);
+    for section in job.sections.drain(..) {
+        let bytes = section.byte_range.clone();
+        let start = range.start.clamp(bytes.start, bytes.end);
+        let end = range.end.clamp(bytes.start, bytes.end);
+        if start >= end {
+            sections.push(section);
+            continue;
+        }
+        for (part, marked) in [(bytes.start..start, false), (start..end, true), (end..bytes.end, false)] {
+            if part.is_empty() {
+                continue;
+            }
+            let mut piece = section.clone();
+            piece.byte_range = part;
+            if marked {
+                piece.format.underline = stroke;
+            }
+            sections.push(piece);
+        }
+    }
+    job.sections = sections;
+}
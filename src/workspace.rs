@@ -0,0 +1,159 @@
+use egui_dock::{Node, NodeIndex, Tree};
+use serde_derive::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+use crate::panels::completion::CompletionPopup;
+use crate::panels::results::ResultsGrid;
+use crate::socrata::data::ResponseData;
+use crate::socrata::soql::SoqlError;
+
+/// A query with its own dataset and results, fetched by the jobs it starts.
+/// Only the dataset and the query text are kept across restarts.
+#[derive(Deserialize, Serialize)]
+pub struct QueryTab {
+    pub id: usize,
+    pub dataset: String,
+    pub current_query: String,
+    #[serde(skip)]
+    pub csv_data: ResponseData,
+    #[serde(skip)]
+    pub results_grid: ResultsGrid,
+    /// Parse error of `current_query`, refreshed whenever the text changes
+    #[serde(skip)]
+    pub query_error: Option<SoqlError>,
+    #[serde(skip)]
+    checked_query: Option<String>,
+    /// Send queries to Socrata even when they don't parse locally
+    #[serde(skip)]
+    pub run_invalid_query: bool,
+    #[serde(skip)]
+    pub completion: CompletionPopup,
+    #[serde(skip)]
+    pub url: String,
+    #[serde(skip)]
+    pub query_duration: Duration,
+}
+
+impl QueryTab {
+    pub fn new(id: usize, dataset: &str, query: &str) -> Self {
+        Self {
+            id,
+            dataset: dataset.to_owned(),
+            current_query: query.to_owned(),
+            csv_data: Default::default(),
+            results_grid: Default::default(),
+            query_error: None,
+            checked_query: None,
+            run_invalid_query: false,
+            completion: Default::default(),
+            url: "".into(),
+            query_duration: Duration::new(0, 0),
+        }
+    }
+
+    /// The dataset and the start of the query
+    pub fn title(&self) -> String {
+        let query: String = self.current_query.split_whitespace().collect::<Vec<_>>().join(" ");
+        let mut query: String = query.chars().take(24).collect();
+        if query.is_empty() {
+            query = "New query".into();
+        }
+        let dataset = self.dataset.trim();
+        if dataset.is_empty() {
+            query
+        } else {
+            format!("{} · {}", dataset, query)
+        }
+    }
+
+    pub fn editor_id(&self) -> egui::Id {
+        egui::Id::new(("query_editor", self.id))
+    }
+
+    pub fn check_query(&mut self) {
+        if self.checked_query.as_ref() != Some(&self.current_query) {
+            self.query_error = crate::socrata::soql::parse(&self.current_query).err();
+            self.checked_query = Some(self.current_query.to_owned());
+        }
+    }
+}
+
+/// The dockable query tabs
+#[derive(Deserialize, Serialize)]
+pub struct Workspace {
+    pub tree: Tree<QueryTab>,
+    next_id: usize,
+}
+
+impl Workspace {
+    pub fn new(dataset: &str, query: &str) -> Self {
+        Self {
+            tree: Tree::new(vec![QueryTab::new(0, dataset, query)]),
+            next_id: 1,
+        }
+    }
+
+    /// Opens a tab next to the focused one and focuses it
+    pub fn open(&mut self, dataset: &str, query: &str) {
+        let tab = QueryTab::new(self.next_id, dataset, query);
+        self.next_id += 1;
+        self.tree.push_to_focused_leaf(tab);
+    }
+
+    pub fn tabs_mut(&mut self) -> impl Iterator<Item = &mut QueryTab> {
+        self.tree
+            .iter_mut()
+            .filter_map(|node| match node {
+                Node::Leaf { tabs, .. } => Some(tabs.iter_mut()),
+                _ => None,
+            })
+            .flatten()
+    }
+
+    pub fn tab_mut(&mut self, id: usize) -> Option<&mut QueryTab> {
+        self.tabs_mut().find(|tab| tab.id == id)
+    }
+
+    /// Brings tab `id` to the front and focuses it
+    pub fn show_tab(&mut self, id: usize) {
+        let found = self.tree.iter().enumerate().find_map(|(node, leaf)| match leaf {
+            Node::Leaf { tabs, .. } => tabs.iter().position(|tab| tab.id == id).map(|tab| (node, tab)),
+            _ => None,
+        });
+        if let Some((node, tab)) = found {
+            self.tree.set_active_tab(NodeIndex(node), tab.into());
+            self.tree.set_focused_node(NodeIndex(node));
+        }
+    }
+
+    /// The tab last interacted with, or the first one when none was yet.
+    /// A new tab is opened if every tab was closed.
+    pub fn focused(&mut self) -> &mut QueryTab {
+        if self.tabs_mut().next().is_none() {
+            self.tree = Tree::new(vec![]);
+            self.open("", "");
+        }
+        let node = self
+            .tree
+            .focused_leaf()
+            .filter(|node| matches!(&self.tree[*node], Node::Leaf { tabs, .. } if !tabs.is_empty()));
+        let node = match node {
+            Some(node) => node,
+            None => {
+                let first = self
+                    .tree
+                    .iter()
+                    .position(|node| matches!(node, Node::Leaf { tabs, .. } if !tabs.is_empty()))
+                    .unwrap();
+                NodeIndex(first)
+            }
+        };
+        match &mut self.tree[node] {
+            Node::Leaf { tabs, active, .. } => {
+                let index = active.0.min(tabs.len() - 1);
+                &mut tabs[index]
+            }
+            _ => unreachable!(),
+        }
+    }
+}